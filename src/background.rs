@@ -1,95 +1,432 @@
-use crate::color::Color;
-use image::DynamicImage;
+use crate::color::{Color, NormalizedColor, denormalize_color, normalize_color};
+use crate::error::{Error, Result};
+use crate::pixel::normalized_pixel;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel, Primitive, Rgb};
 use std::collections::HashMap;
 
+/// RGB image with channels from 0.0 to 1.0, for per-pixel backgrounds at full
+/// precision
+pub type NormalizedRgbImage = ImageBuffer<Rgb<f64>, Vec<f64>>;
+
+/// Background color behind each pixel of an image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background<'a> {
+    /// The same color behind every pixel
+    Solid(Color),
+    /// The same color behind every pixel, with channels from 0.0 to 1.0
+    ///
+    /// Keeps the precision of backgrounds of 16-bit images, which 8-bit
+    /// colors would round to a level the image does not contain.
+    SolidNormalized(NormalizedColor),
+    /// One color per pixel, e.g. a fitted gradient or a clean plate, with
+    /// channels from 0.0 to 1.0
+    PerPixel(&'a NormalizedRgbImage),
+}
+
+impl Background<'_> {
+    /// The background color behind the pixel at (x, y), rounded to 8 bits
+    pub fn color_at(&self, x: u32, y: u32) -> Color {
+        match self {
+            Background::Solid(color) => *color,
+            Background::SolidNormalized(color) => denormalize_color(*color),
+            Background::PerPixel(image) => denormalize_color(image.get_pixel(x, y).0),
+        }
+    }
+
+    /// The background color behind the pixel at (x, y), with channels from
+    /// 0.0 to 1.0
+    pub fn normalized_color_at(&self, x: u32, y: u32) -> NormalizedColor {
+        match self {
+            Background::Solid(color) => normalize_color(*color),
+            Background::SolidNormalized(color) => *color,
+            Background::PerPixel(image) => image.get_pixel(x, y).0,
+        }
+    }
+
+    /// Check that a per-pixel background covers an image of the given size
+    pub fn check_dimensions(&self, dimensions: (u32, u32)) -> Result<()> {
+        match self {
+            Background::PerPixel(image) if image.dimensions() != dimensions => {
+                Err(Error::DimensionMismatch {
+                    expected: dimensions,
+                    actual: image.dimensions(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<Color> for Background<'_> {
+    fn from(color: Color) -> Self {
+        Background::Solid(color)
+    }
+}
+
+/// Convert an image to a per-pixel background at its full precision
+///
+/// Channels are divided by the largest value of their type, so 8-bit, 16-bit
+/// and floating-point images all map to 0.0-1.0. Alpha is ignored.
+pub fn normalize_image<I>(image: &I) -> NormalizedRgbImage
+where
+    I: GenericImageView,
+    <I::Pixel as Pixel>::Subpixel: Into<f64>,
+{
+    let max: f64 = <<I::Pixel as Pixel>::Subpixel as Primitive>::DEFAULT_MAX_VALUE.into();
+    NormalizedRgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let pixel = image.get_pixel(x, y).to_rgb();
+        Rgb([0, 1, 2].map(|i| (pixel[i].into() / max).clamp(0.0, 1.0)))
+    })
+}
+
 /// Configuration for background detection
 pub struct BackgroundDetectionConfig {
-    /// Sample every N pixels on edges
+    /// Sample every N pixels on edges (or along both axes in a rectangle)
+    ///
+    /// The interval is increased for regions that would give more than
+    /// [`MAX_BACKGROUND_SAMPLES`] samples.
     pub edge_sample_interval: u32,
+    /// Where samples are taken
+    pub region: SampleRegion,
+    /// Maximum channel difference, in 8-bit levels, for two edge samples to be
+    /// considered the same color
+    ///
+    /// This keeps JPEG artifacts and scanner noise from splitting the
+    /// background into many slightly different colors.
+    pub tolerance: u8,
 }
 
 impl Default for BackgroundDetectionConfig {
     fn default() -> Self {
         Self {
             edge_sample_interval: 10,
+            region: SampleRegion::default(),
+            tolerance: DEFAULT_BACKGROUND_TOLERANCE,
+        }
+    }
+}
+
+/// Edges of an image to sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edges {
+    pub top: bool,
+    pub right: bool,
+    pub bottom: bool,
+    pub left: bool,
+}
+
+impl Edges {
+    /// All four edges
+    pub const ALL: Edges = Edges {
+        top: true,
+        right: true,
+        bottom: true,
+        left: true,
+    };
+
+    /// No edges
+    pub const NONE: Edges = Edges {
+        top: false,
+        right: false,
+        bottom: false,
+        left: false,
+    };
+}
+
+/// Where background samples are taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleRegion {
+    /// Along the border of the image
+    Border {
+        /// Edges to sample along
+        edges: Edges,
+        /// Also sample the four corners
+        corners: bool,
+        /// Margin to skip from every side of the image, e.g. to avoid a frame
+        inset: u32,
+    },
+    /// Inside a rectangle, on a grid with the sample interval as spacing
+    ///
+    /// The rectangle is clipped to the image.
+    Rect {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl Default for SampleRegion {
+    /// All four edges and corners, with no inset
+    fn default() -> Self {
+        SampleRegion::Border {
+            edges: Edges::ALL,
+            corners: true,
+            inset: 0,
         }
     }
 }
 
+/// Largest number of samples taken for background detection
+///
+/// Sampling regions that would give more samples are sampled more sparsely,
+/// since clustering compares every distinct sample color with every other.
+pub const MAX_BACKGROUND_SAMPLES: usize = 4096;
+
+/// Default tolerance for clustering edge samples, in 8-bit levels
+pub const DEFAULT_BACKGROUND_TOLERANCE: u8 = 2;
+
+/// Default minimum share of edge samples the background color must cover to be
+/// considered confident
+pub const DEFAULT_MIN_BACKGROUND_CONFIDENCE: f64 = 0.6;
+
+/// Result of background color detection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackgroundDetection {
+    /// The detected background color, rounded to 8 bits
+    pub color: Color,
+    /// The detected background color at the precision of the image, with
+    /// channels from 0.0 to 1.0
+    pub normalized_color: NormalizedColor,
+    /// Share of edge samples matching the background color (0.0-1.0)
+    pub confidence: f64,
+    /// Number of edge samples taken
+    pub sample_count: usize,
+    /// The next most common edge color and its share of edge samples, if any
+    /// sample does not match the background color
+    pub runner_up: Option<(Color, f64)>,
+}
+
+impl BackgroundDetection {
+    /// Whether the background color covers at least `min_confidence` of the edge samples
+    pub fn is_confident(&self, min_confidence: f64) -> bool {
+        self.confidence >= min_confidence
+    }
+}
+
 /// Detect the background color by sampling image edges and corners
 ///
 /// # Arguments
 /// * `img` - The image to analyze
 ///
 /// # Returns
-/// The most common RGB color found at image edges and corners
+/// The center color of the largest cluster of colors found at image edges and corners
 pub fn detect_background_color(img: &DynamicImage) -> Color {
     detect_background_color_with_config(img, &BackgroundDetectionConfig::default())
 }
 
 /// Detect background color with custom configuration
 ///
+/// Edge samples are clustered: the sample color with the most samples within
+/// `config.tolerance` of it is the cluster center and is returned as is.
+/// Returning an actual sample color rather than an average keeps exact
+/// backgrounds such as pure white exact when a few noisy samples join the
+/// cluster.
+///
 /// # Arguments
 /// * `img` - The image to analyze
 /// * `config` - Configuration for background detection
 ///
 /// # Returns
-/// The most common RGB color found at image edges and corners
+/// The center color of the largest cluster of colors found at image edges and corners
 pub fn detect_background_color_with_config(
     img: &DynamicImage,
     config: &BackgroundDetectionConfig,
 ) -> Color {
-    let rgba = img.to_rgba8();
-    let (width, height) = rgba.dimensions();
+    detect_background(img, config)
+        .map(|detection| detection.color)
+        .unwrap_or([0, 0, 0])
+}
 
-    let mut color_counts: HashMap<Color, u32> = HashMap::new();
-    let mut sample_points = Vec::new();
+/// Detect the background color and how dominant it is at the image edges
+///
+/// Uses the same clustering as [`detect_background_color_with_config`]. The
+/// runner-up is the largest cluster among the samples that are not part of
+/// the background cluster.
+///
+/// # Arguments
+/// * `img` - The image to analyze
+/// * `config` - Configuration for background detection
+///
+/// # Returns
+/// The detection result, or `None` if the image is empty
+pub fn detect_background(
+    img: &DynamicImage,
+    config: &BackgroundDetectionConfig,
+) -> Option<BackgroundDetection> {
+    let samples = sample_edge_colors(img, config);
+    let background = largest_cluster(&samples, config.tolerance)?;
 
-    // Add corners
-    sample_points.extend(&[
-        (0, 0),
-        (width - 1, 0),
-        (0, height - 1),
-        (width - 1, height - 1),
-    ]);
+    let remaining: Vec<Sample> = samples
+        .iter()
+        .copied()
+        .filter(|&color| !within_tolerance(color, background.center, config.tolerance))
+        .collect();
+    let share = |size: usize| size as f64 / samples.len() as f64;
 
-    // Add edge samples
-    for x in (0..width).step_by(config.edge_sample_interval as usize) {
-        sample_points.push((x, 0));
-        sample_points.push((x, height - 1));
-    }
+    let normalized_color = normalize_sample(background.center);
+    Some(BackgroundDetection {
+        color: denormalize_color(normalized_color),
+        normalized_color,
+        confidence: share(background.size),
+        sample_count: samples.len(),
+        runner_up: largest_cluster(&remaining, config.tolerance).map(|cluster| {
+            (
+                denormalize_color(normalize_sample(cluster.center)),
+                share(cluster.size),
+            )
+        }),
+    })
+}
 
-    for y in (0..height).step_by(config.edge_sample_interval as usize) {
-        sample_points.push((0, y));
-        sample_points.push((width - 1, y));
-    }
+/// Sampled color with 16 bits per channel
+///
+/// Samples keep the precision of 16-bit images. 8-bit channels are scaled by
+/// 257, so they map to exact 16-bit levels.
+type Sample = [u16; 3];
+
+/// Convert a sample to a normalized color
+fn normalize_sample(sample: Sample) -> NormalizedColor {
+    sample.map(|channel| channel as f64 / 65535.0)
+}
+
+/// Sample colors in the configured region of an image
+///
+/// Only the sampled pixels are read, at the precision of the image, so large
+/// images are not converted as a whole.
+fn sample_edge_colors(img: &DynamicImage, config: &BackgroundDetectionConfig) -> Vec<Sample> {
+    let sample_points = sample_points(img.width(), img.height(), config);
 
-    // Count color occurrences
     // For translucent pixels, composite over black to get the effective color
-    for &(x, y) in &sample_points {
-        let pixel = rgba.get_pixel(x, y);
-        let alpha = pixel[3] as f64 / 255.0;
-
-        // Composite over black background for translucent pixels
-        let color = if alpha < 1.0 {
-            [
-                (pixel[0] as f64 * alpha).round() as u8,
-                (pixel[1] as f64 * alpha).round() as u8,
-                (pixel[2] as f64 * alpha).round() as u8,
-            ]
-        } else {
-            [pixel[0], pixel[1], pixel[2]]
-        };
+    sample_points
+        .iter()
+        .map(|&(x, y)| {
+            let pixel = normalized_pixel(img, x, y);
+            [0, 1, 2].map(|i| (pixel[i] * pixel[3] * 65535.0).round() as u16)
+        })
+        .collect()
+}
+
+/// Compute the sample coordinates for an image size
+///
+/// Returns no points if the region doesn't overlap the image, and at most
+/// [`MAX_BACKGROUND_SAMPLES`] points.
+fn sample_points(width: u32, height: u32, config: &BackgroundDetectionConfig) -> Vec<(u32, u32)> {
+    let mut interval = config.edge_sample_interval.max(1) as usize;
+    let mut points = Vec::new();
+
+    match config.region {
+        SampleRegion::Border {
+            edges,
+            corners,
+            inset,
+        } => {
+            // Bounds of the border after skipping the inset
+            let (left, top) = (inset, inset);
+            let far_edge = |size: u32| inset.checked_add(1).and_then(|n| size.checked_sub(n));
+            let (Some(right), Some(bottom)) = (far_edge(width), far_edge(height)) else {
+                return points;
+            };
+            if right < left || bottom < top {
+                return points;
+            }
+
+            if corners {
+                points.extend([(left, top), (right, top), (left, bottom), (right, bottom)]);
+            }
 
+            for x in (left..=right).step_by(interval) {
+                if edges.top {
+                    points.push((x, top));
+                }
+                if edges.bottom {
+                    points.push((x, bottom));
+                }
+            }
+
+            for y in (top..=bottom).step_by(interval) {
+                if edges.left {
+                    points.push((left, y));
+                }
+                if edges.right {
+                    points.push((right, y));
+                }
+            }
+
+            if points.len() > MAX_BACKGROUND_SAMPLES {
+                let step = points.len().div_ceil(MAX_BACKGROUND_SAMPLES);
+                points = points.into_iter().step_by(step).collect();
+            }
+        }
+        SampleRegion::Rect {
+            x,
+            y,
+            width: rect_width,
+            height: rect_height,
+        } => {
+            let right = x.saturating_add(rect_width).min(width);
+            let bottom = y.saturating_add(rect_height).min(height);
+
+            // Widen the grid until it fits, rather than listing every pixel
+            let count = |length: u32, start: u32, interval: usize| {
+                length.saturating_sub(start).div_ceil(interval as u32) as usize
+            };
+            while count(right, x, interval) * count(bottom, y, interval) > MAX_BACKGROUND_SAMPLES {
+                interval += 1;
+            }
+
+            for py in (y..bottom).step_by(interval) {
+                for px in (x..right).step_by(interval) {
+                    points.push((px, py));
+                }
+            }
+        }
+    }
+
+    points
+}
+
+/// A group of sample colors within tolerance of a center color
+struct ColorCluster {
+    /// The sample color the cluster is centered on
+    center: Sample,
+    /// The number of samples in the cluster
+    size: usize,
+}
+
+/// Find the sample color with the most samples within `tolerance` 8-bit
+/// levels of it
+///
+/// Ties are broken by the number of exact matches, then by the lowest color,
+/// so the result does not depend on hash map iteration order.
+fn largest_cluster(samples: &[Sample], tolerance: u8) -> Option<ColorCluster> {
+    let mut color_counts: HashMap<Sample, usize> = HashMap::new();
+    for &color in samples {
         *color_counts.entry(color).or_insert(0) += 1;
     }
 
-    // Find most common color
-    color_counts
-        .into_iter()
-        .max_by_key(|(_, count)| *count)
-        .map(|(color, _)| color)
-        .unwrap_or([0, 0, 0])
+    let mut unique_colors: Vec<(Sample, usize)> = color_counts.into_iter().collect();
+    unique_colors.sort_unstable();
+
+    let (center, size, _) = unique_colors
+        .iter()
+        .map(|&(center, exact_count)| {
+            let size = unique_colors
+                .iter()
+                .filter(|(color, _)| within_tolerance(*color, center, tolerance))
+                .map(|(_, count)| count)
+                .sum::<usize>();
+            (center, size, exact_count)
+        })
+        .min_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)))?;
+
+    Some(ColorCluster { center, size })
+}
+
+/// Check whether every channel of two samples differs by at most `tolerance`
+/// 8-bit levels
+fn within_tolerance(a: Sample, b: Sample, tolerance: u8) -> bool {
+    (0..3).all(|i| a[i].abs_diff(b[i]) <= tolerance as u16 * 257)
 }
 
 #[cfg(test)]
@@ -127,9 +464,276 @@ mod tests {
 
         let config = BackgroundDetectionConfig {
             edge_sample_interval: 5, // Sample more frequently
+            ..Default::default()
         };
 
         let detected = detect_background_color_with_config(&DynamicImage::ImageRgba8(img), &config);
         assert_eq!(detected, [128, 128, 128]);
     }
+
+    #[test]
+    fn test_detect_noisy_background() {
+        // Off-white background with +/-1 level of noise, and a gray top row
+        // that is the most common exact color
+        let img = ImageBuffer::from_fn(100, 100, |x, y| {
+            if y == 0 {
+                Rgba([100, 100, 100, 255])
+            } else {
+                let noise = ((x * 7 + y * 13) % 3) as u8;
+                Rgba([249 + noise, 249 + noise, 249 + noise, 255])
+            }
+        });
+
+        // The noisy levels form the largest cluster, and the most common of
+        // them is returned
+        let img = DynamicImage::ImageRgba8(img);
+        assert_eq!(detect_background_color(&img), [249, 249, 249]);
+
+        let exact = BackgroundDetectionConfig {
+            tolerance: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            detect_background_color_with_config(&img, &exact),
+            [100, 100, 100]
+        );
+    }
+
+    #[test]
+    fn test_detect_exact_white_with_noise() {
+        // A white background with a few slightly darker border pixels, which
+        // would pull an average below 255
+        let img = ImageBuffer::from_fn(100, 100, |x, y| {
+            if (x + y) % 40 == 0 {
+                Rgba([253, 253, 253, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+
+        let detected = detect_background_color(&DynamicImage::ImageRgba8(img));
+        assert_eq!(detected, [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_zero_tolerance_uses_exact_colors() {
+        // 8-bit levels 10, 10, 11 and 12, scaled to 16 bits
+        let samples = [10u16, 10, 11, 12].map(|level| [level * 257; 3]);
+
+        let cluster = largest_cluster(&samples, 0).unwrap();
+        assert_eq!(cluster.center, [10 * 257; 3]);
+        assert_eq!(cluster.size, 2);
+
+        // Level 11 is within 1 level of every sample
+        let cluster = largest_cluster(&samples, 1).unwrap();
+        assert_eq!(cluster.center, [11 * 257; 3]);
+        assert_eq!(cluster.size, 4);
+
+        assert!(largest_cluster(&[], 1).is_none());
+    }
+
+    #[test]
+    fn test_detect_16_bit_background() {
+        // 0x1234 is not a multiple of 257, so it has no exact 8-bit level
+        let img: ImageBuffer<Rgba<u16>, Vec<u16>> = ImageBuffer::from_fn(50, 50, |x, y| {
+            if (20..30).contains(&x) && (20..30).contains(&y) {
+                Rgba([65535, 0, 0, 65535])
+            } else {
+                Rgba([0x1234, 0x1234, 0x1234, 65535])
+            }
+        });
+
+        let detection = detect_background(
+            &DynamicImage::ImageRgba16(img),
+            &BackgroundDetectionConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(detection.normalized_color, [0x1234 as f64 / 65535.0; 3]);
+        assert_eq!(detection.color, [0x12, 0x12, 0x12]);
+    }
+
+    #[test]
+    fn test_detection_confidence() {
+        // Left half white, right half black: no dominant border color
+        let img = ImageBuffer::from_fn(100, 100, |x, _y| {
+            if x < 50 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        });
+
+        let detection =
+            detect_background(&DynamicImage::ImageRgba8(img), &Default::default()).unwrap();
+        assert!(!detection.is_confident(DEFAULT_MIN_BACKGROUND_CONFIDENCE));
+        assert!(detection.confidence < 0.6);
+        let (runner_up, share) = detection.runner_up.unwrap();
+        assert_ne!(runner_up, detection.color);
+        assert!((detection.confidence + share - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_detection_uniform_border_is_confident() {
+        let img = ImageBuffer::from_fn(100, 100, |x, y| {
+            if x > 25 && x < 75 && y > 25 && y < 75 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+
+        let detection =
+            detect_background(&DynamicImage::ImageRgba8(img), &Default::default()).unwrap();
+        assert_eq!(detection.color, [255, 255, 255]);
+        assert_eq!(detection.confidence, 1.0);
+        assert!(detection.sample_count > 0);
+        assert!(detection.runner_up.is_none());
+    }
+
+    #[test]
+    fn test_detection_empty_image() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::new(0, 0));
+        assert!(detect_background(&img, &Default::default()).is_none());
+        assert_eq!(detect_background_color(&img), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_default_sample_points_cover_edges_and_corners() {
+        let points = sample_points(21, 11, &BackgroundDetectionConfig::default());
+        assert!(points.contains(&(20, 10)));
+        assert!(points.contains(&(10, 0)));
+        assert!(points.contains(&(0, 10)));
+        assert!(
+            points
+                .iter()
+                .all(|&(x, y)| x == 0 || x == 20 || y == 0 || y == 10)
+        );
+    }
+
+    #[test]
+    fn test_sample_chosen_edges_with_inset() {
+        let config = BackgroundDetectionConfig {
+            edge_sample_interval: 1,
+            region: SampleRegion::Border {
+                edges: Edges {
+                    bottom: true,
+                    ..Edges::NONE
+                },
+                corners: false,
+                inset: 2,
+            },
+            ..Default::default()
+        };
+
+        let points = sample_points(10, 10, &config);
+        assert_eq!(points.len(), 6);
+        assert!(points.iter().all(|&(x, y)| y == 7 && (2..=7).contains(&x)));
+
+        // An inset larger than the image leaves nothing to sample
+        let config = BackgroundDetectionConfig {
+            region: SampleRegion::Border {
+                edges: Edges::ALL,
+                corners: true,
+                inset: 5,
+            },
+            ..Default::default()
+        };
+        assert!(sample_points(10, 10, &config).is_empty());
+
+        // An inset at or past the image size, up to the largest u32
+        for inset in [9, 10, u32::MAX - 1, u32::MAX] {
+            let config = BackgroundDetectionConfig {
+                region: SampleRegion::Border {
+                    edges: Edges::ALL,
+                    corners: true,
+                    inset,
+                },
+                ..Default::default()
+            };
+            assert!(sample_points(10, 10, &config).is_empty(), "inset {}", inset);
+        }
+    }
+
+    #[test]
+    fn test_sample_rect() {
+        let config = BackgroundDetectionConfig {
+            edge_sample_interval: 2,
+            region: SampleRegion::Rect {
+                x: 6,
+                y: 0,
+                width: 10,
+                height: 3,
+            },
+            ..Default::default()
+        };
+
+        // Clipped to the 10x10 image: x in 6..10, y in 0..3, every 2 pixels
+        assert_eq!(
+            sample_points(10, 10, &config),
+            vec![(6, 0), (8, 0), (6, 2), (8, 2)]
+        );
+    }
+
+    #[test]
+    fn test_sample_count_is_bounded() {
+        let rect = BackgroundDetectionConfig {
+            edge_sample_interval: 1,
+            region: SampleRegion::Rect {
+                x: 0,
+                y: 0,
+                width: 1000,
+                height: 1000,
+            },
+            ..Default::default()
+        };
+        let border = BackgroundDetectionConfig {
+            edge_sample_interval: 1,
+            ..Default::default()
+        };
+        for config in [&rect, &border] {
+            let points = sample_points(5000, 5000, config);
+            assert!(points.len() <= MAX_BACKGROUND_SAMPLES, "{}", points.len());
+            assert!(
+                points.len() > MAX_BACKGROUND_SAMPLES / 4,
+                "{}",
+                points.len()
+            );
+        }
+
+        // Photographic noise over the whole rectangle: every sample is a
+        // different color, which clustering must still handle quickly
+        let img = ImageBuffer::from_fn(1000, 1000, |x, y| {
+            let value = x * 7919 + y * 104729;
+            Rgba([value as u8, (value >> 8) as u8, (value >> 16) as u8, 255])
+        });
+        let detection = detect_background(&DynamicImage::ImageRgba8(img), &rect).unwrap();
+        assert!(detection.sample_count <= MAX_BACKGROUND_SAMPLES);
+    }
+
+    #[test]
+    fn test_detect_skips_title_bar() {
+        // Gray title bar across the top of a white screenshot
+        let img = ImageBuffer::from_fn(100, 100, |_x, y| {
+            if y < 40 {
+                Rgba([60, 60, 60, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let img = DynamicImage::ImageRgba8(img);
+
+        let config = BackgroundDetectionConfig {
+            region: SampleRegion::Border {
+                edges: Edges {
+                    top: false,
+                    ..Edges::ALL
+                },
+                corners: false,
+                inset: 0,
+            },
+            ..Default::default()
+        };
+        let detection = detect_background(&img, &config).unwrap();
+        assert_eq!(detection.color, [255, 255, 255]);
+    }
 }
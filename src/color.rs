@@ -1,4 +1,5 @@
-use anyhow::{Context, Result};
+use crate::error::{Error, Result};
+use std::borrow::Cow;
 
 /// Multiplier to expand hex color shorthand (e.g., F -> FF)
 const HEX_SHORTHAND_MULTIPLIER: u8 = 17;
@@ -20,15 +21,20 @@ pub enum ForegroundColorSpec {
 
 /// Parse a hex color string into RGB
 /// Supports: "#ff0000", "ff0000", "#f00", "f00"
-pub fn parse_hex_color(hex: &str) -> Result<Color> {
-    let hex = hex.trim_start_matches('#');
+pub fn parse_hex_color(input: &str) -> Result<Color> {
+    let hex = input.trim_start_matches('#');
+    let component = |range: std::ops::Range<usize>, name: &str| {
+        hex.get(range)
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| Error::invalid_color(input, format!("Invalid {} component", name)))
+    };
 
     let (r, g, b) = match hex.len() {
         3 => {
             // Expand shorthand: "f00" -> "ff0000"
-            let r = u8::from_str_radix(&hex[0..1], 16).context("Invalid red component")?;
-            let g = u8::from_str_radix(&hex[1..2], 16).context("Invalid green component")?;
-            let b = u8::from_str_radix(&hex[2..3], 16).context("Invalid blue component")?;
+            let r = component(0..1, "red")?;
+            let g = component(1..2, "green")?;
+            let b = component(2..3, "blue")?;
             (
                 r * HEX_SHORTHAND_MULTIPLIER,
                 g * HEX_SHORTHAND_MULTIPLIER,
@@ -37,24 +43,256 @@ pub fn parse_hex_color(hex: &str) -> Result<Color> {
         }
         6 => {
             // Full hex color
-            let r = u8::from_str_radix(&hex[0..2], 16).context("Invalid red component")?;
-            let g = u8::from_str_radix(&hex[2..4], 16).context("Invalid green component")?;
-            let b = u8::from_str_radix(&hex[4..6], 16).context("Invalid blue component")?;
+            let r = component(0..2, "red")?;
+            let g = component(2..4, "green")?;
+            let b = component(4..6, "blue")?;
             (r, g, b)
         }
-        _ => anyhow::bail!("Hex color must be 3 or 6 characters long (got: {})", hex),
+        _ => {
+            return Err(Error::invalid_color(
+                input,
+                format!("Hex color must be 3 or 6 characters long (got: {})", hex),
+            ));
+        }
     };
 
     Ok([r, g, b])
 }
 
+/// Parse a color in any supported syntax
+///
+/// Supports the CSS Color Level 4 syntaxes for opaque sRGB colors:
+/// - Hex with or without `#`: `f00`, `#ff0000`, and with alpha `#f00f`, `#ff0000ff`
+/// - `rgb()` and `rgba()` with numbers (0-255) or percentages, separated by
+///   spaces or commas: `rgb(12 34 56)`, `rgb(100%, 50%, 0%)`, `rgb(12 34 56 / 1)`
+/// - `hsl()` and `hsla()` with a hue in degrees (or `deg`, `rad`, `grad`, `turn`)
+///   and saturation and lightness percentages: `hsl(120 100% 50%)`
+/// - CSS named colors: `red`, `rebeccapurple`, `lightgoldenrodyellow`
+///
+/// Colors are unmixed as opaque colors, so an alpha component below 1 is an
+/// error rather than being dropped.
+pub fn parse_color(input: &str) -> Result<Color> {
+    parse_color_normalized(input).map(denormalize_color)
+}
+
+/// Parse a color in any supported syntax without rounding it to 8 bits
+///
+/// Accepts the same syntaxes as [`parse_color`]. Fractional `rgb()` values,
+/// percentages and `hsl()` colors keep their full precision, e.g. for the
+/// background of a 16-bit image.
+pub fn parse_color_normalized(input: &str) -> Result<NormalizedColor> {
+    let trimmed = input.trim();
+    let lower = trimmed.to_ascii_lowercase();
+
+    let (color, alpha) = if let Some((function, arguments)) = lower
+        .strip_suffix(')')
+        .and_then(|call| call.split_once('('))
+    {
+        parse_color_function(input, function.trim(), arguments)?
+    } else if let Ok(index) = NAMED_COLORS.binary_search_by(|(name, _)| name.cmp(&lower.as_str())) {
+        (normalize_color(NAMED_COLORS[index].1), 1.0)
+    } else if lower == "transparent" {
+        ([0.0; 3], 0.0)
+    } else {
+        let (color, alpha) = parse_hex_color_with_alpha(input, trimmed)?;
+        (normalize_color(color), alpha)
+    };
+
+    if alpha < 1.0 {
+        return Err(Error::invalid_color(
+            input,
+            format!(
+                "Alpha must be 1 (opaque), got {}: foreground and background colors are unmixed as opaque colors",
+                alpha
+            ),
+        ));
+    }
+
+    Ok(color)
+}
+
+/// Parse a hex color with an optional alpha digit or pair (`#rgba`, `#rrggbbaa`)
+fn parse_hex_color_with_alpha(input: &str, hex: &str) -> Result<(Color, f64)> {
+    let digits = hex.trim_start_matches('#');
+    // Checked before splitting, which panics inside a multi-byte character
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        let reason = if hex.starts_with('#') {
+            "Invalid hex digits"
+        } else {
+            "Expected a hex color (e.g., ff0000), rgb(), hsl() or a CSS color name"
+        };
+        return Err(Error::invalid_color(input, reason));
+    }
+
+    let (color_digits, alpha_digits) = match digits.len() {
+        4 => digits.split_at(3),
+        8 => digits.split_at(6),
+        3 | 6 => return Ok((parse_hex_color(hex)?, 1.0)),
+        _ => {
+            return Err(Error::invalid_color(
+                input,
+                format!(
+                    "Hex color must be 3, 4, 6 or 8 characters long (got: {})",
+                    hex
+                ),
+            ));
+        }
+    };
+
+    let color = parse_hex_color(color_digits)
+        .map_err(|_| Error::invalid_color(input, "Invalid hex digits"))?;
+    let alpha = u8::from_str_radix(alpha_digits, 16)
+        .map_err(|_| Error::invalid_color(input, "Invalid alpha component"))?;
+    let alpha = if alpha_digits.len() == 1 {
+        alpha * HEX_SHORTHAND_MULTIPLIER
+    } else {
+        alpha
+    };
+
+    Ok((color, alpha as f64 / 255.0))
+}
+
+/// Parse the arguments of `rgb()`, `rgba()`, `hsl()` or `hsla()`
+///
+/// Returns the color and its alpha, both with channels from 0.0 to 1.0.
+fn parse_color_function(
+    input: &str,
+    function: &str,
+    arguments: &str,
+) -> Result<(NormalizedColor, f64)> {
+    // Modern syntax separates alpha with a slash, legacy syntax with a fourth comma
+    let (channels, slash_alpha) = match arguments.split_once('/') {
+        Some((channels, alpha)) => (channels, Some(alpha.trim())),
+        None => (arguments, None),
+    };
+    let mut components: Vec<&str> = if channels.contains(',') {
+        channels.split(',').map(str::trim).collect()
+    } else {
+        channels.split_whitespace().collect()
+    };
+    let comma_alpha = if components.len() == 4 && slash_alpha.is_none() {
+        components.pop()
+    } else {
+        None
+    };
+
+    let [first, second, third] = components[..] else {
+        return Err(Error::invalid_color(
+            input,
+            format!(
+                "{}() takes 3 components and an optional alpha, got {}",
+                function,
+                components.len()
+            ),
+        ));
+    };
+
+    let alpha = match slash_alpha.or(comma_alpha) {
+        Some(alpha) => parse_css_number(input, alpha, "alpha", 1.0)?,
+        None => 1.0,
+    };
+
+    let color = match function {
+        "rgb" | "rgba" => {
+            let red = parse_css_number(input, first, "red", 255.0)?;
+            let green = parse_css_number(input, second, "green", 255.0)?;
+            let blue = parse_css_number(input, third, "blue", 255.0)?;
+            [red, green, blue].map(|value| value / 255.0)
+        }
+        "hsl" | "hsla" => {
+            let hue = parse_hue(input, first)?;
+            let saturation = parse_css_number(input, second, "saturation", 100.0)?;
+            let lightness = parse_css_number(input, third, "lightness", 100.0)?;
+            hsl_to_rgb(hue, saturation / 100.0, lightness / 100.0)
+        }
+        _ => {
+            return Err(Error::invalid_color(
+                input,
+                format!(
+                    "Unsupported color function {}(), expected rgb() or hsl()",
+                    function
+                ),
+            ));
+        }
+    };
+
+    Ok((color, alpha))
+}
+
+/// Parse a number or percentage and check that it is in range
+///
+/// A percentage is scaled so that 100% is `max`, and the result must be
+/// between 0 and `max`.
+fn parse_css_number(input: &str, value: &str, name: &str, max: f64) -> Result<f64> {
+    let (number, scale, divisor) = match value.strip_suffix('%') {
+        Some(number) => (number, max, 100.0),
+        None => (value, 1.0, 1.0),
+    };
+    let number = number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .ok_or_else(|| {
+            Error::invalid_color(input, format!("Invalid {} component '{}'", name, value))
+        })?
+        * scale
+        / divisor;
+
+    if !(0.0..=max).contains(&number) {
+        let range = if value.ends_with('%') {
+            "0%-100%".to_string()
+        } else {
+            format!("0-{}", max)
+        };
+        return Err(Error::invalid_color(
+            input,
+            format!("{} component {} is out of range ({})", name, value, range),
+        ));
+    }
+
+    Ok(number)
+}
+
+/// Parse a hue angle, in degrees unless it has a unit, and wrap it to 0-360
+fn parse_hue(input: &str, value: &str) -> Result<f64> {
+    let units = [
+        ("deg", 1.0),
+        ("grad", 0.9),
+        ("rad", 180.0 / std::f64::consts::PI),
+        ("turn", 360.0),
+    ];
+    let (number, degrees_per_unit) = units
+        .iter()
+        .find_map(|&(unit, factor)| value.strip_suffix(unit).map(|number| (number, factor)))
+        .unwrap_or((value, 1.0));
+
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .map(|number| (number * degrees_per_unit).rem_euclid(360.0))
+        .ok_or_else(|| Error::invalid_color(input, format!("Invalid hue '{}'", value)))
+}
+
+/// Convert HSL (hue in degrees, saturation and lightness 0.0-1.0) to RGB
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> NormalizedColor {
+    let channel = |n: f64| {
+        let k = (n + hue / 30.0) % 12.0;
+        let a = saturation * lightness.min(1.0 - lightness);
+        lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    [channel(0.0), channel(8.0), channel(4.0)]
+}
+
 /// Parse a foreground color specification
-/// Can be either a hex color or "auto" for unknown
+/// Can be either a color (see [`parse_color`]) or "auto" for unknown
 pub fn parse_foreground_spec(spec: &str) -> Result<ForegroundColorSpec> {
     if spec == "auto" {
         Ok(ForegroundColorSpec::Unknown)
     } else {
-        parse_hex_color(spec).map(ForegroundColorSpec::Known)
+        parse_color(spec).map(ForegroundColorSpec::Known)
     }
 }
 
@@ -76,6 +314,258 @@ pub fn denormalize_color(color: NormalizedColor) -> Color {
     ]
 }
 
+/// How the difference between two colors is measured
+///
+/// Color closeness thresholds are in the units of the chosen metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorDistance {
+    /// Euclidean distance in sRGB with channels from 0.0 to 1.0, from 0.0 to
+    /// about 1.732 (black to white)
+    ///
+    /// Not perceptually uniform: the same distance is much more visible
+    /// between dark blues than between yellows.
+    #[default]
+    Rgb,
+    /// Euclidean distance in OKLab (ΔE OK), perceptually uniform: 1.0 is black
+    /// to white and about 0.02 is a just noticeable difference
+    Oklab,
+}
+
+impl ColorDistance {
+    /// Distance between two colors
+    pub fn between(self, color1: NormalizedColor, color2: NormalizedColor) -> f64 {
+        let (color1, color2) = match self {
+            ColorDistance::Rgb => (color1, color2),
+            ColorDistance::Oklab => (srgb_to_oklab(color1), srgb_to_oklab(color2)),
+        };
+        (0..3)
+            .map(|i| (color1[i] - color2[i]).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+}
+
+/// Convert an sRGB color to OKLab (L from 0.0 to 1.0, a and b roughly -0.4 to 0.4)
+pub fn srgb_to_oklab(color: NormalizedColor) -> [f64; 3] {
+    let [r, g, b] = color.map(srgb_to_linear);
+
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+/// Color space in which a foreground was blended over its background
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendSpace {
+    /// Blended on sRGB-encoded values, like browsers and most 2D editors
+    #[default]
+    Srgb,
+    /// Blended in linear light, like 3D renderers and linear compositors
+    Linear,
+}
+
+impl BlendSpace {
+    /// Convert an sRGB color to this space
+    pub fn decode(self, color: NormalizedColor) -> NormalizedColor {
+        match self {
+            BlendSpace::Srgb => color,
+            BlendSpace::Linear => color.map(srgb_to_linear),
+        }
+    }
+
+    /// Convert a color in this space back to sRGB
+    pub fn encode(self, color: NormalizedColor) -> NormalizedColor {
+        match self {
+            BlendSpace::Srgb => color,
+            BlendSpace::Linear => color.map(linear_to_srgb),
+        }
+    }
+
+    /// Convert sRGB colors to this space, without copying them for sRGB
+    pub(crate) fn decode_all(self, colors: &[NormalizedColor]) -> Cow<'_, [NormalizedColor]> {
+        match self {
+            BlendSpace::Srgb => Cow::Borrowed(colors),
+            BlendSpace::Linear => colors.iter().map(|&color| self.decode(color)).collect(),
+        }
+    }
+}
+
+/// Decode an sRGB channel (0.0-1.0) to linear light
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode a linear light channel (0.0-1.0) to sRGB
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// CSS named colors, sorted by name
+const NAMED_COLORS: [(&str, Color); 148] = [
+    ("aliceblue", [240, 248, 255]),
+    ("antiquewhite", [250, 235, 215]),
+    ("aqua", [0, 255, 255]),
+    ("aquamarine", [127, 255, 212]),
+    ("azure", [240, 255, 255]),
+    ("beige", [245, 245, 220]),
+    ("bisque", [255, 228, 196]),
+    ("black", [0, 0, 0]),
+    ("blanchedalmond", [255, 235, 205]),
+    ("blue", [0, 0, 255]),
+    ("blueviolet", [138, 43, 226]),
+    ("brown", [165, 42, 42]),
+    ("burlywood", [222, 184, 135]),
+    ("cadetblue", [95, 158, 160]),
+    ("chartreuse", [127, 255, 0]),
+    ("chocolate", [210, 105, 30]),
+    ("coral", [255, 127, 80]),
+    ("cornflowerblue", [100, 149, 237]),
+    ("cornsilk", [255, 248, 220]),
+    ("crimson", [220, 20, 60]),
+    ("cyan", [0, 255, 255]),
+    ("darkblue", [0, 0, 139]),
+    ("darkcyan", [0, 139, 139]),
+    ("darkgoldenrod", [184, 134, 11]),
+    ("darkgray", [169, 169, 169]),
+    ("darkgreen", [0, 100, 0]),
+    ("darkgrey", [169, 169, 169]),
+    ("darkkhaki", [189, 183, 107]),
+    ("darkmagenta", [139, 0, 139]),
+    ("darkolivegreen", [85, 107, 47]),
+    ("darkorange", [255, 140, 0]),
+    ("darkorchid", [153, 50, 204]),
+    ("darkred", [139, 0, 0]),
+    ("darksalmon", [233, 150, 122]),
+    ("darkseagreen", [143, 188, 143]),
+    ("darkslateblue", [72, 61, 139]),
+    ("darkslategray", [47, 79, 79]),
+    ("darkslategrey", [47, 79, 79]),
+    ("darkturquoise", [0, 206, 209]),
+    ("darkviolet", [148, 0, 211]),
+    ("deeppink", [255, 20, 147]),
+    ("deepskyblue", [0, 191, 255]),
+    ("dimgray", [105, 105, 105]),
+    ("dimgrey", [105, 105, 105]),
+    ("dodgerblue", [30, 144, 255]),
+    ("firebrick", [178, 34, 34]),
+    ("floralwhite", [255, 250, 240]),
+    ("forestgreen", [34, 139, 34]),
+    ("fuchsia", [255, 0, 255]),
+    ("gainsboro", [220, 220, 220]),
+    ("ghostwhite", [248, 248, 255]),
+    ("gold", [255, 215, 0]),
+    ("goldenrod", [218, 165, 32]),
+    ("gray", [128, 128, 128]),
+    ("green", [0, 128, 0]),
+    ("greenyellow", [173, 255, 47]),
+    ("grey", [128, 128, 128]),
+    ("honeydew", [240, 255, 240]),
+    ("hotpink", [255, 105, 180]),
+    ("indianred", [205, 92, 92]),
+    ("indigo", [75, 0, 130]),
+    ("ivory", [255, 255, 240]),
+    ("khaki", [240, 230, 140]),
+    ("lavender", [230, 230, 250]),
+    ("lavenderblush", [255, 240, 245]),
+    ("lawngreen", [124, 252, 0]),
+    ("lemonchiffon", [255, 250, 205]),
+    ("lightblue", [173, 216, 230]),
+    ("lightcoral", [240, 128, 128]),
+    ("lightcyan", [224, 255, 255]),
+    ("lightgoldenrodyellow", [250, 250, 210]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgreen", [144, 238, 144]),
+    ("lightgrey", [211, 211, 211]),
+    ("lightpink", [255, 182, 193]),
+    ("lightsalmon", [255, 160, 122]),
+    ("lightseagreen", [32, 178, 170]),
+    ("lightskyblue", [135, 206, 250]),
+    ("lightslategray", [119, 136, 153]),
+    ("lightslategrey", [119, 136, 153]),
+    ("lightsteelblue", [176, 196, 222]),
+    ("lightyellow", [255, 255, 224]),
+    ("lime", [0, 255, 0]),
+    ("limegreen", [50, 205, 50]),
+    ("linen", [250, 240, 230]),
+    ("magenta", [255, 0, 255]),
+    ("maroon", [128, 0, 0]),
+    ("mediumaquamarine", [102, 205, 170]),
+    ("mediumblue", [0, 0, 205]),
+    ("mediumorchid", [186, 85, 211]),
+    ("mediumpurple", [147, 112, 219]),
+    ("mediumseagreen", [60, 179, 113]),
+    ("mediumslateblue", [123, 104, 238]),
+    ("mediumspringgreen", [0, 250, 154]),
+    ("mediumturquoise", [72, 209, 204]),
+    ("mediumvioletred", [199, 21, 133]),
+    ("midnightblue", [25, 25, 112]),
+    ("mintcream", [245, 255, 250]),
+    ("mistyrose", [255, 228, 225]),
+    ("moccasin", [255, 228, 181]),
+    ("navajowhite", [255, 222, 173]),
+    ("navy", [0, 0, 128]),
+    ("oldlace", [253, 245, 230]),
+    ("olive", [128, 128, 0]),
+    ("olivedrab", [107, 142, 35]),
+    ("orange", [255, 165, 0]),
+    ("orangered", [255, 69, 0]),
+    ("orchid", [218, 112, 214]),
+    ("palegoldenrod", [238, 232, 170]),
+    ("palegreen", [152, 251, 152]),
+    ("paleturquoise", [175, 238, 238]),
+    ("palevioletred", [219, 112, 147]),
+    ("papayawhip", [255, 239, 213]),
+    ("peachpuff", [255, 218, 185]),
+    ("peru", [205, 133, 63]),
+    ("pink", [255, 192, 203]),
+    ("plum", [221, 160, 221]),
+    ("powderblue", [176, 224, 230]),
+    ("purple", [128, 0, 128]),
+    ("rebeccapurple", [102, 51, 153]),
+    ("red", [255, 0, 0]),
+    ("rosybrown", [188, 143, 143]),
+    ("royalblue", [65, 105, 225]),
+    ("saddlebrown", [139, 69, 19]),
+    ("salmon", [250, 128, 114]),
+    ("sandybrown", [244, 164, 96]),
+    ("seagreen", [46, 139, 87]),
+    ("seashell", [255, 245, 238]),
+    ("sienna", [160, 82, 45]),
+    ("silver", [192, 192, 192]),
+    ("skyblue", [135, 206, 235]),
+    ("slateblue", [106, 90, 205]),
+    ("slategray", [112, 128, 144]),
+    ("slategrey", [112, 128, 144]),
+    ("snow", [255, 250, 250]),
+    ("springgreen", [0, 255, 127]),
+    ("steelblue", [70, 130, 180]),
+    ("tan", [210, 180, 140]),
+    ("teal", [0, 128, 128]),
+    ("thistle", [216, 191, 216]),
+    ("tomato", [255, 99, 71]),
+    ("turquoise", [64, 224, 208]),
+    ("violet", [238, 130, 238]),
+    ("wheat", [245, 222, 179]),
+    ("white", [255, 255, 255]),
+    ("whitesmoke", [245, 245, 245]),
+    ("yellow", [255, 255, 0]),
+    ("yellowgreen", [154, 205, 50]),
+];
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,6 +600,150 @@ mod tests {
         assert!(parse_hex_color("ffff").is_err()); // Wrong length
         assert!(parse_hex_color("#gggggg").is_err()); // Invalid hex
         assert!(parse_hex_color("#ggg").is_err()); // Invalid hex shorthand
+        assert!(parse_hex_color("é0").is_err()); // Non-ASCII
+        assert!(matches!(
+            parse_hex_color("#gggggg"),
+            Err(Error::InvalidColor { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_color_hex() {
+        assert_eq!(parse_color("ff0000").unwrap(), [255, 0, 0]);
+        assert_eq!(parse_color("#f00").unwrap(), [255, 0, 0]);
+        assert_eq!(parse_color(" #369 ").unwrap(), [51, 102, 153]);
+
+        // Opaque alpha is accepted
+        assert_eq!(parse_color("#ff0000ff").unwrap(), [255, 0, 0]);
+        assert_eq!(parse_color("#f00f").unwrap(), [255, 0, 0]);
+        assert_eq!(parse_color("00ff00ff").unwrap(), [0, 255, 0]);
+
+        // Translucent alpha is rejected
+        let err = parse_color("#ff000080").unwrap_err();
+        assert!(err.to_string().contains("opaque"), "{}", err);
+        assert!(parse_color("#f008").is_err());
+
+        let err = parse_color("12345").unwrap_err();
+        assert!(err.to_string().contains("3, 4, 6 or 8"), "{}", err);
+        assert!(parse_color("#ff00000").is_err());
+        assert!(parse_color("#ggg").is_err());
+        assert!(parse_color("#ff0000gg").is_err());
+        assert!(parse_color("#fffffé0").is_err());
+        assert!(parse_color("fffé").is_err());
+    }
+
+    #[test]
+    fn test_parse_color_rgb() {
+        assert_eq!(parse_color("rgb(12 34 56)").unwrap(), [12, 34, 56]);
+        assert_eq!(parse_color("rgb(12, 34, 56)").unwrap(), [12, 34, 56]);
+        assert_eq!(parse_color("RGB( 12 34 56 )").unwrap(), [12, 34, 56]);
+        assert_eq!(parse_color("rgb(100% 50% 0%)").unwrap(), [255, 128, 0]);
+        assert_eq!(parse_color("rgb(12.4 34.6 56)").unwrap(), [12, 35, 56]);
+        assert_eq!(parse_color("rgb(12 34 56 / 1)").unwrap(), [12, 34, 56]);
+        assert_eq!(parse_color("rgb(12 34 56 / 100%)").unwrap(), [12, 34, 56]);
+        assert_eq!(parse_color("rgba(12, 34, 56, 1)").unwrap(), [12, 34, 56]);
+
+        let err = parse_color("rgb(300 0 0)").unwrap_err();
+        assert!(err.to_string().contains("out of range"), "{}", err);
+        assert!(parse_color("rgb(-1 0 0)").is_err());
+        assert!(parse_color("rgb(0 120% 0)").is_err());
+        assert!(parse_color("rgb(12 34 56 / 0.5)").is_err());
+        assert!(parse_color("rgba(12, 34, 56, 50%)").is_err());
+        assert!(parse_color("rgb(12 34 56 / 2)").is_err());
+        assert!(parse_color("rgb(12 34)").is_err());
+        assert!(parse_color("rgb(a b c)").is_err());
+        assert!(parse_color("lab(50 0 0)").is_err());
+    }
+
+    #[test]
+    fn test_parse_color_normalized_keeps_precision() {
+        let color = parse_color_normalized("rgb(18.2 52 100%)").unwrap();
+        assert_eq!(color, [18.2 / 255.0, 52.0 / 255.0, 1.0]);
+        assert_eq!(parse_color_normalized("#ff0000").unwrap(), [1.0, 0.0, 0.0]);
+        assert!(parse_color_normalized("rgb(12 34 56 / 0.5)").is_err());
+    }
+
+    #[test]
+    fn test_parse_color_hsl() {
+        assert_eq!(parse_color("hsl(0 100% 50%)").unwrap(), [255, 0, 0]);
+        assert_eq!(parse_color("hsl(120, 100%, 50%)").unwrap(), [0, 255, 0]);
+        assert_eq!(parse_color("hsl(240deg 100% 50%)").unwrap(), [0, 0, 255]);
+        assert_eq!(parse_color("hsl(0.5turn 100% 25%)").unwrap(), [0, 128, 128]);
+        assert_eq!(parse_color("hsl(-120 100% 50%)").unwrap(), [0, 0, 255]);
+        assert_eq!(parse_color("hsl(0 0% 100%)").unwrap(), [255, 255, 255]);
+        assert_eq!(
+            parse_color("hsla(30, 100%, 50%, 1)").unwrap(),
+            [255, 128, 0]
+        );
+
+        assert!(parse_color("hsl(0 120% 50%)").is_err());
+        assert!(parse_color("hsl(0 100% 50% / 0)").is_err());
+        assert!(parse_color("hsl(red 100% 50%)").is_err());
+    }
+
+    #[test]
+    fn test_parse_color_names() {
+        assert_eq!(parse_color("red").unwrap(), [255, 0, 0]);
+        assert_eq!(parse_color("White").unwrap(), [255, 255, 255]);
+        assert_eq!(parse_color("rebeccapurple").unwrap(), [102, 51, 153]);
+        assert_eq!(parse_color("grey").unwrap(), parse_color("gray").unwrap());
+        assert!(parse_color("transparent").is_err());
+        assert!(parse_color("reddish").is_err());
+
+        // The table must stay sorted for binary search
+        assert!(NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn test_rgb_distance() {
+        let distance = ColorDistance::Rgb;
+        assert!((distance.between([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]) - 1.0).abs() < 1e-10);
+        assert!((distance.between([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]) - 1.732).abs() < 0.01); // sqrt(3)
+    }
+
+    #[test]
+    fn test_srgb_to_oklab() {
+        let white = srgb_to_oklab([1.0, 1.0, 1.0]);
+        assert!((white[0] - 1.0).abs() < 1e-4);
+        assert!(white[1].abs() < 1e-4 && white[2].abs() < 1e-4);
+
+        // Reference value for pure red
+        let red = srgb_to_oklab([1.0, 0.0, 0.0]);
+        assert!((red[0] - 0.6279).abs() < 1e-3);
+        assert!((red[1] - 0.2249).abs() < 1e-3);
+        assert!((red[2] - 0.1258).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_oklab_distance_is_perceptual() {
+        let distance = ColorDistance::Oklab;
+        assert!((distance.between([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]) - 1.0).abs() < 1e-4);
+
+        // The same RGB step is far more visible between dark blues than yellows
+        let step = 0.1;
+        let blues = distance.between([0.0, 0.0, 0.2], [0.0, 0.0, 0.2 + step]);
+        let yellows = distance.between([0.9, 0.9, 0.0], [0.9, 0.9, step]);
+        let rgb = ColorDistance::Rgb;
+        assert!(
+            (rgb.between([0.0, 0.0, 0.2], [0.0, 0.0, 0.2 + step])
+                - rgb.between([0.9, 0.9, 0.0], [0.9, 0.9, step]))
+            .abs()
+                < 1e-10
+        );
+        assert!(blues > yellows, "{} <= {}", blues, yellows);
+    }
+
+    #[test]
+    fn test_blend_space_round_trip() {
+        let linear = BlendSpace::Linear;
+        assert_eq!(BlendSpace::Srgb.decode([0.5, 0.25, 1.0]), [0.5, 0.25, 1.0]);
+        assert!((linear.decode([0.5, 0.5, 0.5])[0] - 0.214).abs() < 1e-3);
+
+        for value in [0.0, 0.02, 0.5, 0.73, 1.0] {
+            let color = [value, value, value];
+            let round_trip = linear.encode(linear.decode(color));
+            assert!((round_trip[0] - value).abs() < 1e-9, "{}", value);
+        }
     }
 
     #[test]
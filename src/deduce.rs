@@ -1,8 +1,11 @@
-use crate::color::{Color, ForegroundColorSpec, NormalizedColor, normalize_color};
+use crate::color::{
+    BlendSpace, Color, ColorDistance, ForegroundColorSpec, NormalizedColor, denormalize_color,
+    normalize_color,
+};
+use crate::error::{Error, Result};
+use crate::progress::{NoopObserver, ProgressObserver};
 use crate::unmix::{compute_result_color, unmix_colors_internal};
-use anyhow::Result;
 use image::DynamicImage;
-use indicatif::{ProgressBar, ProgressStyle};
 use std::collections::HashMap;
 
 // Constants for color deduction algorithm
@@ -16,21 +19,24 @@ fn color_distance(c1: NormalizedColor, c2: NormalizedColor) -> f64 {
 }
 
 /// Given observed colors and a background, find candidate foreground colors
-/// that could have produced these observations through alpha blending
+/// that could have produced these observations through alpha blending in
+/// `blend_space`
 fn find_candidate_foreground_colors(
     observed_colors: &[(Color, usize)], // (color, count)
     background: Color,
     num_candidates: usize,
     threshold: f64,
+    distance: ColorDistance,
+    blend_space: BlendSpace,
 ) -> Vec<Color> {
-    let bg_norm = normalize_color(background);
+    let bg_norm = blend_space.decode(normalize_color(background));
     let mut candidates = Vec::new();
 
     // For each observed color, calculate what foreground colors could produce it
     // at various alpha levels
     for &(observed, _) in observed_colors.iter().take(100) {
         // Limit to avoid too many candidates
-        let obs_norm = normalize_color(observed);
+        let obs_norm = blend_space.decode(normalize_color(observed));
 
         // Skip if too close to background
         if color_distance(obs_norm, bg_norm) < 0.01 {
@@ -58,18 +64,12 @@ fn find_candidate_foreground_colors(
             }
 
             if valid {
-                let fg_u8 = [
-                    (fg[0] * 255.0).round() as u8,
-                    (fg[1] * 255.0).round() as u8,
-                    (fg[2] * 255.0).round() as u8,
-                ];
+                let fg_u8 = denormalize_color(blend_space.encode(fg));
 
                 // Verify reconstruction
-                let reconstructed = [
-                    (fg[0] * alpha + bg_norm[0] * (1.0 - alpha)) * 255.0,
-                    (fg[1] * alpha + bg_norm[1] * (1.0 - alpha)) * 255.0,
-                    (fg[2] * alpha + bg_norm[2] * (1.0 - alpha)) * 255.0,
-                ];
+                let reconstructed = blend_space
+                    .encode([0, 1, 2].map(|i| fg[i] * alpha + bg_norm[i] * (1.0 - alpha)))
+                    .map(|channel| channel * 255.0);
 
                 let error = (0..3)
                     .map(|i| (reconstructed[i] - observed[i] as f64).powi(2))
@@ -89,7 +89,8 @@ fn find_candidate_foreground_colors(
     for candidate in candidates {
         let mut is_duplicate = false;
         for existing in &unique_candidates {
-            if color_distance(normalize_color(candidate), normalize_color(*existing)) < threshold {
+            if distance.between(normalize_color(candidate), normalize_color(*existing)) < threshold
+            {
                 is_duplicate = true;
                 break;
             }
@@ -101,14 +102,14 @@ fn find_candidate_foreground_colors(
 
     // If we have too many candidates, select the most different ones
     if unique_candidates.len() > num_candidates {
-        select_most_different_colors(&unique_candidates, num_candidates)
+        select_most_different_colors(&unique_candidates, num_candidates, distance)
     } else {
         unique_candidates
     }
 }
 
 /// Select N most different colors from a set, prioritizing pure/standard colors
-fn select_most_different_colors(colors: &[Color], n: usize) -> Vec<Color> {
+fn select_most_different_colors(colors: &[Color], n: usize, distance: ColorDistance) -> Vec<Color> {
     if colors.len() <= n {
         return colors.to_vec();
     }
@@ -132,7 +133,8 @@ fn select_most_different_colors(colors: &[Color], n: usize) -> Vec<Color> {
                     selected
                         .iter()
                         .map(|s| {
-                            let dist = color_distance(normalize_color(color), normalize_color(*s));
+                            let dist =
+                                distance.between(normalize_color(color), normalize_color(*s));
                             (dist * 1000.0) as i32
                         })
                         .min()
@@ -151,27 +153,37 @@ fn select_most_different_colors(colors: &[Color], n: usize) -> Vec<Color> {
 }
 
 /// Evaluate how well a set of foreground colors reproduces the image
+///
+/// Colors are unmixed and reconstructed in `blend_space`, and the error is
+/// measured on the sRGB-encoded values.
 fn evaluate_color_set(
     foreground_colors: &[NormalizedColor],
     pixels: &[(Color, usize)], // (color, count)
     background: NormalizedColor,
+    blend_space: BlendSpace,
 ) -> f64 {
     let mut total_error = 0.0;
     let mut total_weight = 0.0;
 
+    let blend_foregrounds = blend_space.decode_all(foreground_colors);
+    let blend_background = blend_space.decode(background);
+
     for &(observed, count) in pixels {
         let weight = (count as f64).sqrt(); // Square root to reduce dominance of most common colors
 
         // Try to unmix this color
-        let unmix_result = unmix_colors_internal(observed, foreground_colors, background, false);
-        let (result_color, alpha) = compute_result_color(&unmix_result, foreground_colors);
+        let unmix_result = unmix_colors_internal(
+            blend_space.decode(normalize_color(observed)),
+            &blend_foregrounds,
+            blend_background,
+            false,
+        );
+        let (result_color, alpha) = compute_result_color(&unmix_result, &blend_foregrounds);
 
         // Reconstruct what we would see
-        let reconstructed = [
-            result_color[0] * alpha + background[0] * (1.0 - alpha),
-            result_color[1] * alpha + background[1] * (1.0 - alpha),
-            result_color[2] * alpha + background[2] * (1.0 - alpha),
-        ];
+        let reconstructed = blend_space.encode(
+            [0, 1, 2].map(|i| result_color[i] * alpha + blend_background[i] * (1.0 - alpha)),
+        );
 
         // Compute error
         let observed_norm = normalize_color(observed);
@@ -215,6 +227,9 @@ fn evaluate_color_set(
 /// * `image` - The input image
 /// * `specs` - The foreground color specifications (mix of known and unknown)
 /// * `background_color` - The background color
+/// * `threshold` - Candidates closer than this are considered the same color
+/// * `distance` - How the distance between colors is measured
+/// * `blend_space` - Color space in which the foreground was blended over the background
 ///
 /// # Returns
 /// A vector of all foreground colors with unknowns replaced by deduced colors
@@ -223,6 +238,29 @@ pub fn deduce_unknown_colors(
     specs: &[ForegroundColorSpec],
     background_color: Color,
     threshold: f64,
+    distance: ColorDistance,
+    blend_space: BlendSpace,
+) -> Result<Vec<Color>> {
+    deduce_unknown_colors_with_observer(
+        image,
+        specs,
+        background_color,
+        threshold,
+        distance,
+        blend_space,
+        &NoopObserver,
+    )
+}
+
+/// Deduce unknown foreground colors from an image, reporting progress to an observer
+pub fn deduce_unknown_colors_with_observer(
+    image: &DynamicImage,
+    specs: &[ForegroundColorSpec],
+    background_color: Color,
+    threshold: f64,
+    distance: ColorDistance,
+    blend_space: BlendSpace,
+    observer: &dyn ProgressObserver,
 ) -> Result<Vec<Color>> {
     // Separate known and unknown specs
     let mut known_colors = Vec::new();
@@ -262,16 +300,7 @@ pub fn deduce_unknown_colors(
     let mut pixels: Vec<(Color, usize)> = color_counts.into_iter().collect();
     pixels.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
 
-    println!("  Found {} unique colors in image", pixels.len());
-
-    // Setup progress bar for deduction
-    let progress = ProgressBar::new_spinner();
-    progress.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} Deducing unknown colors...")
-            .expect("Failed to create progress bar style"),
-    );
-    progress.enable_steady_tick(std::time::Duration::from_millis(100));
+    observer.deduction_started(pixels.len());
 
     // Find candidate foreground colors based on unmixing
     let unknown_count = unknown_indices.len();
@@ -280,6 +309,8 @@ pub fn deduce_unknown_colors(
         background_color,
         unknown_count * 10, // Get more candidates for better selection
         threshold,
+        distance,
+        blend_space,
     );
 
     // If we don't have enough candidates, add some standard colors
@@ -319,7 +350,8 @@ pub fn deduce_unknown_colors(
 
     // For 1-2 unknowns, try all combinations
     if unknown_count == 1 {
-        for candidate in &all_candidates {
+        let total = all_candidates.len();
+        for (evaluated, candidate) in all_candidates.iter().enumerate() {
             let mut test_fg = vec![[0.0; 3]; specs.len()];
             let mut known_idx = 0;
 
@@ -335,13 +367,17 @@ pub fn deduce_unknown_colors(
                 }
             }
 
-            let error = evaluate_color_set(&test_fg, &pixels, background_norm);
+            let error = evaluate_color_set(&test_fg, &pixels, background_norm, blend_space);
             if error < best_error {
                 best_error = error;
                 best_colors = vec![*candidate];
             }
+            observer.deduction_progress(evaluated + 1, total);
         }
     } else if unknown_count == 2 && all_candidates.len() <= MAX_CANDIDATES_2_UNKNOWNS {
+        let n = all_candidates.len();
+        let total = n * n.saturating_sub(1) / 2;
+        let mut evaluated = 0;
         for (i, c1) in all_candidates.iter().enumerate() {
             for c2 in all_candidates.iter().skip(i + 1) {
                 let mut test_fg = vec![[0.0; 3]; specs.len()];
@@ -362,11 +398,13 @@ pub fn deduce_unknown_colors(
                     }
                 }
 
-                let error = evaluate_color_set(&test_fg, &pixels, background_norm);
+                let error = evaluate_color_set(&test_fg, &pixels, background_norm, blend_space);
                 if error < best_error {
                     best_error = error;
                     best_colors = test_unknown.to_vec();
                 }
+                evaluated += 1;
+                observer.deduction_progress(evaluated, total);
             }
         }
     } else if unknown_count == 3 {
@@ -377,10 +415,17 @@ pub fn deduce_unknown_colors(
         } else {
             // Large set: select the most different candidates to keep computation reasonable
             // (20 choose 3 = 1140 combinations)
-            select_most_different_colors(&all_candidates, MAX_CANDIDATES_3_UNKNOWNS_SELECTED)
+            select_most_different_colors(
+                &all_candidates,
+                MAX_CANDIDATES_3_UNKNOWNS_SELECTED,
+                distance,
+            )
         };
 
         // Exhaustive search through all 3-color combinations
+        let n = candidates_to_try.len();
+        let total = n * n.saturating_sub(1) * n.saturating_sub(2) / 6;
+        let mut evaluated = 0;
         for (i, c1) in candidates_to_try.iter().enumerate() {
             for (j, c2) in candidates_to_try.iter().enumerate().skip(i + 1) {
                 for c3 in candidates_to_try.iter().skip(j + 1) {
@@ -402,20 +447,28 @@ pub fn deduce_unknown_colors(
                         }
                     }
 
-                    let error = evaluate_color_set(&test_fg, &pixels, background_norm);
+                    let error = evaluate_color_set(&test_fg, &pixels, background_norm, blend_space);
                     if error < best_error {
                         best_error = error;
                         best_colors = test_unknown.to_vec();
                     }
+                    evaluated += 1;
+                    observer.deduction_progress(evaluated, total);
                 }
             }
         }
     } else {
         // For 4+ unknowns, use the most different candidates
-        best_colors = select_most_different_colors(&all_candidates, unknown_count);
+        best_colors = select_most_different_colors(&all_candidates, unknown_count, distance);
     }
 
-    progress.finish_and_clear();
+    if best_colors.len() < unknown_count {
+        return Err(Error::DeductionFailed(format!(
+            "found only {} candidate colors for {} unknown colors",
+            best_colors.len(),
+            unknown_count
+        )));
+    }
 
     // Build final result
     let mut final_colors = Vec::new();
@@ -427,33 +480,13 @@ pub fn deduce_unknown_colors(
                 final_colors.push(*color);
             }
             ForegroundColorSpec::Unknown => {
-                if unknown_idx < best_colors.len() {
-                    final_colors.push(best_colors[unknown_idx]);
-                } else {
-                    final_colors.push([128, 128, 128]); // Fallback
-                }
+                final_colors.push(best_colors[unknown_idx]);
                 unknown_idx += 1;
             }
         }
     }
 
-    // Print results
-    let deduced_strs: Vec<String> = best_colors
-        .iter()
-        .map(|&[r, g, b]| format!("#{:02x}{:02x}{:02x}", r, g, b))
-        .collect();
-
-    let plural = if unknown_count == 1 {
-        "color"
-    } else {
-        "colors"
-    };
-    println!(
-        "✓ Deduced {} unknown {}: {}",
-        unknown_count,
-        plural,
-        deduced_strs.join(" ")
-    );
+    observer.deduction_finished(&best_colors);
 
     Ok(final_colors)
 }
@@ -472,8 +505,48 @@ mod tests {
         ];
 
         let img = DynamicImage::new_rgb8(10, 10);
-        let result = deduce_unknown_colors(&img, &specs, [0, 0, 0], 0.05).unwrap();
+        let result = deduce_unknown_colors(
+            &img,
+            &specs,
+            [0, 0, 0],
+            0.05,
+            ColorDistance::Rgb,
+            BlendSpace::Srgb,
+        )
+        .unwrap();
 
         assert_eq!(result, vec![[255, 0, 0], [0, 255, 0]]);
     }
+
+    #[test]
+    fn test_not_enough_candidates() {
+        // A background-only image has no candidates of its own, and the
+        // standard colors are all known already
+        let mut specs: Vec<_> = [
+            [255, 0, 0],
+            [0, 255, 0],
+            [0, 0, 255],
+            [255, 255, 0],
+            [255, 0, 255],
+            [0, 255, 255],
+            [255, 128, 0],
+            [128, 0, 255],
+        ]
+        .into_iter()
+        .map(ForegroundColorSpec::Known)
+        .collect();
+        specs.extend((0..4).map(|_| ForegroundColorSpec::Unknown));
+
+        let img = DynamicImage::new_rgb8(10, 10);
+        let result = deduce_unknown_colors(
+            &img,
+            &specs,
+            [0, 0, 0],
+            0.05,
+            ColorDistance::Rgb,
+            BlendSpace::Srgb,
+        );
+
+        assert!(matches!(result, Err(Error::DeductionFailed(_))));
+    }
 }
@@ -0,0 +1,205 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::color::Color;
+use crate::options::Mode;
+use crate::quality::ErrorStats;
+
+/// Errors returned by the bgone library
+#[derive(Debug)]
+pub enum Error {
+    /// A color string could not be parsed
+    InvalidColor {
+        /// The color string as given
+        input: String,
+        /// Why it could not be parsed
+        reason: String,
+    },
+    /// A pixel position string could not be parsed
+    InvalidPosition {
+        /// The position string as given
+        input: String,
+        /// Why it could not be parsed
+        reason: String,
+    },
+    /// A pixel position is outside the image
+    PositionOutOfBounds {
+        position: (u32, u32),
+        dimensions: (u32, u32),
+    },
+    /// An input image could not be opened or decoded
+    ImageDecode {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    /// An output image could not be encoded or written
+    ImageEncode {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    /// A palette file could not be read or written
+    PaletteIo {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A palette file could not be parsed, or has an unsupported format
+    InvalidPalette {
+        path: PathBuf,
+        /// Why it could not be parsed
+        reason: String,
+    },
+    /// No swatch of a palette has the requested name
+    SwatchNotFound {
+        name: String,
+        /// Names of the swatches in the palette
+        available: Vec<String>,
+    },
+    /// The color similarity threshold is outside 0.0-1.0
+    InvalidThreshold(f64),
+    /// A linear light color has a negative or non-finite channel
+    InvalidLinearColor([f32; 3]),
+    /// The largest foreground value of an HDR image is below 1.0 or not finite
+    InvalidMaxForeground(f32),
+    /// A per-pixel background was given for a floating-point image
+    HdrBackgroundImageUnsupported,
+    /// Strict mode was requested without any foreground color
+    StrictModeRequiresForeground,
+    /// The mode does not match the presence of foreground colors
+    ModeForegroundMismatch { mode: Mode, foreground_count: usize },
+    /// Unknown foreground colors could not be deduced
+    DeductionFailed(String),
+    /// A background gradient could not be fitted
+    GradientFitFailed(String),
+    /// Difference matting was given the same background color for both images
+    IdenticalPairBackgrounds(Color),
+    /// Layer export was requested without any foreground color
+    LayersRequireForeground,
+    /// Two images that must have the same size do not
+    DimensionMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// The output does not reconstruct the input within the tolerance
+    ReconstructionExceedsTolerance(ErrorStats),
+}
+
+/// Result type used throughout the bgone library
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Create an [`Error::InvalidColor`] error
+    pub(crate) fn invalid_color(input: &str, reason: impl Into<String>) -> Self {
+        Error::InvalidColor {
+            input: input.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidColor { input, reason } => {
+                write!(f, "Invalid color '{}': {}", input, reason)
+            }
+            Error::InvalidPosition { input, reason } => {
+                write!(f, "Invalid pixel position '{}': {}", input, reason)
+            }
+            Error::PositionOutOfBounds {
+                position,
+                dimensions,
+            } => write!(
+                f,
+                "Pixel position {},{} is outside the {}x{} image",
+                position.0, position.1, dimensions.0, dimensions.1
+            ),
+            Error::ImageDecode { path, .. } => {
+                write!(f, "Failed to open input image: {}", path.display())
+            }
+            Error::ImageEncode { path, .. } => {
+                write!(f, "Failed to save output image: {}", path.display())
+            }
+            Error::PaletteIo { path, .. } => {
+                write!(f, "Failed to access palette file: {}", path.display())
+            }
+            Error::InvalidPalette { path, reason } => {
+                write!(f, "Invalid palette {}: {}", path.display(), reason)
+            }
+            Error::SwatchNotFound { name, available } => write!(
+                f,
+                "No swatch named '{}' in the palette (available: {})",
+                name,
+                available.join(", ")
+            ),
+            Error::InvalidThreshold(threshold) => {
+                write!(
+                    f,
+                    "Threshold must be between 0.0 and 1.0, got: {}",
+                    threshold
+                )
+            }
+            Error::InvalidLinearColor([r, g, b]) => write!(
+                f,
+                "Linear colors need finite, non-negative channels, got: {},{},{}",
+                r, g, b
+            ),
+            Error::InvalidMaxForeground(max_foreground) => write!(
+                f,
+                "The largest foreground value must be at least 1.0, got: {}",
+                max_foreground
+            ),
+            Error::HdrBackgroundImageUnsupported => write!(
+                f,
+                "Gradient and clean plate backgrounds are not supported for floating-point images"
+            ),
+            Error::StrictModeRequiresForeground => {
+                write!(
+                    f,
+                    "In strict mode, at least one foreground color must be specified"
+                )
+            }
+            Error::ModeForegroundMismatch {
+                mode,
+                foreground_count,
+            } => write!(
+                f,
+                "{:?} mode cannot be used with {} foreground colors",
+                mode, foreground_count
+            ),
+            Error::DeductionFailed(reason) => {
+                write!(f, "Failed to deduce unknown colors: {}", reason)
+            }
+            Error::GradientFitFailed(reason) => {
+                write!(f, "Failed to fit background gradient: {}", reason)
+            }
+            Error::IdenticalPairBackgrounds([r, g, b]) => write!(
+                f,
+                "The two images of a pair need different background colors, got #{:02x}{:02x}{:02x} for both",
+                r, g, b
+            ),
+            Error::LayersRequireForeground => {
+                write!(f, "Exporting layers requires at least one foreground color")
+            }
+            Error::DimensionMismatch { expected, actual } => write!(
+                f,
+                "Image dimensions do not match: expected {}x{}, got {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            Error::ReconstructionExceedsTolerance(stats) => write!(
+                f,
+                "Reconstruction exceeds tolerance: {} of {} pixels differ by more than {} levels (max {})",
+                stats.over_tolerance, stats.pixel_count, stats.tolerance, stats.max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ImageDecode { source, .. } | Error::ImageEncode { source, .. } => Some(source),
+            Error::PaletteIo { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::background::NormalizedRgbImage;
+use crate::error::{Error, Result};
+use crate::pixel::normalized_pixel;
+use image::{DynamicImage, GenericImageView, Rgb};
+use nalgebra::DMatrix;
+
+/// Number of refits after dropping border samples that don't match the gradient
+const OUTLIER_PASSES: usize = 3;
+
+/// Border samples within this distance of the fitted gradient, in 8-bit levels,
+/// are always kept as inliers (the outlier cutoff never goes below it)
+const MIN_OUTLIER_DISTANCE: f64 = 4.0;
+
+/// Shape of a background gradient
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientModel {
+    /// A plane: `c0 + c1*x + c2*y`, for straight linear gradients
+    Linear,
+    /// A plane with a twist: adds `c3*x*y`, for gradients between four corner colors
+    Bilinear,
+    /// A second order polynomial: adds `c4*x² + c5*y²`, for soft radial gradients
+    Quadratic,
+}
+
+impl GradientModel {
+    /// Number of polynomial terms in the model
+    fn term_count(self) -> usize {
+        match self {
+            GradientModel::Linear => 3,
+            GradientModel::Bilinear => 4,
+            GradientModel::Quadratic => 6,
+        }
+    }
+
+    /// Evaluate the polynomial terms at normalized coordinates (-1.0 to 1.0)
+    fn terms(self, u: f64, v: f64) -> impl Iterator<Item = f64> {
+        [1.0, u, v, u * v, u * u, v * v]
+            .into_iter()
+            .take(self.term_count())
+    }
+}
+
+/// Fit a gradient to the border of an image and render the background color
+/// behind every pixel
+///
+/// Every border pixel is sampled at the precision of the image (translucent
+/// pixels are composited over black, like in background detection), and each
+/// channel is fitted with a least-squares polynomial. Samples that are far
+/// from the fit, such as foreground objects touching the border, are dropped
+/// and the fit is repeated.
+///
+/// # Arguments
+/// * `img` - The image to analyze
+/// * `model` - The shape of the gradient
+///
+/// # Returns
+/// An image of the same size with the fitted background color of every pixel,
+/// unrounded so that background pixels of 16-bit images and gradients between
+/// 8-bit levels are removed exactly
+pub fn fit_background_gradient(
+    img: &DynamicImage,
+    model: GradientModel,
+) -> Result<NormalizedRgbImage> {
+    let (width, height) = img.dimensions();
+    if width < 2 || height < 2 {
+        return Err(Error::GradientFitFailed(format!(
+            "the image is too small ({}x{})",
+            width, height
+        )));
+    }
+
+    let to_u = |x: u32| 2.0 * x as f64 / (width - 1) as f64 - 1.0;
+    let to_v = |y: u32| 2.0 * y as f64 / (height - 1) as f64 - 1.0;
+
+    let samples: Vec<((f64, f64), [f64; 3])> = border_points(width, height)
+        .map(|(x, y)| {
+            let pixel = normalized_pixel(img, x, y);
+            let color = [0, 1, 2].map(|i| pixel[i] * pixel[3] * 255.0);
+            ((to_u(x), to_v(y)), color)
+        })
+        .collect();
+
+    let mut inliers: Vec<_> = samples.iter().collect();
+    let mut coefficients = solve(model, &inliers)?;
+
+    for _ in 0..OUTLIER_PASSES {
+        let distances: Vec<f64> = samples
+            .iter()
+            .map(|(point, color)| distance(model, &coefficients, *point, *color))
+            .collect();
+
+        // Keep samples within a few median distances of the fit
+        let mut sorted = distances.clone();
+        sorted.sort_by(f64::total_cmp);
+        let cutoff = (sorted[sorted.len() / 2] * 3.0).max(MIN_OUTLIER_DISTANCE);
+
+        let next: Vec<_> = samples
+            .iter()
+            .zip(&distances)
+            .filter(|&(_, &distance)| distance <= cutoff)
+            .map(|(sample, _)| sample)
+            .collect();
+        if next.len() == inliers.len() || next.len() < model.term_count() {
+            break;
+        }
+
+        inliers = next;
+        coefficients = solve(model, &inliers)?;
+    }
+
+    Ok(NormalizedRgbImage::from_fn(width, height, |x, y| {
+        let color = evaluate(model, &coefficients, (to_u(x), to_v(y)));
+        Rgb(color.map(|value| (value / 255.0).clamp(0.0, 1.0)))
+    }))
+}
+
+/// Iterate over every pixel on the border of an image, once each
+fn border_points(width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+    let horizontal = (0..width).flat_map(move |x| [(x, 0), (x, height - 1)]);
+    let vertical = (1..height - 1).flat_map(move |y| [(0, y), (width - 1, y)]);
+    horizontal.chain(vertical)
+}
+
+/// Least-squares fit of the model to samples, one column of coefficients per channel
+fn solve(model: GradientModel, samples: &[&((f64, f64), [f64; 3])]) -> Result<DMatrix<f64>> {
+    let terms = DMatrix::from_row_iterator(
+        samples.len(),
+        model.term_count(),
+        samples.iter().flat_map(|((u, v), _)| model.terms(*u, *v)),
+    );
+    let colors = DMatrix::from_row_iterator(
+        samples.len(),
+        3,
+        samples.iter().flat_map(|(_, color)| *color),
+    );
+
+    terms
+        .svd(true, true)
+        .solve(&colors, 1e-10)
+        .map_err(|reason| Error::GradientFitFailed(reason.to_string()))
+}
+
+/// Evaluate the fitted gradient at normalized coordinates, in 8-bit levels
+fn evaluate(model: GradientModel, coefficients: &DMatrix<f64>, (u, v): (f64, f64)) -> [f64; 3] {
+    let mut color = [0.0; 3];
+    for (row, term) in model.terms(u, v).enumerate() {
+        for (channel, value) in color.iter_mut().enumerate() {
+            *value += coefficients[(row, channel)] * term;
+        }
+    }
+
+    color
+}
+
+/// Largest channel difference between a sample and the fitted gradient
+fn distance(
+    model: GradientModel,
+    coefficients: &DMatrix<f64>,
+    point: (f64, f64),
+    color: [f64; 3],
+) -> f64 {
+    let fitted = evaluate(model, coefficients, point);
+    (0..3)
+        .map(|i| (fitted[i] - color[i]).abs())
+        .fold(0.0, f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn linear_gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let r = (x * 255 / (width - 1)) as u8;
+            let b = (y * 255 / (height - 1)) as u8;
+            Rgba([r, 100, b, 255])
+        })
+    }
+
+    #[test]
+    fn test_fit_linear_gradient() {
+        let img = DynamicImage::ImageRgba8(linear_gradient(64, 32));
+        let background = fit_background_gradient(&img, GradientModel::Linear).unwrap();
+
+        assert_eq!(background.dimensions(), (64, 32));
+        for (x, y, pixel) in background.enumerate_pixels() {
+            let expected = img.as_rgba8().unwrap().get_pixel(x, y);
+            for i in 0..3 {
+                assert!(
+                    (pixel[i] * 255.0 - expected[i] as f64).abs() <= 1.0,
+                    "Pixel ({}, {}) is {:?}, expected {:?}",
+                    x,
+                    y,
+                    pixel,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_fit_ignores_foreground_on_border() {
+        // A red bar crossing the top edge of a linear gradient
+        let mut img = linear_gradient(64, 64);
+        for x in 20..30 {
+            for y in 0..20 {
+                img.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+            }
+        }
+
+        let img = DynamicImage::ImageRgba8(img);
+        let background = fit_background_gradient(&img, GradientModel::Bilinear).unwrap();
+
+        let expected = linear_gradient(64, 64);
+        let fitted = background.get_pixel(25, 0);
+        let original = expected.get_pixel(25, 0);
+        for i in 0..3 {
+            assert!((fitted[i] * 255.0 - original[i] as f64).abs() <= 2.0);
+        }
+    }
+
+    #[test]
+    fn test_fit_quadratic_radial_gradient() {
+        // Brighter in the center, darker towards the edges
+        let img = RgbaImage::from_fn(50, 50, |x, y| {
+            let u = x as f64 / 49.0 * 2.0 - 1.0;
+            let v = y as f64 / 49.0 * 2.0 - 1.0;
+            let value = (200.0 - 50.0 * (u * u + v * v)).round() as u8;
+            Rgba([value, value, value, 255])
+        });
+
+        let img = DynamicImage::ImageRgba8(img);
+        let background = fit_background_gradient(&img, GradientModel::Quadratic).unwrap();
+        assert!((background.get_pixel(25, 25)[0] * 255.0 - 200.0).abs() <= 1.0);
+        assert!((background.get_pixel(0, 0)[0] * 255.0 - 100.0).abs() <= 1.0);
+    }
+
+    #[test]
+    fn test_fit_keeps_16_bit_precision() {
+        // A gradient in steps of a 16-bit level, between two 8-bit levels
+        let img = image::ImageBuffer::from_fn(40, 20, |x, _| {
+            let value = 0x8000 + x as u16;
+            Rgba([value, value, value, 65535])
+        });
+
+        let img = DynamicImage::ImageRgba16(img);
+        let background = fit_background_gradient(&img, GradientModel::Linear).unwrap();
+        for x in [0, 13, 39] {
+            let expected = (0x8000 + x) as f64 / 65535.0;
+            let fitted = background.get_pixel(x, 10)[0];
+            assert!((fitted - expected).abs() < 1e-9, "{} at x={}", fitted, x);
+        }
+    }
+
+    #[test]
+    fn test_fit_too_small() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::new(1, 10));
+        assert!(matches!(
+            fit_background_gradient(&img, GradientModel::Linear),
+            Err(Error::GradientFitFailed(_))
+        ));
+    }
+}
@@ -0,0 +1,323 @@
+use crate::color::{
+    BlendSpace, Color, NormalizedColor, denormalize_color, linear_to_srgb, normalize_color,
+    srgb_to_linear,
+};
+use crate::error::{Error, Result};
+use crate::options::ProcessOptions;
+use crate::progress::{NoopObserver, ProgressObserver};
+use crate::quality::ReconstructionError;
+use crate::unmixer::unmixer_with_max_foreground;
+use image::{GrayImage, Luma, Rgba, Rgba32FImage, RgbaImage};
+use rayon::prelude::*;
+
+/// Color in linear light, with channels that may exceed 1.0
+pub type LinearColor = [f32; 3];
+
+/// Options for removing the background of a floating-point (HDR) image
+///
+/// # Example
+/// ```
+/// use bgone::hdr::HdrOptions;
+///
+/// // A bright gray background, with glows up to 4x brighter than white
+/// let options = HdrOptions::new([2.0, 2.0, 2.0]).max_foreground(4.0);
+/// assert!(options.validate().is_ok());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrOptions {
+    /// Background color in linear light
+    pub background: LinearColor,
+    /// Largest value of a foreground channel, in linear light
+    ///
+    /// With the default of 1.0, the foreground is no brighter than white and
+    /// pixels brighter than that become opaque. Higher values let bright HDR
+    /// glows keep a low alpha with a foreground brighter than white.
+    pub max_foreground: f32,
+}
+
+impl HdrOptions {
+    /// Create options for a background color in linear light
+    pub fn new(background: LinearColor) -> Self {
+        Self {
+            background,
+            max_foreground: 1.0,
+        }
+    }
+
+    /// Create options for an sRGB background color, such as one given with
+    /// `--bg` or detected on [`srgb_preview`]
+    pub fn from_srgb(background: Color) -> Self {
+        Self::from_normalized_srgb(normalize_color(background))
+    }
+
+    /// Create options for an sRGB background color with channels from 0.0 to
+    /// 1.0, keeping more than 8 bits of precision
+    pub fn from_normalized_srgb(background: NormalizedColor) -> Self {
+        Self::new(background.map(|channel| srgb_to_linear(channel) as f32))
+    }
+
+    /// Create options for the background color of processing options, with
+    /// `normalized_background_color` taking precedence when set
+    pub fn from_options(options: &ProcessOptions) -> Self {
+        match options.normalized_background_color {
+            Some(color) => Self::from_normalized_srgb(color),
+            None => Self::from_srgb(options.background_color),
+        }
+    }
+
+    /// Set the largest value of a foreground channel
+    pub fn max_foreground(mut self, max_foreground: f32) -> Self {
+        self.max_foreground = max_foreground;
+        self
+    }
+
+    /// Check that the background and foreground limit are usable
+    pub fn validate(&self) -> Result<()> {
+        if !self
+            .background
+            .iter()
+            .all(|channel| channel.is_finite() && *channel >= 0.0)
+        {
+            return Err(Error::InvalidLinearColor(self.background));
+        }
+        if !(self.max_foreground.is_finite() && self.max_foreground >= 1.0) {
+            return Err(Error::InvalidMaxForeground(self.max_foreground));
+        }
+        Ok(())
+    }
+
+    /// The background color encoded to sRGB, clamped to 8 bits
+    pub fn srgb_background(&self) -> Color {
+        denormalize_color(
+            self.background
+                .map(|channel| linear_to_srgb(channel as f64)),
+        )
+    }
+}
+
+/// Remove the background from a floating-point image
+///
+/// Pixels are in linear light and are unmixed without clamping them to 0.0-1.0.
+/// The mode, foreground colors (in sRGB), threshold and distance come from
+/// `options`; its background color and blend space are not used, since the
+/// background is `hdr.background` and floating-point images are always blended
+/// in linear light.
+///
+/// # Returns
+/// A new image of the same size with the background made transparent, with
+/// straight (not premultiplied) alpha, or
+/// [`Error::HdrBackgroundImageUnsupported`] if `options` has a per-pixel
+/// background image
+pub fn remove_background_hdr(
+    image: &Rgba32FImage,
+    hdr: &HdrOptions,
+    options: &ProcessOptions,
+) -> Result<Rgba32FImage> {
+    remove_background_hdr_with_observer(image, hdr, options, &NoopObserver)
+}
+
+/// Remove the background from a floating-point image, reporting progress to
+/// an observer
+///
+/// Colors are passed to the unmixer encoded to sRGB, with a linear blend
+/// space, so that it unmixes the linear values while foreground colors and
+/// closeness thresholds keep their usual meaning. sRGB encoding extends past
+/// 1.0, so values brighter than white survive the round trip.
+pub fn remove_background_hdr_with_observer(
+    image: &Rgba32FImage,
+    hdr: &HdrOptions,
+    options: &ProcessOptions,
+    observer: &dyn ProgressObserver,
+) -> Result<Rgba32FImage> {
+    options.validate()?;
+    hdr.validate()?;
+    if options.background_image.is_some() {
+        return Err(Error::HdrBackgroundImageUnsupported);
+    }
+
+    let options = ProcessOptions {
+        blend_space: BlendSpace::Linear,
+        ..options.clone()
+    };
+    let unmixer = unmixer_with_max_foreground(&options, hdr.max_foreground as f64);
+    let background = hdr.background.map(f64::from);
+    let encoded_background = BlendSpace::Linear.encode(background);
+
+    let (width, height) = image.dimensions();
+    let total_pixels = width as u64 * height as u64;
+    observer.processing_started(total_pixels);
+
+    let pixels: Vec<_> = image.pixels().collect();
+    let processed_pixels: Vec<Rgba<f32>> = pixels
+        .par_iter()
+        .inspect(|_| observer.pixels_processed(1))
+        .map(|pixel| {
+            let observed = composite_over(pixel, background);
+            let [r, g, b, alpha] =
+                unmixer.unmix_normalized(BlendSpace::Linear.encode(observed), encoded_background);
+            let [r, g, b] = BlendSpace::Linear.decode([r, g, b]);
+            Rgba([r as f32, g as f32, b as f32, alpha as f32])
+        })
+        .collect();
+
+    observer.processing_finished(total_pixels);
+
+    Ok(Rgba32FImage::from_fn(width, height, |x, y| {
+        processed_pixels[(y * width + x) as usize]
+    }))
+}
+
+/// Compute the reconstruction error of a floating-point output against its input
+///
+/// Both are composited over the background in linear light, then encoded to
+/// sRGB, and the error of a pixel is their largest channel difference in 8-bit
+/// levels (saturating at 255 for values far brighter than white).
+pub fn hdr_reconstruction_error(
+    input: &Rgba32FImage,
+    output: &Rgba32FImage,
+    background: LinearColor,
+) -> Result<ReconstructionError> {
+    if input.dimensions() != output.dimensions() {
+        return Err(Error::DimensionMismatch {
+            expected: input.dimensions(),
+            actual: output.dimensions(),
+        });
+    }
+
+    let background = background.map(f64::from);
+    let errors = GrayImage::from_fn(input.width(), input.height(), |x, y| {
+        let observed = composite_over(input.get_pixel(x, y), background);
+        let reconstructed = composite_over(output.get_pixel(x, y), background);
+        let error = (0..3)
+            .map(|i| {
+                let difference = linear_to_srgb(observed[i]) - linear_to_srgb(reconstructed[i]);
+                (difference.abs() * 255.0).round().min(255.0) as u8
+            })
+            .max()
+            .unwrap_or(0);
+        Luma([error])
+    });
+
+    Ok(ReconstructionError::from_errors(errors))
+}
+
+/// 8-bit sRGB view of a linear floating-point image
+///
+/// Background detection, color picking and color deduction work on 8-bit sRGB
+/// images; this view gives them colors that mean the same as colors given with
+/// `--bg` and `--fg`. Values outside 0.0-1.0 are clamped.
+pub fn srgb_preview(image: &Rgba32FImage) -> RgbaImage {
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let pixel = image.get_pixel(x, y);
+        let [r, g, b] = denormalize_color([0, 1, 2].map(|i| linear_to_srgb(pixel[i] as f64)));
+        let alpha = (pixel[3].clamp(0.0, 1.0) * 255.0).round() as u8;
+        Rgba([r, g, b, alpha])
+    })
+}
+
+/// Composite a linear pixel over a linear background color
+///
+/// Negative channels are treated as 0.
+fn composite_over(pixel: &Rgba<f32>, background: [f64; 3]) -> [f64; 3] {
+    let alpha = (pixel[3] as f64).clamp(0.0, 1.0);
+    [0, 1, 2].map(|i| (pixel[i] as f64).max(0.0) * alpha + background[i] * (1.0 - alpha))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Mode;
+
+    #[test]
+    fn test_hdr_glow_above_white() {
+        // A glow twice as bright as white over black, at 25% coverage
+        let image = Rgba32FImage::from_pixel(1, 1, Rgba([0.5, 0.25, 0.0, 1.0]));
+        let options = ProcessOptions::new([0, 0, 0]);
+
+        // Clamped to white, the red channel forces half coverage
+        let clamped = remove_background_hdr(&image, &HdrOptions::new([0.0; 3]), &options).unwrap();
+        let pixel = clamped.get_pixel(0, 0);
+        assert!((pixel[3] - 0.5).abs() < 1e-3, "{:?}", pixel);
+
+        // Allowing values up to 2.0 recovers the bright glow at 25%
+        let hdr = HdrOptions::new([0.0; 3]).max_foreground(2.0);
+        let unclamped = remove_background_hdr(&image, &hdr, &options).unwrap();
+        let pixel = unclamped.get_pixel(0, 0);
+        assert!((pixel[3] - 0.25).abs() < 1e-3, "{:?}", pixel);
+        assert!((pixel[0] - 2.0).abs() < 1e-2, "{:?}", pixel);
+
+        let error = hdr_reconstruction_error(&image, &unclamped, [0.0; 3]).unwrap();
+        assert_eq!(error.stats(0).max, 0);
+    }
+
+    #[test]
+    fn test_hdr_bright_background() {
+        // Red over a background brighter than white
+        let background = [4.0, 4.0, 4.0];
+        let image = Rgba32FImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([4.0, 4.0, 4.0, 1.0])
+            } else {
+                Rgba([2.5, 2.0, 2.0, 1.0])
+            }
+        });
+        let options = ProcessOptions::new([255, 255, 255])
+            .mode(Mode::Strict)
+            .foreground_colors(vec![[255, 0, 0]]);
+        let output = remove_background_hdr(&image, &HdrOptions::new(background), &options).unwrap();
+
+        assert_eq!(output.get_pixel(0, 0)[3], 0.0);
+        let pixel = output.get_pixel(1, 0);
+        assert!((pixel[3] - 0.5).abs() < 1e-3, "{:?}", pixel);
+        assert!((pixel[0] - 1.0).abs() < 1e-3, "{:?}", pixel);
+    }
+
+    #[test]
+    fn test_hdr_rejects_background_image() {
+        let image = Rgba32FImage::from_pixel(2, 2, Rgba([0.5, 0.5, 0.5, 1.0]));
+        let plate = crate::background::NormalizedRgbImage::new(2, 2);
+        let options = ProcessOptions::new([0, 0, 0]).background_image(plate);
+        assert!(matches!(
+            remove_background_hdr(&image, &HdrOptions::new([0.0; 3]), &options),
+            Err(Error::HdrBackgroundImageUnsupported)
+        ));
+    }
+
+    #[test]
+    fn test_hdr_options_from_normalized_background() {
+        let color = [0x1234 as f64 / 65535.0; 3];
+        let options = ProcessOptions::new([0, 0, 0]).normalized_background_color(color);
+        let hdr = HdrOptions::from_options(&options);
+        assert_eq!(hdr.background, [srgb_to_linear(color[0]) as f32; 3]);
+        assert_ne!(
+            hdr.background,
+            HdrOptions::from_srgb(options.background_color).background
+        );
+    }
+
+    #[test]
+    fn test_hdr_options_validation() {
+        assert!(HdrOptions::new([0.0, 0.5, 8.0]).validate().is_ok());
+        assert!(matches!(
+            HdrOptions::new([f32::NAN, 0.0, 0.0]).validate(),
+            Err(Error::InvalidLinearColor(_))
+        ));
+        assert!(matches!(
+            HdrOptions::new([0.0; 3]).max_foreground(0.5).validate(),
+            Err(Error::InvalidMaxForeground(_))
+        ));
+        assert_eq!(
+            HdrOptions::from_srgb([255, 0, 188]).srgb_background(),
+            [255, 0, 188]
+        );
+    }
+
+    #[test]
+    fn test_srgb_preview() {
+        let image = Rgba32FImage::from_pixel(1, 1, Rgba([0.5, 2.0, -1.0, 0.5]));
+        assert_eq!(
+            srgb_preview(&image).get_pixel(0, 0),
+            &Rgba([188, 255, 0, 128])
+        );
+    }
+}
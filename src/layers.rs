@@ -0,0 +1,137 @@
+use crate::color::{Color, denormalize_color};
+use crate::composite_pixel_in_blend_space;
+use crate::error::{Error, Result};
+use crate::options::ProcessOptions;
+use crate::progress::{NoopObserver, ProgressObserver};
+use crate::unmix_pixels;
+use crate::unmixer::unmixer_for_options;
+use image::{Rgba, RgbaImage};
+
+/// Result of removing the background with one layer per foreground color
+#[derive(Debug, Clone)]
+pub struct LayeredImage {
+    /// The flattened image with the background removed
+    pub image: RgbaImage,
+    /// One layer per foreground color, in the order of the foreground colors
+    ///
+    /// Each layer is filled with its foreground color, with an alpha equal to
+    /// the weight of that color in each pixel. Pixels that are not made of any
+    /// foreground color (e.g. glows in non-strict mode) are transparent in
+    /// every layer.
+    pub layers: Vec<RgbaImage>,
+}
+
+/// Remove the background and separate the foreground colors into layers
+///
+/// # Arguments
+/// * `image` - The image to process
+/// * `options` - Processing options, with at least one foreground color
+///
+/// # Returns
+/// The flattened image and one layer per foreground color
+pub fn remove_background_layered(
+    image: &RgbaImage,
+    options: &ProcessOptions,
+) -> Result<LayeredImage> {
+    remove_background_layered_with_observer(image, options, &NoopObserver)
+}
+
+/// Remove the background and separate the foreground colors into layers,
+/// reporting progress to an observer
+pub fn remove_background_layered_with_observer(
+    image: &RgbaImage,
+    options: &ProcessOptions,
+    observer: &dyn ProgressObserver,
+) -> Result<LayeredImage> {
+    options.validate()?;
+    if options.foreground_colors.is_empty() {
+        return Err(Error::LayersRequireForeground);
+    }
+
+    let unmixer = unmixer_for_options(options);
+    let results = unmix_pixels(
+        image,
+        options.background(),
+        observer,
+        |pixel, background| {
+            let background = denormalize_color(background);
+            // Pre-composite translucent pixels over background to get opaque color
+            let observed =
+                composite_pixel_in_blend_space(pixel, background, unmixer.assumed_blend_space());
+            unmixer.unmix_with_weights(observed, background)
+        },
+    )?;
+
+    let (width, height) = image.dimensions();
+    let pixel_at = |x: u32, y: u32| &results[(y * width + x) as usize];
+
+    let flattened = RgbaImage::from_fn(width, height, |x, y| Rgba(pixel_at(x, y).0));
+    let layers = options
+        .foreground_colors
+        .iter()
+        .enumerate()
+        .map(|(i, &color)| {
+            RgbaImage::from_fn(width, height, |x, y| {
+                let weight = pixel_at(x, y).1.get(i).copied().unwrap_or(0.0);
+                layer_pixel(color, weight)
+            })
+        })
+        .collect();
+
+    Ok(LayeredImage {
+        image: flattened,
+        layers,
+    })
+}
+
+/// Build a layer pixel from a foreground color and its weight
+fn layer_pixel(color: Color, weight: f64) -> Rgba<u8> {
+    let alpha = (weight.clamp(0.0, 1.0) * 255.0).round() as u8;
+    if alpha == 0 {
+        Rgba([0, 0, 0, 0])
+    } else {
+        Rgba([color[0], color[1], color[2], alpha])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Mode;
+
+    #[test]
+    fn test_layers_require_foreground() {
+        let img = RgbaImage::new(2, 2);
+        let options = ProcessOptions::new([0, 0, 0]);
+        assert!(matches!(
+            remove_background_layered(&img, &options),
+            Err(Error::LayersRequireForeground)
+        ));
+    }
+
+    #[test]
+    fn test_layers_split_colors() {
+        // Red, green and 50% green pixels on black
+        let mut img = RgbaImage::new(3, 1);
+        img.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        img.put_pixel(1, 0, Rgba([0, 255, 0, 255]));
+        img.put_pixel(2, 0, Rgba([0, 128, 0, 255]));
+
+        let options = ProcessOptions::new([0, 0, 0])
+            .mode(Mode::Strict)
+            .foreground_colors(vec![[255, 0, 0], [0, 255, 0]]);
+        let result = remove_background_layered(&img, &options).unwrap();
+
+        assert_eq!(result.layers.len(), 2);
+        assert_eq!(result.image.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+
+        let red = &result.layers[0];
+        assert_eq!(red.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(red.get_pixel(1, 0)[3], 0);
+
+        let green = &result.layers[1];
+        assert_eq!(green.get_pixel(0, 0)[3], 0);
+        assert_eq!(green.get_pixel(1, 0), &Rgba([0, 255, 0, 255]));
+        assert_eq!(green.get_pixel(2, 0), &Rgba([0, 255, 0, 128]));
+    }
+}
@@ -1,161 +1,428 @@
 pub mod background;
 pub mod color;
 pub mod deduce;
+pub mod error;
+pub mod gradient;
+pub mod hdr;
+pub mod layers;
+pub mod metadata;
+pub mod options;
+pub mod pair;
+pub mod palette;
+pub mod pick;
+pub mod pixel;
+pub mod progress;
+pub mod quality;
 pub mod unmix;
+pub mod unmixer;
 
-use anyhow::{Context, Result};
-use image::{ImageBuffer, Rgba};
-use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
+pub use error::{Error, Result};
+pub use layers::{
+    LayeredImage, remove_background_layered, remove_background_layered_with_observer,
+};
+pub use metadata::ImageMetadata;
+pub use options::{Mode, ProcessOptions};
+pub use pair::{PairMatte, remove_background_pair, remove_background_pair_with_observer};
+pub use pixel::{Rgba16Image, UnmixPixel};
+pub use progress::{NoopObserver, ProgressObserver};
+pub use quality::{ErrorStats, ReconstructionError, ResidualStats};
+pub use unmixer::PixelUnmixer;
+
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::metadata::Orientation;
+use image::{
+    DynamicImage, EncodableLayout, ImageBuffer, ImageDecoder, ImageEncoder, ImageError,
+    ImageFormat, ImageReader, ImageResult, PixelWithColorType, Rgba, RgbaImage,
+};
 use rayon::prelude::*;
+use std::io::Cursor;
 use std::path::Path;
 
-use crate::color::{Color, NormalizedColor, denormalize_color, normalize_color};
-use crate::unmix::{
-    DEFAULT_COLOR_CLOSENESS_THRESHOLD, compute_result_color, is_color_close_to_foreground,
-    unmix_colors,
-};
-use nalgebra::Vector3;
+use crate::background::Background;
+use crate::color::{BlendSpace, Color, NormalizedColor, denormalize_color, normalize_color};
+use crate::hdr::{HdrOptions, remove_background_hdr_with_observer};
+use crate::unmixer::unmixer_for_options;
 
 /// Process an image to remove its background
+///
+/// Loads the input image, removes its background with [`remove_background`],
+/// and saves the result to the output path with the ICC profile and metadata
+/// of the input (see [`ImageMetadata`]). The precision depends on the input
+/// and the output format (see [`Pipeline`]): floating-point inputs saved as
+/// OpenEXR go through [`hdr::remove_background_hdr`] with the background color
+/// decoded to linear light.
 pub fn process_image<P: AsRef<Path>>(
     input_path: P,
     output_path: P,
-    foreground_colors: Vec<Color>,
-    background_color: Color,
-    strict_mode: bool,
-    threshold: Option<f64>,
+    options: &ProcessOptions,
+) -> Result<()> {
+    process_image_with_observer(input_path, output_path, options, &NoopObserver)
+}
+
+/// Process an image to remove its background, reporting progress to an observer
+pub fn process_image_with_observer<P: AsRef<Path>>(
+    input_path: P,
+    output_path: P,
+    options: &ProcessOptions,
+    observer: &dyn ProgressObserver,
 ) -> Result<()> {
     let input_path = input_path.as_ref();
     let output_path = output_path.as_ref();
 
-    // Loading progress
-    let load_progress = ProgressBar::new_spinner();
-    load_progress.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} Loading image...")
-            .expect("Failed to create progress bar style"),
-    );
-    load_progress.enable_steady_tick(std::time::Duration::from_millis(100));
-
-    // Load image
-    let img = image::open(input_path)
-        .with_context(|| format!("Failed to open input image: {}", input_path.display()))?;
-
-    let rgba = img.to_rgba8();
-    let (width, height) = rgba.dimensions();
-    load_progress.finish_and_clear();
-    println!(
-        "✓ Loaded {} ({}x{} pixels)",
-        input_path.file_name().unwrap_or_default().to_string_lossy(),
-        width,
-        height
-    );
-
-    // Normalize colors for processing
-    let fg_normalized: Vec<NormalizedColor> = foreground_colors
-        .iter()
-        .map(|&color| normalize_color(color))
-        .collect();
+    options.validate()?;
 
-    let bg_normalized = normalize_color(background_color);
+    let (img, metadata) = load_image_with_metadata(input_path, observer)?;
+    match Pipeline::for_image(&img, output_path) {
+        Pipeline::Float => {
+            let hdr = HdrOptions::from_options(options);
+            let output_img =
+                remove_background_hdr_with_observer(&img.to_rgba32f(), &hdr, options, observer)?;
+            save_image_with_metadata(&output_img, output_path, &metadata, observer)?;
+        }
+        Pipeline::Rgba16 => {
+            let output_img = remove_background_unchecked(&img.to_rgba16(), options, observer)?;
+            save_image_with_metadata(&output_img, output_path, &metadata, observer)?;
+        }
+        Pipeline::Rgba8 => {
+            let output_img = remove_background_unchecked(&to_srgb_rgba8(&img), options, observer)?;
+            save_image_with_metadata(&output_img, output_path, &metadata, observer)?;
+        }
+    }
 
-    // Setup progress bar
-    let progress = create_progress_bar((width * height) as u64)?;
+    Ok(())
+}
 
-    // Process pixels in parallel
-    let pixels: Vec<_> = rgba.pixels().collect();
-    let processed_pixels: Vec<[u8; 4]> = if !strict_mode && foreground_colors.is_empty() {
-        // Non-strict mode without foreground colors
-        pixels
-            .par_iter()
-            .progress_with(progress.clone())
-            .map(|pixel| {
-                // Pre-composite translucent pixels over background to get opaque color
-                let observed = composite_pixel_over_background(pixel, background_color);
-                process_pixel_non_strict_no_fg(observed, bg_normalized)
-            })
-            .collect()
-    } else if !strict_mode {
-        // Non-strict mode WITH foreground colors
-        let color_threshold = threshold.unwrap_or(DEFAULT_COLOR_CLOSENESS_THRESHOLD);
-        pixels
-            .par_iter()
-            .progress_with(progress.clone())
-            .map(|pixel| {
-                // Pre-composite translucent pixels over background to get opaque color
-                let observed = composite_pixel_over_background(pixel, background_color);
-                process_pixel_non_strict_with_fg(
-                    observed,
-                    &fg_normalized,
-                    bg_normalized,
-                    color_threshold,
-                )
-            })
-            .collect()
+/// Precision an image is processed and saved at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pipeline {
+    /// 8 bits per channel in sRGB, with floating-point inputs encoded to sRGB
+    /// first (see [`to_srgb_rgba8`])
+    Rgba8,
+    /// 16 bits per channel in sRGB
+    Rgba16,
+    /// Floating-point channels in linear light, with
+    /// [`hdr::remove_background_hdr`]
+    Float,
+}
+
+impl Pipeline {
+    /// Pick the pipeline for an image saved to an output path
+    ///
+    /// 16-bit and floating-point images keep their precision when the output
+    /// format can store it.
+    pub fn for_image(img: &DynamicImage, output_path: &Path) -> Self {
+        if has_float_channels(img) && supports_float_output(output_path) {
+            Pipeline::Float
+        } else if has_16_bit_channels(img) && supports_16_bit_output(output_path) {
+            Pipeline::Rgba16
+        } else {
+            Pipeline::Rgba8
+        }
+    }
+
+    /// Bits per channel of the output image
+    pub fn bit_depth(self) -> u8 {
+        match self {
+            Pipeline::Rgba8 => 8,
+            Pipeline::Rgba16 => 16,
+            Pipeline::Float => 32,
+        }
+    }
+}
+
+/// Convert an image to 8-bit sRGB RGBA
+///
+/// Floating-point images are in linear light and are encoded to sRGB (see
+/// [`hdr::srgb_preview`]) instead of being scaled as they are.
+pub fn to_srgb_rgba8(img: &DynamicImage) -> RgbaImage {
+    if has_float_channels(img) {
+        hdr::srgb_preview(&img.to_rgba32f())
     } else {
-        // Strict mode
-        pixels
-            .par_iter()
-            .progress_with(progress.clone())
-            .map(|pixel| {
-                // Pre-composite translucent pixels over background to get opaque color
-                let observed = composite_pixel_over_background(pixel, background_color);
-                let unmix_result = unmix_colors(observed, &fg_normalized, bg_normalized);
-                let (result_color, alpha) = compute_result_color(&unmix_result, &fg_normalized);
-
-                let final_color = denormalize_color(result_color);
-                [
-                    final_color[0],
-                    final_color[1],
-                    final_color[2],
-                    (alpha * 255.0).round() as u8,
-                ]
-            })
-            .collect()
+        img.to_rgba8()
+    }
+}
+
+/// Check if an image has 16 bits per channel
+pub fn has_16_bit_channels(img: &DynamicImage) -> bool {
+    matches!(
+        img,
+        DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_)
+    )
+}
+
+/// Check if an image has floating-point channels, like OpenEXR and Radiance
+/// HDR images
+pub fn has_float_channels(img: &DynamicImage) -> bool {
+    matches!(
+        img,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    )
+}
+
+/// Check if the format of an output path can store floating-point RGBA images
+///
+/// Only OpenEXR can; Radiance HDR has no alpha channel.
+pub fn supports_float_output(path: &Path) -> bool {
+    matches!(ImageFormat::from_path(path), Ok(ImageFormat::OpenExr))
+}
+
+/// Check if the format of an output path can store 16-bit RGBA images
+///
+/// The format is determined by the file extension.
+pub fn supports_16_bit_output(path: &Path) -> bool {
+    matches!(
+        ImageFormat::from_path(path),
+        Ok(ImageFormat::Png | ImageFormat::Tiff)
+    )
+}
+
+/// Load an image from disk, reporting progress to an observer
+///
+/// The image is rotated and flipped upright according to its EXIF orientation.
+pub fn load_image(path: &Path, observer: &dyn ProgressObserver) -> Result<DynamicImage> {
+    load_image_with_metadata(path, observer).map(|(img, _)| img)
+}
+
+/// Load an image and its metadata from disk, reporting progress to an observer
+///
+/// The image is rotated and flipped upright according to its EXIF orientation,
+/// and the orientation in the returned EXIF data is reset accordingly.
+pub fn load_image_with_metadata(
+    path: &Path,
+    observer: &dyn ProgressObserver,
+) -> Result<(DynamicImage, ImageMetadata)> {
+    observer.loading(path);
+    let decode_error = |source| Error::ImageDecode {
+        path: path.to_path_buf(),
+        source,
     };
 
-    progress.finish_with_message(format!("✓ Processed {} pixels", width * height));
+    let bytes = std::fs::read(path).map_err(|e| decode_error(ImageError::IoError(e)))?;
+    let reader = match ImageFormat::from_path(path) {
+        Ok(format) => ImageReader::with_format(Cursor::new(&bytes[..]), format),
+        Err(_) => ImageReader::new(Cursor::new(&bytes[..]))
+            .with_guessed_format()
+            .map_err(|e| decode_error(ImageError::IoError(e)))?,
+    };
+    let mut decoder = reader.into_decoder().map_err(decode_error)?;
 
-    // Create and save output image
-    let save_progress = ProgressBar::new_spinner();
-    save_progress.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} Saving image...")
-            .expect("Failed to create progress bar style"),
-    );
-    save_progress.enable_steady_tick(std::time::Duration::from_millis(100));
+    // Metadata is best effort: an unreadable profile doesn't fail the load
+    let mut metadata = ImageMetadata {
+        icc_profile: decoder.icc_profile().ok().flatten(),
+        exif: decoder.exif_metadata().ok().flatten(),
+        ..ImageMetadata::default()
+    };
+    metadata.read_from_file_contents(&bytes);
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
 
-    let mut output_img = ImageBuffer::<Rgba<u8>, Vec<u8>>::new(width, height);
-    for (i, pixel) in output_img.pixels_mut().enumerate() {
-        *pixel = Rgba(processed_pixels[i]);
+    let mut img = DynamicImage::from_decoder(decoder).map_err(decode_error)?;
+    img.apply_orientation(orientation);
+    if let Some(exif) = &mut metadata.exif {
+        let _ = Orientation::remove_from_exif_chunk(exif);
     }
+    observer.loaded(path, img.width(), img.height());
 
-    output_img
-        .save(output_path)
-        .with_context(|| format!("Failed to save output image: {}", output_path.display()))?;
+    Ok((img, metadata))
+}
 
-    save_progress.finish_and_clear();
-    println!(
-        "✓ Saved to {}",
-        output_path
-            .file_name()
-            .unwrap_or_default()
-            .to_string_lossy()
-    );
+/// Save an image to disk, reporting progress to an observer
+///
+/// The format is determined by the file extension. Saving a 16-bit image
+/// fails for formats that cannot store it (see [`supports_16_bit_output`]).
+pub fn save_image<P>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    path: &Path,
+    observer: &dyn ProgressObserver,
+) -> Result<()>
+where
+    P: PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    save_image_with_metadata(image, path, &ImageMetadata::default(), observer)
+}
+
+/// Save an image and metadata to disk, reporting progress to an observer
+///
+/// Formats that cannot store some of the metadata are saved without it (see
+/// [`ImageMetadata`]).
+pub fn save_image_with_metadata<P>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    path: &Path,
+    metadata: &ImageMetadata,
+    observer: &dyn ProgressObserver,
+) -> Result<()>
+where
+    P: PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    observer.saving(path);
+    encode_with_metadata(image, path, metadata).map_err(|source| Error::ImageEncode {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    observer.saved(path);
 
     Ok(())
 }
 
-/// Create a progress bar with consistent styling
-fn create_progress_bar(total: u64) -> Result<ProgressBar> {
-    let progress = ProgressBar::new(total);
-    progress.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} pixels ({percent}%)")?
-            .progress_chars("#>-")
-    );
-    progress.set_message("Processing pixels...");
-    Ok(progress)
+/// Encode an image to a file, with the metadata its format supports
+fn encode_with_metadata<P>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    path: &Path,
+    metadata: &ImageMetadata,
+) -> ImageResult<()>
+where
+    P: PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    let format = ImageFormat::from_path(path)?;
+    if metadata.is_empty() || !matches!(format, ImageFormat::Png | ImageFormat::WebP) {
+        return image.save(path);
+    }
+
+    let (width, height) = image.dimensions();
+    let mut bytes = Vec::new();
+    if format == ImageFormat::Png {
+        let mut encoder = PngEncoder::new(&mut bytes);
+        set_encoder_metadata(&mut encoder, metadata)?;
+        encoder.write_image(image.as_bytes(), width, height, P::COLOR_TYPE)?;
+        metadata.write_to_png(&mut bytes);
+    } else {
+        let mut encoder = WebPEncoder::new_lossless(&mut bytes);
+        set_encoder_metadata(&mut encoder, metadata)?;
+        encoder.write_image(image.as_bytes(), width, height, P::COLOR_TYPE)?;
+    }
+    std::fs::write(path, bytes)?;
+
+    Ok(())
+}
+
+/// Pass the ICC profile and EXIF data to an encoder
+fn set_encoder_metadata(
+    encoder: &mut impl ImageEncoder,
+    metadata: &ImageMetadata,
+) -> ImageResult<()> {
+    if let Some(icc_profile) = &metadata.icc_profile {
+        encoder
+            .set_icc_profile(icc_profile.clone())
+            .map_err(ImageError::Unsupported)?;
+    }
+    if let Some(exif) = &metadata.exif {
+        encoder
+            .set_exif_metadata(exif.clone())
+            .map_err(ImageError::Unsupported)?;
+    }
+
+    Ok(())
+}
+
+/// Remove the background from an in-memory image
+///
+/// This is the core of [`process_image`] without any file I/O, printing or
+/// progress reporting.
+///
+/// Both 8-bit ([`RgbaImage`](image::RgbaImage)) and 16-bit ([`Rgba16Image`])
+/// images are supported. 16-bit images are unmixed without rounding to 8 bits.
+///
+/// # Arguments
+/// * `image` - The image to process
+/// * `options` - Processing options (validated before processing)
+///
+/// # Returns
+/// A new image of the same size and bit depth with the background made transparent
+pub fn remove_background<P: UnmixPixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    options: &ProcessOptions,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    remove_background_with_observer(image, options, &NoopObserver)
+}
+
+/// Remove the background from an in-memory image, reporting progress to an observer
+pub fn remove_background_with_observer<P: UnmixPixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    options: &ProcessOptions,
+    observer: &dyn ProgressObserver,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    options.validate()?;
+    remove_background_unchecked(image, options, observer)
+}
+
+/// Remove the background from an in-memory image with already validated options
+fn remove_background_unchecked<P: UnmixPixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    options: &ProcessOptions,
+    observer: &dyn ProgressObserver,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    let unmixer = unmixer_for_options(options);
+    remove_background_with_unmixer(image, options.background(), unmixer.as_ref(), observer)
+}
+
+/// Remove the background from an in-memory image with a custom unmixing strategy
+///
+/// Translucent input pixels are first composited over their background color,
+/// then every pixel is unmixed in parallel by `unmixer`.
+///
+/// # Arguments
+/// * `image` - The image to process
+/// * `background` - The background color of every pixel (a [`Color`] converts into a solid background)
+/// * `unmixer` - The strategy turning each observed color into an RGBA pixel
+/// * `observer` - Receives pixel processing events
+///
+/// # Returns
+/// A new image of the same size with the background made transparent, or
+/// [`Error::DimensionMismatch`] if a per-pixel background has another size
+pub fn remove_background_with_unmixer<P: UnmixPixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    background: Background<'_>,
+    unmixer: &dyn PixelUnmixer,
+    observer: &dyn ProgressObserver,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    let (width, height) = image.dimensions();
+    let processed_pixels = unmix_pixels(image, background, observer, |pixel, bg| {
+        pixel.unmix(bg, unmixer)
+    })?;
+
+    Ok(ImageBuffer::from_fn(width, height, |x, y| {
+        processed_pixels[(y * width + x) as usize]
+    }))
+}
+
+/// Unmix every pixel of an image in parallel
+///
+/// Each pixel is passed to `unmix` with its background color, with channels
+/// from 0.0 to 1.0. Results are returned in row-major order.
+pub(crate) fn unmix_pixels<P, T, F>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    background: Background<'_>,
+    observer: &dyn ProgressObserver,
+    unmix: F,
+) -> Result<Vec<T>>
+where
+    P: UnmixPixel,
+    T: Send,
+    F: Fn(&P, NormalizedColor) -> T + Sync,
+{
+    background.check_dimensions(image.dimensions())?;
+
+    let (width, height) = image.dimensions();
+    let total_pixels = width as u64 * height as u64;
+    observer.processing_started(total_pixels);
+
+    // Process pixels in parallel
+    let pixels: Vec<_> = image.enumerate_pixels().collect();
+    let processed_pixels = pixels
+        .par_iter()
+        .inspect(|_| observer.pixels_processed(1))
+        .map(|&(x, y, pixel)| unmix(pixel, background.normalized_color_at(x, y)))
+        .collect();
+
+    observer.processing_finished(total_pixels);
+
+    Ok(processed_pixels)
 }
 
 /// Composite a pixel over a background color to handle existing alpha channels
@@ -165,7 +432,7 @@ fn create_progress_bar(total: u64) -> Result<ProgressBar> {
 /// correctly process images that already have transparency.
 ///
 /// Formula: result = foreground * alpha + background * (1 - alpha)
-fn composite_pixel_over_background(pixel: &Rgba<u8>, background: Color) -> Color {
+pub(crate) fn composite_pixel_over_background(pixel: &Rgba<u8>, background: Color) -> Color {
     let alpha = pixel[3] as f64 / 255.0;
 
     if alpha >= 1.0 {
@@ -192,6 +459,28 @@ fn composite_pixel_over_background(pixel: &Rgba<u8>, background: Color) -> Color
     }
 }
 
+/// Composite a pixel over a background color in a blend space
+///
+/// Like [`composite_pixel_over_background`], but blends linear light values
+/// for [`BlendSpace::Linear`].
+pub(crate) fn composite_pixel_in_blend_space(
+    pixel: &Rgba<u8>,
+    background: Color,
+    blend_space: BlendSpace,
+) -> Color {
+    if blend_space == BlendSpace::Srgb || pixel[3] == 255 {
+        return composite_pixel_over_background(pixel, background);
+    }
+
+    let alpha = pixel[3] as f64 / 255.0;
+    let foreground = blend_space.decode(normalize_color([pixel[0], pixel[1], pixel[2]]));
+    let background = blend_space.decode(normalize_color(background));
+    denormalize_color(
+        blend_space
+            .encode([0, 1, 2].map(|i| foreground[i] * alpha + background[i] * (1.0 - alpha))),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -244,219 +533,62 @@ mod tests {
         // Approximately 25% of the color
         assert_eq!(result, [50, 25, 13]);
     }
-}
 
-/// Find the minimum alpha value that produces a valid foreground color
-///
-/// Given an observed color and background, this function finds the minimum alpha
-/// value (between 0 and 1) such that there exists a valid foreground color
-/// (all RGB components in [0, 1]) that satisfies:
-/// observed = alpha * foreground + (1 - alpha) * background
-///
-/// Returns (foreground_color, alpha) or None if no valid solution exists
-fn find_minimum_alpha_for_color(
-    obs_norm: NormalizedColor,
-    background: NormalizedColor,
-) -> Option<(NormalizedColor, f64)> {
-    let mut best_alpha = 1.0;
-    let mut best_fg = obs_norm;
-
-    // For truly minimal alpha, we need to consider different foreground colors.
-    // The optimal foreground often has components at the extremes (0 or 1).
-    // We'll try all 8 combinations of extreme values, plus the computed values.
-
-    // First, let's compute the minimum alpha needed for each channel independently
-    // For each channel i: observed[i] = alpha * fg[i] + (1 - alpha) * bg[i]
-    // If fg[i] = 0: alpha = (bg[i] - observed[i]) / bg[i] (if bg[i] != 0)
-    // If fg[i] = 1: alpha = (observed[i] - bg[i]) / (1 - bg[i]) (if bg[i] != 1)
-
-    // Try all combinations of extreme foreground values (0 or 1 for each channel)
-    for r_extreme in &[0.0, 1.0] {
-        for g_extreme in &[0.0, 1.0] {
-            for b_extreme in &[0.0, 1.0] {
-                let fg_candidate = [*r_extreme, *g_extreme, *b_extreme];
-
-                // Calculate required alpha for this foreground color
-                // observed = alpha * foreground + (1 - alpha) * background
-                // alpha = (observed - background) / (foreground - background)
-
-                let mut alpha_needed = 0.0;
-                let mut valid = true;
-
-                let mut first_alpha_set = false;
-
-                for i in 0..3 {
-                    let denom = fg_candidate[i] - background[i];
-                    if denom.abs() < 1e-10 {
-                        // fg[i] ≈ bg[i], check if observed[i] ≈ bg[i] too
-                        if (obs_norm[i] - background[i]).abs() > 1e-10 {
-                            valid = false;
-                            break;
-                        }
-                        // Any alpha works for this channel, continue
-                    } else {
-                        let alpha_i = (obs_norm[i] - background[i]) / denom;
-                        if !first_alpha_set {
-                            alpha_needed = alpha_i;
-                            first_alpha_set = true;
-                        } else if (alpha_i - alpha_needed).abs() > 1e-10 {
-                            // Different channels require different alphas - invalid
-                            valid = false;
-                            break;
-                        }
-                    }
-                }
-
-                if valid
-                    && first_alpha_set
-                    && alpha_needed > 0.0
-                    && alpha_needed <= 1.0
-                    && alpha_needed < best_alpha
-                {
-                    // Verify the solution
-                    let mut reconstructed_valid = true;
-                    for i in 0..3 {
-                        let reconstructed =
-                            alpha_needed * fg_candidate[i] + (1.0 - alpha_needed) * background[i];
-                        if (reconstructed - obs_norm[i]).abs() > 1e-10 {
-                            reconstructed_valid = false;
-                            break;
-                        }
-                    }
-
-                    if reconstructed_valid {
-                        best_alpha = alpha_needed;
-                        best_fg = fg_candidate;
-                    }
-                }
-            }
-        }
-    }
-
-    // Also try the direct computation approach with fine-grained alpha search
-    for alpha_int in 1..=1000 {
-        let alpha = alpha_int as f64 / 1000.0;
-
-        if alpha >= best_alpha {
-            break; // No point checking higher alphas
-        }
-
-        // Calculate the required foreground color for this alpha
-        let fg_r = (obs_norm[0] - (1.0 - alpha) * background[0]) / alpha;
-        let fg_g = (obs_norm[1] - (1.0 - alpha) * background[1]) / alpha;
-        let fg_b = (obs_norm[2] - (1.0 - alpha) * background[2]) / alpha;
-
-        // Check if this foreground color is valid (all components in [0, 1])
-        if (0.0..=1.0).contains(&fg_r) && (0.0..=1.0).contains(&fg_g) && (0.0..=1.0).contains(&fg_b)
-        {
-            best_alpha = alpha;
-            best_fg = [fg_r, fg_g, fg_b];
-            break; // This is the minimum alpha with direct computation
-        }
-    }
-
-    Some((best_fg, best_alpha))
-}
-
-/// Process a pixel in non-strict mode without foreground colors
-///
-/// In this mode, we find the optimal foreground color and alpha that produces
-/// the observed color when alpha-blended with the background.
-///
-/// The algorithm:
-/// 1. Searches for the minimum alpha value that allows a valid foreground color
-/// 2. A valid foreground color has all RGB components in [0, 1] range
-/// 3. Always produces perfect reconstruction of the original image
-fn process_pixel_non_strict_no_fg(observed: Color, background: NormalizedColor) -> [u8; 4] {
-    let obs_norm = normalize_color(observed);
-
-    // If the observed color is exactly the background, it's fully transparent
-    if (obs_norm[0] - background[0]).abs() < 1e-6
-        && (obs_norm[1] - background[1]).abs() < 1e-6
-        && (obs_norm[2] - background[2]).abs() < 1e-6
-    {
-        return [0, 0, 0, 0];
+    #[test]
+    fn test_composite_pixel_in_linear_light() {
+        // 50% white over black is 0.5 in linear light, which encodes to 188
+        let pixel = Rgba([255, 255, 255, 128]);
+        let result = composite_pixel_in_blend_space(&pixel, [0, 0, 0], BlendSpace::Linear);
+        assert_eq!(result, [188, 188, 188]);
+
+        let result = composite_pixel_in_blend_space(&pixel, [0, 0, 0], BlendSpace::Srgb);
+        assert_eq!(result, [128, 128, 128]);
+
+        let opaque = Rgba([10, 20, 30, 255]);
+        let result = composite_pixel_in_blend_space(&opaque, [0, 0, 0], BlendSpace::Linear);
+        assert_eq!(result, [10, 20, 30]);
     }
 
-    // Find the optimal alpha and foreground color
-    let (best_fg, best_alpha) = find_minimum_alpha_for_color(obs_norm, background).unwrap_or({
-        // If we didn't find a valid solution with alpha <= 1.0, something is wrong
-        // Fall back to using alpha = 1.0
-        (obs_norm, 1.0)
-    });
-
-    let final_color = denormalize_color(best_fg);
-    [
-        final_color[0],
-        final_color[1],
-        final_color[2],
-        (best_alpha * 255.0).round() as u8,
-    ]
-}
-
-/// Process a pixel in non-strict mode with foreground colors
-///
-/// This mode combines two strategies:
-/// 1. For pixels "close enough" to specified foreground colors (within threshold):
-///    - Uses the standard unmixing algorithm optimized for high opacity
-///    - Restricts to the specified foreground colors
-/// 2. For pixels NOT close to any foreground color:
-///    - Allows ANY color to be used
-///    - Finds the minimum alpha that produces a valid foreground color
-///    - Ensures perfect reconstruction
-///
-/// This allows the tool to preserve colors like glows and gradients that aren't
-/// close to the specified foreground colors, while still optimizing for the
-/// specified colors when appropriate.
-fn process_pixel_non_strict_with_fg(
-    observed: Color,
-    foreground_colors: &[NormalizedColor],
-    background: NormalizedColor,
-    threshold: f64,
-) -> [u8; 4] {
-    let obs_norm = normalize_color(observed);
-    let obs_vec = Vector3::new(obs_norm[0] as f64, obs_norm[1] as f64, obs_norm[2] as f64);
-
-    // If the observed color is exactly the background, it's fully transparent
-    if (obs_norm[0] - background[0]).abs() < 1e-6
-        && (obs_norm[1] - background[1]).abs() < 1e-6
-        && (obs_norm[2] - background[2]).abs() < 1e-6
-    {
-        return [0, 0, 0, 0];
+    #[test]
+    fn test_pipeline_for_image() {
+        let rgba8 = DynamicImage::new_rgba8(1, 1);
+        let rgba16 = DynamicImage::new_rgba16(1, 1);
+        let float = DynamicImage::new_rgba32f(1, 1);
+
+        assert_eq!(
+            Pipeline::for_image(&rgba8, Path::new("a.png")),
+            Pipeline::Rgba8
+        );
+        assert_eq!(
+            Pipeline::for_image(&rgba16, Path::new("a.png")),
+            Pipeline::Rgba16
+        );
+        assert_eq!(
+            Pipeline::for_image(&rgba16, Path::new("a.webp")),
+            Pipeline::Rgba8
+        );
+        assert_eq!(
+            Pipeline::for_image(&float, Path::new("a.exr")),
+            Pipeline::Float
+        );
+        assert_eq!(
+            Pipeline::for_image(&float, Path::new("a.tiff")),
+            Pipeline::Rgba8
+        );
+        assert_eq!(Pipeline::Float.bit_depth(), 32);
     }
 
-    // Check if this pixel is close to any foreground color
-    let close_to_fg =
-        is_color_close_to_foreground(obs_vec, foreground_colors, background, threshold);
-
-    if close_to_fg {
-        // Use the standard unmixing algorithm optimized for high opacity
-        let unmix_result = unmix_colors(observed, foreground_colors, background);
-        let (result_color, alpha) = compute_result_color(&unmix_result, foreground_colors);
-        let final_color = denormalize_color(result_color);
-        [
-            final_color[0],
-            final_color[1],
-            final_color[2],
-            (alpha * 255.0).round() as u8,
-        ]
-    } else {
-        // Not close to any foreground color - find ANY color that works with minimal alpha
-        let obs_norm = normalize_color(observed);
-
-        // Find the optimal alpha and foreground color
-        let (best_fg, best_alpha) = find_minimum_alpha_for_color(obs_norm, background).unwrap_or({
-            // If we didn't find a valid solution with alpha <= 1.0, something is wrong
-            // Fall back to using alpha = 1.0
-            (obs_norm, 1.0)
-        });
-
-        let final_color = denormalize_color(best_fg);
-        [
-            final_color[0],
-            final_color[1],
-            final_color[2],
-            (best_alpha * 255.0).round() as u8,
-        ]
+    #[test]
+    fn test_to_srgb_rgba8_encodes_float_images() {
+        // 0.5 in linear light encodes to 188 in sRGB
+        let float = DynamicImage::ImageRgba32F(image::Rgba32FImage::from_pixel(
+            1,
+            1,
+            Rgba([0.5, 0.5, 0.5, 1.0]),
+        ));
+        assert_eq!(
+            *to_srgb_rgba8(&float).get_pixel(0, 0),
+            Rgba([188, 188, 188, 255])
+        );
     }
 }
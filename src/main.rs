@@ -1,14 +1,36 @@
 use anyhow::{Context, Result};
-use clap::Parser;
-use indicatif::{ProgressBar, ProgressStyle};
+use clap::{Parser, ValueEnum};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use bgone::{
-    background::detect_background_color,
-    color::{Color, ForegroundColorSpec, parse_foreground_spec, parse_hex_color},
-    deduce::deduce_unknown_colors,
-    process_image, unmix,
+    ErrorStats, ImageMetadata, Mode, Pipeline, ProcessOptions, ProgressObserver,
+    ReconstructionError, ResidualStats, UnmixPixel,
+    background::{
+        Background, BackgroundDetection, BackgroundDetectionConfig, DEFAULT_BACKGROUND_TOLERANCE,
+        DEFAULT_MIN_BACKGROUND_CONFIDENCE, Edges, NormalizedRgbImage, SampleRegion,
+        detect_background, normalize_image,
+    },
+    color::{
+        BlendSpace, Color, ColorDistance, ForegroundColorSpec, NormalizedColor, denormalize_color,
+        parse_color, parse_color_normalized, parse_foreground_spec,
+    },
+    deduce::deduce_unknown_colors_with_observer,
+    gradient::{GradientModel, fit_background_gradient},
+    has_float_channels,
+    hdr::{HdrOptions, LinearColor, hdr_reconstruction_error, remove_background_hdr_with_observer},
+    load_image, load_image_with_metadata,
+    palette::{Swatch, filter_swatches, read_palette, write_palette},
+    pick::{parse_pixel_position, pick_color, pick_color_normalized},
+    quality::DEFAULT_ERROR_TOLERANCE,
+    remove_background_layered_with_observer, remove_background_pair_with_observer,
+    remove_background_with_observer, save_image, save_image_with_metadata, to_srgb_rgba8, unmix,
+    unmixer::StrictUnmixer,
 };
+use image::{DynamicImage, EncodableLayout, ImageBuffer, PixelWithColorType, Rgba32FImage};
+use serde_json::json;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -18,61 +40,371 @@ use bgone::{
     disable_version_flag = true
 )]
 struct Args {
-    /// Input image path
+    /// Input image path (with --pair, the image over the first background)
     input: PathBuf,
 
-    /// Output image path (optional, defaults to input file with -bgone suffix)
+    /// Output image path (optional, defaults to input file with -bgone suffix).
+    /// With --pair, the image over the second background.
     output: Option<PathBuf>,
 
-    /// Foreground colors in hex format (e.g., f00, ff0000, #ff0000) or 'auto' for unknown.
+    /// Output image path with --pair (optional, defaults to the first input file
+    /// with -bgone suffix)
+    #[arg(requires = "pair")]
+    pair_output: Option<PathBuf>,
+
+    /// Foreground colors as hex (e.g., f00, ff0000, #ff0000), rgb(), hsl() or CSS names,
+    /// 'auto' for unknown, or '@X,Y' to read the color from a pixel of the input image.
     /// Multiple colors can be specified for color unmixing.
-    /// Use 'auto' to let the tool deduce unknown colors (e.g., --fg ff0000 auto auto).
+    /// Use 'auto' to let the tool deduce unknown colors (e.g., --fg ff0000 @120,45 auto).
     /// In non-strict mode, this is optional.
     #[arg(short = 'f', long = "fg", num_args = 1.., value_name = "COLOR")]
     foreground_colors: Vec<String>,
 
-    /// Background color in hex format (e.g., fff, ffffff, #ffffff).
+    /// Read foreground colors from pixels of the input image (e.g., --fg-at 120,45 8,8),
+    /// after the colors given with --fg
+    #[arg(long = "fg-at", num_args = 1.., value_name = "X,Y", value_parser = parse_pixel_position)]
+    foreground_positions: Vec<(u32, u32)>,
+
+    /// Read foreground colors from a palette file: GIMP (.gpl), Adobe Swatch Exchange
+    /// (.ase) or JSON design tokens (.json), after the colors given with --fg and --fg-at
+    #[arg(long = "palette", value_name = "FILE")]
+    palette: Option<PathBuf>,
+
+    /// Only use the palette swatches with these names, in this order
+    /// (case-insensitive, e.g., --swatch "Brand Red" Ink)
+    #[arg(long = "swatch", num_args = 1.., value_name = "NAME", requires = "palette")]
+    swatches: Vec<String>,
+
+    /// Write the foreground colors, including deduced ones, to a palette file
+    /// (.gpl, .ase or .json) to reuse them with --palette
+    #[arg(long = "save-palette", value_name = "FILE")]
+    save_palette: Option<PathBuf>,
+
+    /// Background color as hex (e.g., fff, ffffff, #ffffff), rgb(), hsl() or a CSS name.
     /// If not specified, the background color will be auto-detected.
     #[arg(short = 'b', long = "bg", value_name = "COLOR")]
     background_color: Option<String>,
 
+    /// Read the background color from a pixel of the input image (e.g., --bg-at 0,0)
+    #[arg(long = "bg-at", value_name = "X,Y", value_parser = parse_pixel_position, conflicts_with_all = [
+        "background_color", "bg_tolerance", "bg_sample", "bg_inset", "bg_rect", "bg_sample_interval",
+        "require_confident_bg", "bg_gradient", "bg_image",
+    ])]
+    background_position: Option<(u32, u32)>,
+
+    /// Background color in linear light for floating-point images (EXR or Radiance
+    /// HDR input, EXR output), as R,G,B with channels that may exceed 1.0
+    /// (e.g., --bg-linear 2.5,2.5,2.5)
+    #[arg(long = "bg-linear", value_name = "R,G,B", value_parser = parse_linear_color, conflicts_with_all = [
+        "background_color", "background_position", "bg_tolerance", "bg_sample", "bg_inset", "bg_rect",
+        "bg_sample_interval", "require_confident_bg", "bg_gradient", "bg_image",
+    ])]
+    bg_linear: Option<LinearColor>,
+
+    /// Largest linear value of a foreground channel for floating-point images (default: 1.0).
+    /// Above 1.0, glows brighter than white keep a low alpha instead of becoming opaque.
+    #[arg(long = "max-fg", value_name = "FLOAT")]
+    max_foreground: Option<f32>,
+
+    /// Average the colors read with --bg-at, --fg-at and @X,Y over a square of
+    /// this many pixels on each side, centered on the position (default: 1)
+    #[arg(long = "pick-size", value_name = "PIXELS", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pick_size: u32,
+
+    /// Maximum channel difference (0-255) for edge pixels to count as the same
+    /// color when auto-detecting the background. Raise it for JPEGs and noisy scans.
+    #[arg(long = "bg-tolerance", value_name = "LEVELS", default_value_t = DEFAULT_BACKGROUND_TOLERANCE, conflicts_with = "background_color")]
+    bg_tolerance: u8,
+
+    /// Parts of the image border sampled to auto-detect the background, comma-separated:
+    /// top, right, bottom, left, edges (all four) and corners (e.g., --bg-sample left,right,bottom).
+    /// Default: edges,corners
+    #[arg(long = "bg-sample", value_enum, value_delimiter = ',', value_name = "PARTS", conflicts_with_all = ["background_color", "bg_gradient", "bg_image"])]
+    bg_sample: Vec<BgSamplePart>,
+
+    /// Skip this many pixels from every side of the image when sampling the border,
+    /// e.g. to ignore a colored frame
+    #[arg(long = "bg-inset", value_name = "PIXELS", default_value_t = 0, conflicts_with_all = ["background_color", "bg_gradient", "bg_image"])]
+    bg_inset: u32,
+
+    /// Sample a rectangle instead of the image border to auto-detect the background,
+    /// given as X,Y,WIDTH,HEIGHT (e.g., --bg-rect 0,40,200,100)
+    #[arg(long = "bg-rect", value_name = "X,Y,W,H", value_parser = parse_rect, conflicts_with_all = ["background_color", "bg_gradient", "bg_image", "bg_sample", "bg_inset"])]
+    bg_rect: Option<[u32; 4]>,
+
+    /// Distance in pixels between background samples (default: 10)
+    #[arg(long = "bg-sample-interval", value_name = "PIXELS", value_parser = clap::value_parser!(u32).range(1..), conflicts_with_all = ["background_color", "bg_gradient", "bg_image"])]
+    bg_sample_interval: Option<u32>,
+
+    /// Fail instead of warning when the auto-detected background color doesn't
+    /// dominate the image border (e.g. for photos without a solid background).
+    #[arg(long = "require-confident-bg", conflicts_with = "background_color")]
+    require_confident_bg: bool,
+
+    /// Fit a gradient to the image border and remove it instead of a solid color.
+    /// 'linear' for straight gradients, 'bilinear' for four-corner gradients,
+    /// 'quadratic' for soft radial gradients.
+    #[arg(long = "bg-gradient", value_enum, value_name = "MODEL", conflicts_with_all = ["background_color", "require_confident_bg"])]
+    bg_gradient: Option<BgGradient>,
+
+    /// Image of the empty background (a "clean plate"), same size as the input.
+    /// Each pixel is unmixed against its own background pixel, which handles
+    /// textured backgrounds. Its alpha channel is ignored.
+    #[arg(long = "bg-image", value_name = "PATH", conflicts_with_all = ["background_color", "bg_gradient", "require_confident_bg"])]
+    bg_image: Option<PathBuf>,
+
     /// Strict mode: requires --fg and restricts unmixing to specified colors only.
     /// Without this flag, the tool can use any color for reconstruction.
     #[arg(short = 's', long = "strict")]
     strict: bool,
 
-    /// Color similarity threshold (0.0-1.0).
+    /// Color similarity threshold (0.0-1.0), in the units of --distance.
     /// In non-strict mode with --fg: pixels within this threshold of a foreground color will use that color.
     /// In strict mode with 'auto': colors within this threshold are considered similar during deduction.
     /// Default: 0.05 (5%)
     #[arg(short = 't', long = "threshold", value_name = "FLOAT")]
     threshold: Option<f64>,
 
+    /// How color similarity is measured for --threshold: 'rgb' (Euclidean sRGB distance,
+    /// sqrt(3) from black to white) or 'oklab' (perceptual OKLab distance, 1.0 from black
+    /// to white, about 0.02 is a just noticeable difference)
+    #[arg(long = "distance", value_enum, value_name = "METRIC", default_value_t = Distance::Rgb)]
+    distance: Distance,
+
+    /// Color space the image was blended in: 'srgb' for browsers and most 2D editors,
+    /// 'linear' for 3D renderers and linear-light compositors. Colors are decoded to
+    /// this space before unmixing and encoded back afterwards.
+    /// Default: srgb, or linear for floating-point images saved as EXR, which are
+    /// always unmixed in linear light.
+    #[arg(long = "blend-space", value_enum, value_name = "SPACE")]
+    blend_space: Option<Blend>,
+
+    /// Also write one image per foreground color, filled with that color and
+    /// with alpha set to its weight in each pixel (e.g., output-layer-1-ff0000.png).
+    /// Requires --fg.
+    #[arg(long = "layers")]
+    layers: bool,
+
+    /// Write an image of the per-pixel reconstruction error (the difference between
+    /// the input and the output composited back over the background).
+    #[arg(long = "error-map", value_name = "PATH")]
+    error_map: Option<PathBuf>,
+
+    /// Style of the error map: 'heatmap' (black, then blue to red) or 'gray'
+    #[arg(long = "error-map-style", value_enum, default_value_t = ErrorMapStyle::Heatmap)]
+    error_map_style: ErrorMapStyle,
+
+    /// Reconstruction error, in 8-bit levels, above which a pixel is reported as
+    /// over tolerance (and fails --verify).
+    #[arg(long = "error-tolerance", value_name = "LEVELS", default_value_t = DEFAULT_ERROR_TOLERANCE)]
+    error_tolerance: u8,
+
+    /// Fail with a non-zero exit code if any pixel of the output, composited back
+    /// over the background, differs from the input by more than --error-tolerance.
+    #[arg(long = "verify")]
+    verify: bool,
+
+    /// Don't copy the ICC profile, EXIF data, pixel density and PNG text and
+    /// colorimetry chunks of the input to the output.
+    #[arg(long = "strip-metadata")]
+    strip_metadata: bool,
+
+    /// Difference matting: INPUT and OUTPUT are the same image rendered over two
+    /// backgrounds (black and white by default, see --pair-bg), and the exact color
+    /// and alpha of every pixel are computed from them. The result is written to
+    /// PAIR_OUTPUT (e.g., bgone --pair on-black.png on-white.png out.png).
+    #[arg(long = "pair", requires = "output", conflicts_with_all = [
+        "foreground_colors", "foreground_positions", "background_color", "background_position",
+        "strict", "layers", "bg_gradient", "bg_image", "require_confident_bg", "blend_space",
+        "bg_linear", "max_foreground", "palette", "save_palette",
+    ])]
+    pair: bool,
+
+    /// Background colors of the two --pair images, each as hex (e.g., fff, ffffff, #ffffff),
+    /// rgb(), hsl() or a CSS name. Defaults to 000 fff.
+    #[arg(long = "pair-bg", num_args = 2, value_names = ["FIRST", "SECOND"], requires = "pair")]
+    pair_backgrounds: Vec<String>,
+
+    /// Write a machine-readable report of the run (paths, dimensions, colors,
+    /// mode, timings, alpha and reconstruction statistics).
+    /// Printed to stdout unless --report-file is given. When the report goes
+    /// to stdout, status lines are printed to stderr instead.
+    #[arg(long = "report", value_enum, value_name = "FORMAT")]
+    report: Option<ReportFormat>,
+
+    /// Write the report to this file instead of stdout
+    #[arg(long = "report-file", value_name = "PATH", requires = "report")]
+    report_file: Option<PathBuf>,
+
     /// Print version
     #[arg(short = 'v', short_alias = 'V', long = "version", action = clap::ArgAction::Version)]
     version: (),
 }
 
+/// Rendering of the reconstruction error map
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ErrorMapStyle {
+    /// Black for no error, then blue through green and yellow to red
+    Heatmap,
+    /// Grayscale, with the largest error in white
+    Gray,
+}
+
+/// Part of the image border sampled for background detection
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum BgSamplePart {
+    Top,
+    Right,
+    Bottom,
+    Left,
+    /// All four edges
+    Edges,
+    /// The four corners
+    Corners,
+}
+
+/// Color distance metric selectable on the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Distance {
+    /// Euclidean distance in sRGB
+    Rgb,
+    /// Euclidean distance in OKLab
+    Oklab,
+}
+
+impl From<Distance> for ColorDistance {
+    fn from(distance: Distance) -> Self {
+        match distance {
+            Distance::Rgb => ColorDistance::Rgb,
+            Distance::Oklab => ColorDistance::Oklab,
+        }
+    }
+}
+
+/// Blend space selectable on the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Blend {
+    /// Blending on sRGB-encoded values
+    Srgb,
+    /// Blending in linear light
+    Linear,
+}
+
+impl From<Blend> for BlendSpace {
+    fn from(blend: Blend) -> Self {
+        match blend {
+            Blend::Srgb => BlendSpace::Srgb,
+            Blend::Linear => BlendSpace::Linear,
+        }
+    }
+}
+
+/// Background gradient model selectable on the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum BgGradient {
+    /// c0 + c1*x + c2*y
+    Linear,
+    /// Linear plus c3*x*y
+    Bilinear,
+    /// Bilinear plus c4*x² + c5*y²
+    Quadratic,
+}
+
+impl From<BgGradient> for GradientModel {
+    fn from(gradient: BgGradient) -> Self {
+        match gradient {
+            BgGradient::Linear => GradientModel::Linear,
+            BgGradient::Bilinear => GradientModel::Bilinear,
+            BgGradient::Quadratic => GradientModel::Quadratic,
+        }
+    }
+}
+
+/// Format of the machine-readable run report
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ReportFormat {
+    /// A single JSON object
+    Json,
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
+    let started = Instant::now();
+
+    // Keep stdout clean for the report when it is printed there
+    let report_to_stdout = args.report.is_some() && args.report_file.is_none();
+    let observer = TerminalObserver::new(report_to_stdout);
+    let mut timings = StageTimings::default();
 
-    // In strict mode, foreground colors are required
-    if args.strict && args.foreground_colors.is_empty() {
-        anyhow::bail!("In strict mode, at least one foreground color must be specified with --fg");
+    if args.pair {
+        return run_pair(&args, started, &mut timings, &observer);
     }
 
     // Determine output path
     let output_path = determine_output_path(&args.input, args.output.as_deref())?;
 
     // Parse foreground color specifications (if any)
-    let foreground_specs = if args.foreground_colors.is_empty() {
-        Vec::new()
+    let foreground_args = parse_foreground_specs(
+        &args.foreground_colors,
+        &args.foreground_positions,
+        args.palette.as_deref(),
+        &args.swatches,
+    )?;
+
+    // Use threshold if provided, otherwise use default
+    let threshold = args
+        .threshold
+        .unwrap_or(unmix::DEFAULT_COLOR_CLOSENESS_THRESHOLD);
+
+    // Load the input image once for detection, deduction and processing
+    let (img, metadata) =
+        timings.time("load", || load_image_with_metadata(&args.input, &observer))?;
+    let metadata = if args.strip_metadata {
+        ImageMetadata::default()
     } else {
-        parse_foreground_specs(&args.foreground_colors)?
+        metadata
+    };
+
+    // Floating-point images saved as EXR are processed in linear light, and
+    // detection, picking and deduction run on an 8-bit sRGB view of them.
+    // Saved in other formats, they are processed entirely on that view.
+    // Layers are always 8-bit.
+    let pipeline = if args.layers {
+        Pipeline::Rgba8
+    } else {
+        Pipeline::for_image(&img, &output_path)
+    };
+    let hdr_input = (pipeline == Pipeline::Float).then(|| img.to_rgba32f());
+    if hdr_input.is_none() && (args.bg_linear.is_some() || args.max_foreground.is_some()) {
+        anyhow::bail!(
+            "--bg-linear and --max-fg require a floating-point input (EXR or Radiance HDR) and an EXR output, without --layers"
+        );
+    }
+
+    // The linear pipeline always unmixes in linear light, so deduction and the
+    // report must use the same blend space
+    let blend_space = match (&hdr_input, args.blend_space) {
+        (Some(_), Some(Blend::Srgb)) => anyhow::bail!(
+            "--blend-space srgb can't be used for a floating-point input saved as EXR, which is always unmixed in linear light"
+        ),
+        (Some(_), _) => BlendSpace::Linear,
+        (None, blend) => blend.unwrap_or(Blend::Srgb).into(),
+    };
+    let img = if has_float_channels(&img) {
+        DynamicImage::ImageRgba8(to_srgb_rgba8(&img))
+    } else {
+        img
     };
 
     // Determine background color
-    let background_color = determine_background_color(&args)?;
+    let background = timings.time("background", || {
+        determine_background(&args, &img, &observer)
+    })?;
+    let background_color = background.color;
+
+    // Read foreground colors given as pixel positions
+    let foreground_specs =
+        resolve_foreground_specs(&foreground_args, &img, args.pick_size, &observer)?;
 
     // Check if we have any unknown colors to deduce
     let has_unknowns = foreground_specs
@@ -80,20 +412,17 @@ fn main() -> Result<()> {
         .any(|spec| matches!(spec, ForegroundColorSpec::Unknown));
 
     let foreground_colors = if has_unknowns {
-        // Load the image for color deduction
-        let img = image::open(&args.input)
-            .with_context(|| format!("Failed to open input image: {}", args.input.display()))?;
-
-        // Use threshold for color deduction if provided, otherwise use default
-        let deduction_threshold = args
-            .threshold
-            .unwrap_or(unmix::DEFAULT_COLOR_CLOSENESS_THRESHOLD);
-        deduce_unknown_colors(
-            &img,
-            &foreground_specs,
-            background_color,
-            deduction_threshold,
-        )?
+        timings.time("deduction", || {
+            deduce_unknown_colors_with_observer(
+                &img,
+                &foreground_specs,
+                background_color,
+                threshold,
+                args.distance.into(),
+                blend_space,
+                &observer,
+            )
+        })?
     } else {
         // All colors are known, just extract them
         foreground_specs
@@ -105,26 +434,582 @@ fn main() -> Result<()> {
             .collect::<Result<Vec<_>>>()?
     };
 
-    // Validate threshold if provided
-    if let Some(threshold) = args.threshold
-        && (!(0.0..=1.0).contains(&threshold))
-    {
-        anyhow::bail!("Threshold must be between 0.0 and 1.0, got: {}", threshold);
-    }
-
-    // Process the image
-    process_image(
-        &args.input,
-        &output_path,
-        foreground_colors,
-        background_color,
-        args.strict,
-        args.threshold,
-    )?;
+    let mut options = ProcessOptions::new(background_color)
+        .mode(Mode::from_flags(args.strict, !foreground_colors.is_empty()))
+        .foreground_colors(foreground_colors)
+        .threshold(threshold)
+        .distance(args.distance.into())
+        .blend_space(blend_space);
+    if let Some(color) = background.normalized_color {
+        options = options.normalized_background_color(color);
+    }
+    if let Some(background_image) = background.image {
+        options = options.background_image(background_image);
+    }
+
+    if let Some(path) = &args.save_palette {
+        let swatches = resolved_swatches(&foreground_args, &options.foreground_colors);
+        write_palette(path, &swatches)?;
+        observer.status(format!(
+            "✓ Saved {} foreground colors to {}",
+            swatches.len(),
+            display_file_name(path)
+        ));
+    }
+
+    // Process the image with the pipeline picked for the input and output
+    let mut layer_paths = Vec::new();
+    let (reconstruction_error, alpha) = if args.layers {
+        let rgba = img.to_rgba8();
+        let layered = timings.time("processing", || {
+            remove_background_layered_with_observer(&rgba, &options, &observer)
+        })?;
+
+        timings.time("saving", || -> Result<()> {
+            save_image_with_metadata(&layered.image, &output_path, &metadata, &observer)?;
+
+            for (i, (layer, &color)) in layered
+                .layers
+                .iter()
+                .zip(&options.foreground_colors)
+                .enumerate()
+            {
+                let path = layer_path(&output_path, i + 1, color);
+                save_image_with_metadata(layer, &path, &metadata, &observer)?;
+                layer_paths.push(path);
+            }
+
+            Ok(())
+        })?;
+
+        check_output(&rgba, &layered.image, &options, &mut timings)?
+    } else if let Some(input) = &hdr_input {
+        let hdr = match args.bg_linear {
+            Some(linear) => HdrOptions::new(linear),
+            None => HdrOptions::from_options(&options),
+        }
+        .max_foreground(args.max_foreground.unwrap_or(1.0));
+        process_and_save_hdr(input, &hdr, &options, &output_path, &mut timings, &observer)?
+    } else if pipeline == Pipeline::Rgba16 {
+        process_and_save(
+            &img.to_rgba16(),
+            &options,
+            &output_path,
+            &metadata,
+            &mut timings,
+            &observer,
+        )?
+    } else {
+        process_and_save(
+            &img.to_rgba8(),
+            &options,
+            &output_path,
+            &metadata,
+            &mut timings,
+            &observer,
+        )?
+    };
+
+    let error_stats = reconstruction_error.stats(args.error_tolerance);
+    observer.status(format_error_stats(&error_stats));
+
+    // The solver residual shows the colors strict mode can't reproduce, apart
+    // from the rounding in the reconstruction error
+    let residual_stats = if options.mode == Mode::Strict && hdr_input.is_none() {
+        let unmixer =
+            StrictUnmixer::new(&options.foreground_colors).blend_space(options.blend_space);
+        let stats = timings.time("residual", || {
+            if pipeline == Pipeline::Rgba16 {
+                ResidualStats::compute(&img.to_rgba16(), options.background(), &unmixer)
+            } else {
+                ResidualStats::compute(&img.to_rgba8(), options.background(), &unmixer)
+            }
+        })?;
+        observer.status(format!(
+            "✓ Solver residual: max {:.4}, mean {:.4}",
+            stats.max, stats.mean
+        ));
+        Some(stats)
+    } else {
+        None
+    };
+
+    if let Some(error_map_path) = &args.error_map {
+        save_error_map(
+            &reconstruction_error,
+            args.error_map_style,
+            error_map_path,
+            &observer,
+        )?;
+    }
+
+    if let Some(ReportFormat::Json) = args.report {
+        let report = RunReport {
+            input_path: &args.input,
+            output_path: &output_path,
+            layer_paths: &layer_paths,
+            dimensions: (img.width(), img.height()),
+            bit_depth: pipeline.bit_depth(),
+            run: ReportedRun::Single {
+                background_source: &background.source,
+                foreground_specs: &foreground_specs,
+                options: &options,
+                residual_stats: residual_stats.as_ref(),
+            },
+            timings: &timings,
+            total_time: started.elapsed(),
+            alpha,
+            error_stats: &error_stats,
+        };
+        write_report(&report.to_json(), args.report_file.as_deref())?;
+    }
+
+    if args.verify {
+        reconstruction_error.verify(args.error_tolerance)?;
+        observer.status("✓ Verified reconstruction within tolerance");
+    }
 
     Ok(())
 }
 
+/// Remove the background at the bit depth of the input image and save it with
+/// the metadata of the input
+///
+/// Returns the reconstruction error and alpha statistics of the output.
+fn process_and_save<P>(
+    input: &ImageBuffer<P, Vec<P::Subpixel>>,
+    options: &ProcessOptions,
+    output_path: &Path,
+    metadata: &ImageMetadata,
+    timings: &mut StageTimings,
+    observer: &TerminalObserver,
+) -> Result<(ReconstructionError, serde_json::Value)>
+where
+    P: UnmixPixel + PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    let output = timings.time("processing", || {
+        remove_background_with_observer(input, options, observer)
+    })?;
+    timings.time("saving", || {
+        save_image_with_metadata(&output, output_path, metadata, observer)
+    })?;
+    check_output(input, &output, options, timings)
+}
+
+/// Remove the background of a floating-point image in linear light and save it
+///
+/// Returns the reconstruction error and alpha statistics of the output.
+fn process_and_save_hdr(
+    input: &Rgba32FImage,
+    hdr: &HdrOptions,
+    options: &ProcessOptions,
+    output_path: &Path,
+    timings: &mut StageTimings,
+    observer: &TerminalObserver,
+) -> Result<(ReconstructionError, serde_json::Value)> {
+    let output = timings.time("processing", || {
+        remove_background_hdr_with_observer(input, hdr, options, observer)
+    })?;
+    timings.time("saving", || save_image(&output, output_path, observer))?;
+    let reconstruction_error = timings.time("verification", || {
+        hdr_reconstruction_error(input, &output, hdr.background)
+    })?;
+    Ok((
+        reconstruction_error,
+        alpha_stats(output.pixels().map(|pixel| pixel[3] as f64)),
+    ))
+}
+
+/// Measure how well an output image reconstructs its input, and summarize
+/// its alpha channel
+fn check_output<P: UnmixPixel>(
+    input: &ImageBuffer<P, Vec<P::Subpixel>>,
+    output: &ImageBuffer<P, Vec<P::Subpixel>>,
+    options: &ProcessOptions,
+    timings: &mut StageTimings,
+) -> Result<(ReconstructionError, serde_json::Value)> {
+    let reconstruction_error = timings.time("verification", || {
+        ReconstructionError::compute_with_background(
+            input,
+            output,
+            options.background(),
+            options.blend_space,
+        )
+    })?;
+    Ok((
+        reconstruction_error,
+        alpha_stats(output.pixels().map(UnmixPixel::normalized_alpha)),
+    ))
+}
+
+/// Recover the foreground from two renders over different backgrounds (--pair)
+fn run_pair(
+    args: &Args,
+    started: Instant,
+    timings: &mut StageTimings,
+    observer: &TerminalObserver,
+) -> Result<()> {
+    let second_path = args
+        .output
+        .as_deref()
+        .context("--pair requires two input images")?;
+    let output_path = determine_output_path(&args.input, args.pair_output.as_deref())?;
+
+    let (first_background, second_background) = match args.pair_backgrounds.as_slice() {
+        [] => ([0, 0, 0], [255, 255, 255]),
+        [first, second] => (
+            parse_color(first).context("Invalid first pair background color")?,
+            parse_color(second).context("Invalid second pair background color")?,
+        ),
+        _ => unreachable!("--pair-bg takes exactly two colors"),
+    };
+
+    let (first, second, metadata) = timings.time("load", || -> Result<_> {
+        let (first, metadata) = load_image_with_metadata(&args.input, observer)?;
+        let second = load_image(second_path, observer)?;
+        Ok((first.to_rgba8(), second.to_rgba8(), metadata))
+    })?;
+    let metadata = if args.strip_metadata {
+        ImageMetadata::default()
+    } else {
+        metadata
+    };
+
+    let matte = timings.time("processing", || {
+        remove_background_pair_with_observer(
+            &first,
+            first_background,
+            &second,
+            second_background,
+            observer,
+        )
+    })?;
+    timings.time("saving", || {
+        save_image_with_metadata(&matte.image, &output_path, &metadata, observer)
+    })?;
+
+    // Pixels that can't be reproduced over both backgrounds differ between the inputs
+    let stats = matte.disagreement.stats(args.error_tolerance);
+    observer.status(format!(
+        "✓ Inputs disagree on {} of {} pixels by more than {} levels (max {})",
+        stats.over_tolerance, stats.pixel_count, stats.tolerance, stats.max
+    ));
+
+    if let Some(error_map_path) = &args.error_map {
+        save_error_map(
+            &matte.disagreement,
+            args.error_map_style,
+            error_map_path,
+            observer,
+        )?;
+    }
+
+    if let Some(ReportFormat::Json) = args.report {
+        let report = RunReport {
+            input_path: &args.input,
+            output_path: &output_path,
+            layer_paths: &[],
+            dimensions: (matte.image.width(), matte.image.height()),
+            bit_depth: 8,
+            run: ReportedRun::Pair {
+                second_input_path: second_path,
+                backgrounds: (first_background, second_background),
+            },
+            timings,
+            total_time: started.elapsed(),
+            alpha: alpha_stats(matte.image.pixels().map(UnmixPixel::normalized_alpha)),
+            error_stats: &stats,
+        };
+        write_report(&report.to_json(), args.report_file.as_deref())?;
+    }
+
+    if args.verify {
+        matte.disagreement.verify(args.error_tolerance)?;
+        observer.status("✓ Verified reconstruction within tolerance");
+    }
+
+    Ok(())
+}
+
+/// Format reconstruction error statistics as a status line
+fn format_error_stats(stats: &ErrorStats) -> String {
+    format!(
+        "✓ Reconstruction error: max {}, mean {:.3}, 99th percentile {}, {} of {} pixels over tolerance {}",
+        stats.max, stats.mean, stats.p99, stats.over_tolerance, stats.pixel_count, stats.tolerance
+    )
+}
+
+/// Save the reconstruction error map in the requested style
+fn save_error_map(
+    reconstruction_error: &ReconstructionError,
+    style: ErrorMapStyle,
+    path: &Path,
+    observer: &TerminalObserver,
+) -> Result<()> {
+    match style {
+        ErrorMapStyle::Heatmap => reconstruction_error.heatmap().save(path),
+        ErrorMapStyle::Gray => reconstruction_error.grayscale_map().save(path),
+    }
+    .with_context(|| format!("Failed to save error map: {}", path.display()))?;
+
+    observer.status(format!("✓ Saved error map to {}", display_file_name(path)));
+    Ok(())
+}
+
+/// Duration of each stage of a run, in the order they ran
+#[derive(Default)]
+struct StageTimings(Vec<(&'static str, Duration)>);
+
+impl StageTimings {
+    /// Run a stage and record how long it took
+    fn time<T>(&mut self, stage: &'static str, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.0.push((stage, started.elapsed()));
+        result
+    }
+}
+
+/// Everything recorded about a run for the machine-readable report
+struct RunReport<'a> {
+    input_path: &'a Path,
+    output_path: &'a Path,
+    layer_paths: &'a [PathBuf],
+    dimensions: (u32, u32),
+    bit_depth: u8,
+    run: ReportedRun<'a>,
+    timings: &'a StageTimings,
+    total_time: Duration,
+    alpha: serde_json::Value,
+    error_stats: &'a ErrorStats,
+}
+
+/// How the background was removed in a reported run
+enum ReportedRun<'a> {
+    /// From a single image, with the resolved background and foreground colors
+    Single {
+        background_source: &'a BackgroundSource,
+        foreground_specs: &'a [ForegroundColorSpec],
+        options: &'a ProcessOptions,
+        /// Solver residuals of strict mode runs on 8-bit and 16-bit images
+        residual_stats: Option<&'a ResidualStats>,
+    },
+    /// From two renders over different backgrounds with --pair
+    Pair {
+        second_input_path: &'a Path,
+        backgrounds: (Color, Color),
+    },
+}
+
+impl RunReport<'_> {
+    fn to_json(&self) -> serde_json::Value {
+        let mut timings = serde_json::Map::new();
+        for (stage, duration) in &self.timings.0 {
+            timings.insert(stage.to_string(), json!(duration_ms(*duration)));
+        }
+        timings.insert("total".to_string(), json!(duration_ms(self.total_time)));
+
+        let error_stats = json!({
+            "max": self.error_stats.max,
+            "mean": self.error_stats.mean,
+            "p99": self.error_stats.p99,
+            "tolerance": self.error_stats.tolerance,
+            "over_tolerance": self.error_stats.over_tolerance,
+        });
+
+        let mut report = json!({
+            "input": self.input_path,
+            "output": self.output_path,
+            "layers": self.layer_paths,
+            "width": self.dimensions.0,
+            "height": self.dimensions.1,
+            "bit_depth": self.bit_depth,
+        });
+
+        let run = match self.run {
+            ReportedRun::Single {
+                background_source,
+                foreground_specs,
+                options,
+                residual_stats,
+            } => {
+                let foreground_colors: Vec<_> = options
+                    .foreground_colors
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &color)| {
+                        json!({
+                            "color": format_hex_color(color),
+                            "deduced": matches!(
+                                foreground_specs.get(i),
+                                Some(ForegroundColorSpec::Unknown)
+                            ),
+                        })
+                    })
+                    .collect();
+
+                json!({
+                    "background": background_json(background_source, options),
+                    "foreground_colors": foreground_colors,
+                    "mode": match options.mode {
+                        Mode::NonStrict => "non-strict",
+                        Mode::NonStrictWithFg => "non-strict-with-fg",
+                        Mode::Strict => "strict",
+                    },
+                    "threshold": options.threshold,
+                    "distance": match options.distance {
+                        ColorDistance::Rgb => "rgb",
+                        ColorDistance::Oklab => "oklab",
+                    },
+                    "blend_space": match options.blend_space {
+                        BlendSpace::Srgb => "srgb",
+                        BlendSpace::Linear => "linear",
+                    },
+                    "timings_ms": timings,
+                    "alpha": self.alpha,
+                    "reconstruction_error": error_stats,
+                    "solver_residual": residual_stats.map(|stats| json!({
+                        "max": stats.max,
+                        "mean": stats.mean,
+                    })),
+                })
+            }
+            // The matte reproduces both inputs exactly where they agree, so the
+            // only error to report is where they don't
+            ReportedRun::Pair {
+                second_input_path,
+                backgrounds: (first, second),
+            } => json!({
+                "second_input": second_input_path,
+                "background": {
+                    "colors": [format_hex_color(first), format_hex_color(second)],
+                    "source": "pair",
+                },
+                "foreground_colors": [],
+                "mode": "pair",
+                "timings_ms": timings,
+                "alpha": self.alpha,
+                "disagreement": error_stats,
+            }),
+        };
+
+        if let (Some(report), serde_json::Value::Object(run)) = (report.as_object_mut(), run) {
+            report.extend(run);
+        }
+        report
+    }
+}
+
+/// Describe where the background of a single-image run came from
+fn background_json(source: &BackgroundSource, options: &ProcessOptions) -> serde_json::Value {
+    let color = format_hex_color(options.background_color);
+    match source {
+        BackgroundSource::Given => json!({ "color": color, "source": "given" }),
+        BackgroundSource::Linear(linear) => json!({
+            "color": color,
+            "source": "linear",
+            "linear": linear,
+        }),
+        BackgroundSource::Picked(position) => json!({
+            "color": color,
+            "source": "picked",
+            "position": [position.0, position.1],
+        }),
+        BackgroundSource::Gradient(model) => json!({
+            "color": color,
+            "source": "gradient",
+            "model": gradient_name(*model),
+        }),
+        BackgroundSource::Plate(path) => json!({
+            "color": color,
+            "source": "image",
+            "path": path,
+        }),
+        BackgroundSource::Detected(detection) => json!({
+            "color": color,
+            "source": "detected",
+            "confidence": detection.confidence,
+            "sample_count": detection.sample_count,
+            "runner_up": detection.runner_up.map(|(color, share)| json!({
+                "color": format_hex_color(color),
+                "share": share,
+            })),
+        }),
+    }
+}
+
+/// Summarize the alpha channel of an output image, given as the alpha of
+/// every pixel from 0.0 to 1.0
+///
+/// The histogram has 16 buckets of 16 8-bit alpha levels each.
+fn alpha_stats(alphas: impl Iterator<Item = f64>) -> serde_json::Value {
+    let mut histogram = [0u64; 16];
+    let (mut transparent, mut opaque, mut total) = (0u64, 0u64, 0.0);
+    let mut pixel_count = 0u64;
+
+    for alpha in alphas {
+        let alpha = alpha.clamp(0.0, 1.0);
+        pixel_count += 1;
+        histogram[(alpha * 255.0).round() as usize / 16] += 1;
+        total += alpha;
+        if alpha == 0.0 {
+            transparent += 1;
+        } else if alpha == 1.0 {
+            opaque += 1;
+        }
+    }
+
+    json!({
+        "transparent": transparent,
+        "translucent": pixel_count - transparent - opaque,
+        "opaque": opaque,
+        "mean": if pixel_count > 0 { total / pixel_count as f64 } else { 0.0 },
+        "histogram": histogram,
+    })
+}
+
+/// Convert a duration to fractional milliseconds
+fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Write a report to a file, or to stdout if no path is given
+fn write_report(report: &serde_json::Value, path: Option<&Path>) -> Result<()> {
+    let json = serde_json::to_string_pretty(report)?;
+    match path {
+        Some(path) => std::fs::write(path, json + "\n")
+            .with_context(|| format!("Failed to write report: {}", path.display())),
+        None => {
+            println!("{}", json);
+            Ok(())
+        }
+    }
+}
+
+/// Determine the path of a foreground color layer next to the output image
+///
+/// For example, layer 1 of `out.png` with color #ff0000 is `out-layer-1-ff0000.png`.
+fn layer_path(output_path: &Path, index: usize, color: Color) -> PathBuf {
+    let stem = output_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let ext = output_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "png".to_string());
+    let hex = format_hex_color(color);
+
+    output_path.with_file_name(format!(
+        "{}-layer-{}-{}.{}",
+        stem,
+        index,
+        hex.trim_start_matches('#'),
+        ext
+    ))
+}
+
 /// Determine the output path for the processed image
 ///
 /// If output is provided, use it as-is.
@@ -151,6 +1036,8 @@ fn determine_output_path(input: &Path, output: Option<&Path>) -> Result<PathBuf>
     let output_ext = match input_ext.as_str() {
         // Formats that support alpha channels
         "png" | "webp" | "tiff" | "tif" | "gif" | "qoi" | "exr" => input_ext,
+        // Radiance HDR has no alpha -> keep floating-point values in EXR
+        "hdr" => "exr".to_string(),
         // Formats without alpha support -> convert to PNG
         _ => "png".to_string(),
     };
@@ -174,13 +1061,35 @@ fn determine_output_path(input: &Path, output: Option<&Path>) -> Result<PathBuf>
     anyhow::bail!("Could not generate unique output filename (tried up to -bgone-999)")
 }
 
+/// A foreground color from the command line, before the input image is loaded
+#[derive(Debug, PartialEq)]
+enum ForegroundArg {
+    /// A hex color or 'auto'
+    Spec(ForegroundColorSpec),
+    /// A pixel of the input image to read the color from (@X,Y or --fg-at)
+    At((u32, u32)),
+    /// A swatch of the --palette file
+    Swatch(Swatch),
+}
+
 /// Parse and validate foreground color specifications from command line arguments
-fn parse_foreground_specs(color_strings: &[String]) -> Result<Vec<ForegroundColorSpec>> {
-    let specs: Result<Vec<ForegroundColorSpec>> = color_strings
+///
+/// Positions given with --fg-at come after the colors given with --fg.
+fn parse_foreground_specs(
+    color_strings: &[String],
+    positions: &[(u32, u32)],
+    palette: Option<&Path>,
+    swatch_names: &[String],
+) -> Result<Vec<ForegroundArg>> {
+    let specs: Result<Vec<ForegroundArg>> = color_strings
         .iter()
         .enumerate()
         .map(|(i, spec_str)| {
-            parse_foreground_spec(spec_str).with_context(|| {
+            match spec_str.strip_prefix('@') {
+                Some(position) => parse_pixel_position(position).map(ForegroundArg::At),
+                None => parse_foreground_spec(spec_str).map(ForegroundArg::Spec),
+            }
+            .with_context(|| {
                 format!(
                     "Invalid foreground color specification #{}: {}",
                     i + 1,
@@ -190,35 +1099,475 @@ fn parse_foreground_specs(color_strings: &[String]) -> Result<Vec<ForegroundColo
         })
         .collect();
 
-    specs
+    let mut specs = specs?;
+    specs.extend(
+        positions
+            .iter()
+            .map(|&position| ForegroundArg::At(position)),
+    );
+
+    if let Some(path) = palette {
+        let swatches = read_palette(path)?;
+        let swatches = if swatch_names.is_empty() {
+            swatches
+        } else {
+            filter_swatches(&swatches, swatch_names)?
+        };
+        if swatches.is_empty() {
+            anyhow::bail!("Palette {} has no colors", path.display());
+        }
+        specs.extend(swatches.into_iter().map(ForegroundArg::Swatch));
+    }
+    Ok(specs)
 }
 
-/// Determine background color either from user input or auto-detection
-fn determine_background_color(args: &Args) -> Result<Color> {
+/// Read the foreground colors given as pixel positions from the input image
+fn resolve_foreground_specs(
+    foreground_args: &[ForegroundArg],
+    img: &DynamicImage,
+    pick_size: u32,
+    observer: &TerminalObserver,
+) -> Result<Vec<ForegroundColorSpec>> {
+    foreground_args
+        .iter()
+        .map(|arg| match arg {
+            ForegroundArg::Spec(spec) => Ok(spec.clone()),
+            ForegroundArg::Swatch(swatch) => Ok(ForegroundColorSpec::Known(swatch.color)),
+            ForegroundArg::At(position) => {
+                let color = pick_color(img, *position, pick_size)
+                    .context("Cannot read a foreground color from the input image")?;
+                observer.status(format!(
+                    "✓ Picked foreground color {} at {},{}",
+                    format_hex_color(color),
+                    position.0,
+                    position.1
+                ));
+                Ok(ForegroundColorSpec::Known(color))
+            }
+        })
+        .collect()
+}
+
+/// Name the resolved foreground colors for --save-palette
+///
+/// Colors from the --palette file keep their swatch name, other colors are
+/// named by their hex value.
+fn resolved_swatches(foreground_args: &[ForegroundArg], colors: &[Color]) -> Vec<Swatch> {
+    foreground_args
+        .iter()
+        .zip(colors)
+        .map(|(arg, &color)| match arg {
+            ForegroundArg::Swatch(swatch) if !swatch.name.is_empty() => swatch.clone(),
+            _ => Swatch::new(format_hex_color(color), color),
+        })
+        .collect()
+}
+
+/// Where the background came from
+enum BackgroundSource {
+    /// Given with --bg
+    Given,
+    /// Given in linear light with --bg-linear
+    Linear(LinearColor),
+    /// Read from a pixel of the input image with --bg-at
+    Picked((u32, u32)),
+    /// Auto-detected from the image border
+    Detected(BackgroundDetection),
+    /// Fitted to the image border with --bg-gradient
+    Gradient(GradientModel),
+    /// Loaded from a clean plate image with --bg-image
+    Plate(PathBuf),
+}
+
+/// Background to remove, resolved from the command line arguments
+struct ResolvedBackground {
+    /// The background color, or a representative color for per-pixel backgrounds
+    color: Color,
+    /// The background color with more than 8 bits of precision, for colors
+    /// given with --bg, and detected or picked in 16-bit images
+    normalized_color: Option<NormalizedColor>,
+    /// The background color of every pixel, for per-pixel backgrounds
+    image: Option<NormalizedRgbImage>,
+    source: BackgroundSource,
+}
+
+/// Determine the background from user input, a pixel of the image, a clean
+/// plate, a fitted gradient or auto-detection
+///
+/// Warns, or fails with --require-confident-bg, when an auto-detected border
+/// is not dominated by one color.
+fn determine_background(
+    args: &Args,
+    img: &DynamicImage,
+    observer: &TerminalObserver,
+) -> Result<ResolvedBackground> {
+    if let Some(linear) = args.bg_linear {
+        return Ok(ResolvedBackground {
+            color: HdrOptions::new(linear).srgb_background(),
+            normalized_color: None,
+            image: None,
+            source: BackgroundSource::Linear(linear),
+        });
+    }
+
     if let Some(bg_str) = &args.background_color {
-        parse_hex_color(bg_str).context("Invalid background color")
+        let color = parse_color_normalized(bg_str).context("Invalid background color")?;
+        return Ok(ResolvedBackground {
+            color: denormalize_color(color),
+            normalized_color: Some(color),
+            image: None,
+            source: BackgroundSource::Given,
+        });
+    }
+
+    if let Some(position) = args.background_position {
+        let normalized_color = pick_color_normalized(img, position, args.pick_size)
+            .context("Cannot read the background color from the input image")?;
+        let color = denormalize_color(normalized_color);
+        observer.status(format!(
+            "✓ Picked background color {} at {},{}",
+            format_hex_color(color),
+            position.0,
+            position.1
+        ));
+        return Ok(ResolvedBackground {
+            color,
+            normalized_color: Some(normalized_color),
+            image: None,
+            source: BackgroundSource::Picked(position),
+        });
+    }
+
+    if let Some(plate_path) = &args.bg_image {
+        let image = normalize_image(&load_image(plate_path, observer)?.to_rgb16());
+        Background::PerPixel(&image)
+            .check_dimensions((img.width(), img.height()))
+            .context("The background image must have the same size as the input image")?;
+
+        return Ok(ResolvedBackground {
+            color: mean_color(&image),
+            normalized_color: None,
+            image: Some(image),
+            source: BackgroundSource::Plate(plate_path.clone()),
+        });
+    }
+
+    if let Some(gradient) = args.bg_gradient {
+        let model = GradientModel::from(gradient);
+        observer.start_spinner("Fitting background gradient...");
+        let image = fit_background_gradient(img, model)?;
+        observer.finish_spinner();
+
+        let color = mean_color(&image);
+        observer.status(format!(
+            "✓ Fitted {} background gradient (average color: {})",
+            gradient_name(model),
+            format_hex_color(color)
+        ));
+        return Ok(ResolvedBackground {
+            color,
+            normalized_color: None,
+            image: Some(image),
+            source: BackgroundSource::Gradient(model),
+        });
+    }
+
+    // Auto-detect background color
+    observer.detecting_background();
+    let detection = detect_background(img, &background_detection_config(args)).context(
+        "Cannot detect the background color: there are no pixels in the sampling region",
+    )?;
+    observer.background_detected(detection.color);
+
+    if !detection.is_confident(DEFAULT_MIN_BACKGROUND_CONFIDENCE) {
+        let message = describe_uncertain_background(&detection);
+        if args.require_confident_bg {
+            anyhow::bail!(message);
+        }
+        observer.warning(message);
+    }
+
+    Ok(ResolvedBackground {
+        color: detection.color,
+        normalized_color: Some(detection.normalized_color),
+        image: None,
+        source: BackgroundSource::Detected(detection),
+    })
+}
+
+/// Name of a gradient model as given on the command line
+fn gradient_name(model: GradientModel) -> &'static str {
+    match model {
+        GradientModel::Linear => "linear",
+        GradientModel::Bilinear => "bilinear",
+        GradientModel::Quadratic => "quadratic",
+    }
+}
+
+/// Average color of an image
+fn mean_color(image: &NormalizedRgbImage) -> Color {
+    let mut sum = [0.0; 3];
+    for pixel in image.pixels() {
+        for i in 0..3 {
+            sum[i] += pixel[i];
+        }
+    }
+
+    let count = (image.width() as u64 * image.height() as u64).max(1);
+    denormalize_color(sum.map(|channel| channel / count as f64))
+}
+
+/// Build the background detection configuration from the command line arguments
+fn background_detection_config(args: &Args) -> BackgroundDetectionConfig {
+    let mut config = BackgroundDetectionConfig {
+        tolerance: args.bg_tolerance,
+        ..Default::default()
+    };
+
+    if let Some(interval) = args.bg_sample_interval {
+        config.edge_sample_interval = interval;
+    }
+
+    if let Some([x, y, width, height]) = args.bg_rect {
+        config.region = SampleRegion::Rect {
+            x,
+            y,
+            width,
+            height,
+        };
     } else {
-        // Auto-detect background color
-        let detect_progress = ProgressBar::new_spinner();
-        detect_progress.set_style(
+        let (edges, corners) = if args.bg_sample.is_empty() {
+            (Edges::ALL, true)
+        } else {
+            let has = |part| args.bg_sample.contains(&part);
+            let all_edges = has(BgSamplePart::Edges);
+            (
+                Edges {
+                    top: all_edges || has(BgSamplePart::Top),
+                    right: all_edges || has(BgSamplePart::Right),
+                    bottom: all_edges || has(BgSamplePart::Bottom),
+                    left: all_edges || has(BgSamplePart::Left),
+                },
+                has(BgSamplePart::Corners),
+            )
+        };
+        config.region = SampleRegion::Border {
+            edges,
+            corners,
+            inset: args.bg_inset,
+        };
+    }
+
+    config
+}
+
+/// Parse a linear light color given as "R,G,B", e.g. "2.5,2.5,2.5"
+fn parse_linear_color(input: &str) -> std::result::Result<LinearColor, String> {
+    let values = input
+        .split(',')
+        .map(|value| value.trim().parse::<f32>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid number: {}", e))?;
+
+    match values.as_slice() {
+        &[r, g, b] if values.iter().all(|v| v.is_finite() && *v >= 0.0) => Ok([r, g, b]),
+        [_, _, _] => Err("channels must be finite and non-negative".to_string()),
+        _ => Err(format!("expected R,G,B, got {} values", values.len())),
+    }
+}
+
+/// Parse a rectangle given as X,Y,WIDTH,HEIGHT
+fn parse_rect(input: &str) -> std::result::Result<[u32; 4], String> {
+    let values = input
+        .split(',')
+        .map(|value| value.trim().parse::<u32>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid number: {}", e))?;
+
+    match values.as_slice() {
+        [_, _, 0, _] | [_, _, _, 0] => Err("width and height must be at least 1".to_string()),
+        &[x, y, width, height] => Ok([x, y, width, height]),
+        _ => Err(format!(
+            "expected X,Y,WIDTH,HEIGHT, got {} values",
+            values.len()
+        )),
+    }
+}
+
+/// Describe a background detection whose color doesn't dominate the border
+fn describe_uncertain_background(detection: &BackgroundDetection) -> String {
+    let runner_up = detection
+        .runner_up
+        .map(|(color, share)| {
+            format!(
+                " (runner-up {} at {:.0}%)",
+                format_hex_color(color),
+                share * 100.0
+            )
+        })
+        .unwrap_or_default();
+
+    format!(
+        "Background color {} covers only {:.0}% of the image border{}. \
+         The image may not have a solid background; use --bg to specify it.",
+        format_hex_color(detection.color),
+        detection.confidence * 100.0,
+        runner_up
+    )
+}
+
+/// Format a color as a lowercase hex string (e.g., #ff0000)
+fn format_hex_color([r, g, b]: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Get the file name of a path for display
+fn display_file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Reports progress on the terminal with spinners, a progress bar and status lines
+struct TerminalObserver {
+    /// Spinner for the current step (loading, detecting, deducing, saving)
+    spinner: Mutex<Option<ProgressBar>>,
+    /// Progress bar for pixel processing, hidden until processing starts
+    pixel_progress: ProgressBar,
+    /// Print status lines to stderr instead of stdout
+    status_to_stderr: bool,
+}
+
+impl TerminalObserver {
+    fn new(status_to_stderr: bool) -> Self {
+        Self {
+            spinner: Mutex::new(None),
+            pixel_progress: ProgressBar::hidden(),
+            status_to_stderr,
+        }
+    }
+
+    /// Print a status line
+    fn status(&self, line: impl std::fmt::Display) {
+        if self.status_to_stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    /// Print a warning as a status line
+    fn warning(&self, message: impl std::fmt::Display) {
+        self.status(format!("⚠ {}", message));
+    }
+
+    /// Show a spinner with a message, replacing any current spinner
+    fn start_spinner(&self, message: &str) {
+        let spinner = ProgressBar::new_spinner();
+        spinner.set_style(
             ProgressStyle::default_spinner()
-                .template("{spinner:.green} Auto-detecting background color...")
+                .template(&format!("{{spinner:.green}} {} {{msg}}", message))
                 .expect("Failed to create progress bar style"),
         );
-        detect_progress.enable_steady_tick(std::time::Duration::from_millis(100));
+        spinner.enable_steady_tick(std::time::Duration::from_millis(100));
 
-        let img = image::open(&args.input)
-            .with_context(|| format!("Failed to open input image: {}", args.input.display()))?;
+        if let Some(previous) = self.spinner.lock().unwrap().replace(spinner) {
+            previous.finish_and_clear();
+        }
+    }
 
-        let detected = detect_background_color(&img);
+    /// Clear the current spinner, if any
+    fn finish_spinner(&self) {
+        if let Some(spinner) = self.spinner.lock().unwrap().take() {
+            spinner.finish_and_clear();
+        }
+    }
+}
+
+impl ProgressObserver for TerminalObserver {
+    fn loading(&self, _path: &Path) {
+        self.start_spinner("Loading image...");
+    }
+
+    fn loaded(&self, path: &Path, width: u32, height: u32) {
+        self.finish_spinner();
+        self.status(format!(
+            "✓ Loaded {} ({}x{} pixels)",
+            display_file_name(path),
+            width,
+            height
+        ));
+    }
 
-        detect_progress.finish_and_clear();
-        println!(
-            "✓ Auto-detected background color: #{:02x}{:02x}{:02x}",
-            detected[0], detected[1], detected[2]
+    fn detecting_background(&self) {
+        self.start_spinner("Auto-detecting background color...");
+    }
+
+    fn background_detected(&self, color: Color) {
+        self.finish_spinner();
+        self.status(format!(
+            "✓ Auto-detected background color: {}",
+            format_hex_color(color)
+        ));
+    }
+
+    fn deduction_started(&self, unique_colors: usize) {
+        self.status(format!("  Found {} unique colors in image", unique_colors));
+        self.start_spinner("Deducing unknown colors...");
+    }
+
+    fn deduction_progress(&self, evaluated: usize, total: usize) {
+        if let Some(spinner) = self.spinner.lock().unwrap().as_ref() {
+            spinner.set_message(format!("({}/{})", evaluated, total));
+        }
+    }
+
+    fn deduction_finished(&self, deduced: &[Color]) {
+        self.finish_spinner();
+
+        let deduced_strs: Vec<String> = deduced.iter().map(|&c| format_hex_color(c)).collect();
+        let plural = if deduced.len() == 1 {
+            "color"
+        } else {
+            "colors"
+        };
+        self.status(format!(
+            "✓ Deduced {} unknown {}: {}",
+            deduced.len(),
+            plural,
+            deduced_strs.join(" ")
+        ));
+    }
+
+    fn processing_started(&self, total_pixels: u64) {
+        self.pixel_progress.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} pixels ({percent}%)")
+                .expect("Failed to create progress bar style")
+                .progress_chars("#>-"),
         );
+        self.pixel_progress.set_length(total_pixels);
+        self.pixel_progress
+            .set_draw_target(ProgressDrawTarget::stderr());
+    }
 
-        Ok(detected)
+    fn pixels_processed(&self, count: u64) {
+        self.pixel_progress.inc(count);
+    }
+
+    fn processing_finished(&self, total_pixels: u64) {
+        self.pixel_progress
+            .finish_with_message(format!("✓ Processed {} pixels", total_pixels));
+    }
+
+    fn saving(&self, _path: &Path) {
+        self.start_spinner("Saving image...");
+    }
+
+    fn saved(&self, path: &Path) {
+        self.finish_spinner();
+        self.status(format!("✓ Saved to {}", display_file_name(path)));
     }
 }
 
@@ -228,6 +1577,108 @@ mod tests {
     use std::fs;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_parse_linear_color() {
+        assert_eq!(parse_linear_color("2.5,1, 0"), Ok([2.5, 1.0, 0.0]));
+        assert!(parse_linear_color("1,1").is_err());
+        assert!(parse_linear_color("-1,0,0").is_err());
+        assert!(parse_linear_color("inf,0,0").is_err());
+    }
+
+    #[test]
+    fn test_parse_rect() {
+        assert_eq!(parse_rect("0,40,200,100"), Ok([0, 40, 200, 100]));
+        assert_eq!(parse_rect(" 1, 2, 3, 4 "), Ok([1, 2, 3, 4]));
+        assert!(parse_rect("1,2,3").is_err());
+        assert!(parse_rect("1,2,0,4").is_err());
+        assert!(parse_rect("a,2,3,4").is_err());
+    }
+
+    #[test]
+    fn test_background_detection_config() {
+        let args = Args::parse_from(["bgone", "in.png", "--bg-sample", "left,corners"]);
+        let config = background_detection_config(&args);
+        assert_eq!(
+            config.region,
+            SampleRegion::Border {
+                edges: Edges {
+                    left: true,
+                    ..Edges::NONE
+                },
+                corners: true,
+                inset: 0,
+            }
+        );
+
+        let args = Args::parse_from(["bgone", "in.png", "--bg-rect", "1,2,3,4"]);
+        let config = background_detection_config(&args);
+        assert_eq!(
+            config.region,
+            SampleRegion::Rect {
+                x: 1,
+                y: 2,
+                width: 3,
+                height: 4
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_foreground_specs() {
+        let specs = parse_foreground_specs(
+            &[
+                "ff0000".to_string(),
+                "@120,45".to_string(),
+                "auto".to_string(),
+            ],
+            &[(8, 9)],
+            None,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            specs,
+            vec![
+                ForegroundArg::Spec(ForegroundColorSpec::Known([255, 0, 0])),
+                ForegroundArg::At((120, 45)),
+                ForegroundArg::Spec(ForegroundColorSpec::Unknown),
+                ForegroundArg::At((8, 9)),
+            ]
+        );
+
+        assert!(parse_foreground_specs(&["@120".to_string()], &[], None, &[]).is_err());
+    }
+
+    #[test]
+    fn test_resolved_swatches() {
+        let foreground_args = [
+            ForegroundArg::Swatch(Swatch::new("Brand Red", [230, 30, 40])),
+            ForegroundArg::Spec(ForegroundColorSpec::Unknown),
+        ];
+        assert_eq!(
+            resolved_swatches(&foreground_args, &[[230, 30, 40], [0, 128, 255]]),
+            vec![
+                Swatch::new("Brand Red", [230, 30, 40]),
+                Swatch::new("#0080ff", [0, 128, 255]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layer_path() {
+        let output = Path::new("/some/path/output.png");
+        assert_eq!(
+            layer_path(output, 1, [255, 0, 0]),
+            Path::new("/some/path/output-layer-1-ff0000.png")
+        );
+
+        let output = Path::new("image.webp");
+        assert_eq!(
+            layer_path(output, 2, [0, 128, 255]),
+            Path::new("image-layer-2-0080ff.webp")
+        );
+    }
+
     #[test]
     fn test_determine_output_path_explicit() {
         let input = Path::new("/some/path/input.png");
@@ -301,6 +1752,16 @@ mod tests {
         assert_eq!(result, temp_dir.path().join("image-bgone.webp"));
     }
 
+    #[test]
+    fn test_determine_output_path_converts_radiance_to_exr() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_path = temp_dir.path().join("render.hdr");
+
+        let result = determine_output_path(&input_path, None).unwrap();
+        // Radiance HDR has no alpha, EXR keeps the floating-point values
+        assert_eq!(result, temp_dir.path().join("render-bgone.exr"));
+    }
+
     #[test]
     fn test_determine_output_path_no_extension() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,220 @@
+/// Metadata of an input image that is carried over to the output
+///
+/// The ICC profile and EXIF data are written to PNG and WebP outputs, the pixel
+/// density and the other PNG chunks to PNG outputs only. Other formats are
+/// saved without metadata.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageMetadata {
+    /// Embedded ICC color profile, e.g. Display P3
+    pub icc_profile: Option<Vec<u8>>,
+    /// Raw EXIF data
+    ///
+    /// Images are loaded upright, so the EXIF orientation is reset to
+    /// "no transforms" once it has been applied to the pixels.
+    pub exif: Option<Vec<u8>>,
+    /// Physical pixel density, which sets the print size of the image
+    pub density: Option<PixelDensity>,
+    /// PNG text (`tEXt`, `zTXt`, `iTXt`) and colorimetry (`gAMA`, `cHRM`,
+    /// `sRGB`) chunks, copied as they are
+    pub png_chunks: Vec<PngChunk>,
+}
+
+/// Physical pixel density of an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelDensity {
+    /// Horizontal pixels per unit
+    pub x: u32,
+    /// Vertical pixels per unit
+    pub y: u32,
+    /// Whether the unit is the meter; otherwise only the aspect ratio of the
+    /// pixels is known
+    pub per_meter: bool,
+}
+
+/// Raw PNG chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PngChunk {
+    /// Chunk type, e.g. `*b"tEXt"`
+    pub kind: [u8; 4],
+    /// Chunk data, without the length, type and CRC
+    pub data: Vec<u8>,
+}
+
+/// PNG chunks copied from the input to the output
+const COPIED_PNG_CHUNKS: [&[u8; 4]; 6] = [b"tEXt", b"zTXt", b"iTXt", b"gAMA", b"cHRM", b"sRGB"];
+
+/// PNG file signature
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+impl ImageMetadata {
+    /// Check if there is no metadata to write
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Read the pixel density and PNG chunks of an encoded PNG or JPEG file
+    ///
+    /// The ICC profile and EXIF data come from the image decoder instead.
+    pub(crate) fn read_from_file_contents(&mut self, bytes: &[u8]) {
+        if bytes.starts_with(&PNG_SIGNATURE) {
+            let chunks = read_png_chunks(bytes);
+            self.density = chunks
+                .iter()
+                .find(|chunk| &chunk.kind == b"pHYs")
+                .and_then(|chunk| parse_png_density(&chunk.data));
+            self.png_chunks = chunks
+                .into_iter()
+                .filter(|chunk| COPIED_PNG_CHUNKS.contains(&&chunk.kind))
+                .collect();
+        } else {
+            self.density = parse_jfif_density(bytes);
+        }
+    }
+
+    /// Insert the pixel density and PNG chunks into an encoded PNG file,
+    /// right after its header
+    pub(crate) fn write_to_png(&self, png: &mut Vec<u8>) {
+        // Signature (8 bytes) and IHDR chunk (length, type, 13 bytes of data, CRC)
+        const HEADER_END: usize = 8 + 4 + 4 + 13 + 4;
+        if png.len() < HEADER_END {
+            return;
+        }
+
+        let density = self.density.map(|density| PngChunk {
+            kind: *b"pHYs",
+            data: [
+                &density.x.to_be_bytes()[..],
+                &density.y.to_be_bytes(),
+                &[density.per_meter as u8],
+            ]
+            .concat(),
+        });
+
+        let mut encoded = Vec::new();
+        for chunk in density.iter().chain(&self.png_chunks) {
+            encoded.extend_from_slice(&(chunk.data.len() as u32).to_be_bytes());
+            encoded.extend_from_slice(&chunk.kind);
+            encoded.extend_from_slice(&chunk.data);
+            let mut crc = crc32fast::Hasher::new();
+            crc.update(&chunk.kind);
+            crc.update(&chunk.data);
+            encoded.extend_from_slice(&crc.finalize().to_be_bytes());
+        }
+        png.splice(HEADER_END..HEADER_END, encoded);
+    }
+}
+
+/// Read the chunks of an encoded PNG file, stopping at the first malformed one
+pub fn read_png_chunks(png: &[u8]) -> Vec<PngChunk> {
+    let mut chunks = Vec::new();
+    let mut rest = png.strip_prefix(&PNG_SIGNATURE[..]).unwrap_or_default();
+
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let Some(data) = rest.get(8..8 + length) else {
+            break;
+        };
+        chunks.push(PngChunk {
+            kind: [rest[4], rest[5], rest[6], rest[7]],
+            data: data.to_vec(),
+        });
+        rest = rest.get(12 + length..).unwrap_or_default();
+    }
+
+    chunks
+}
+
+/// Parse the data of a PNG `pHYs` chunk
+fn parse_png_density(data: &[u8]) -> Option<PixelDensity> {
+    let data: &[u8; 9] = data.try_into().ok()?;
+    Some(PixelDensity {
+        x: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+        y: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+        per_meter: data[8] == 1,
+    })
+}
+
+/// Parse the pixel density of the JFIF header of a JPEG file
+fn parse_jfif_density(jpeg: &[u8]) -> Option<PixelDensity> {
+    // SOI marker, then an APP0 segment: marker, length, "JFIF\0", version,
+    // units, horizontal and vertical density
+    let header = jpeg.get(..18)?;
+    if header[..4] != [0xff, 0xd8, 0xff, 0xe0] || &header[6..11] != b"JFIF\0" {
+        return None;
+    }
+
+    let x = u16::from_be_bytes([header[14], header[15]]) as f64;
+    let y = u16::from_be_bytes([header[16], header[17]]) as f64;
+    let per_meter = match header[13] {
+        // Dots per inch
+        1 => 1.0 / 0.0254,
+        // Dots per centimeter
+        2 => 100.0,
+        _ => {
+            return Some(PixelDensity {
+                x: x as u32,
+                y: y as u32,
+                per_meter: false,
+            });
+        }
+    };
+    Some(PixelDensity {
+        x: (x * per_meter).round() as u32,
+        y: (y * per_meter).round() as u32,
+        per_meter: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_png_chunks_round_trip() {
+        let metadata = ImageMetadata {
+            density: Some(PixelDensity {
+                x: 11811,
+                y: 11811,
+                per_meter: true,
+            }),
+            png_chunks: vec![PngChunk {
+                kind: *b"tEXt",
+                data: b"Title\0Logo".to_vec(),
+            }],
+            ..ImageMetadata::default()
+        };
+
+        // A bare PNG: signature, IHDR and IEND
+        let mut png = Vec::new();
+        image::RgbaImage::new(1, 1)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        metadata.write_to_png(&mut png);
+
+        // The file still decodes, and the chunks read back the same
+        assert!(image::load_from_memory(&png).is_ok());
+        let mut read = ImageMetadata::default();
+        read.read_from_file_contents(&png);
+        assert_eq!(read, metadata);
+    }
+
+    #[test]
+    fn test_jfif_density() {
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10];
+        jpeg.extend_from_slice(b"JFIF\0");
+        jpeg.extend_from_slice(&[1, 1, 1, 0x01, 0x2c, 0x00, 0x48]);
+        assert_eq!(
+            parse_jfif_density(&jpeg),
+            Some(PixelDensity {
+                x: 11811,
+                y: 2835,
+                per_meter: true
+            })
+        );
+
+        // Aspect ratio only
+        jpeg[13] = 0;
+        assert_eq!(parse_jfif_density(&jpeg).map(|d| d.per_meter), Some(false));
+        assert_eq!(parse_jfif_density(b"not a jpeg"), None);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::background::{Background, NormalizedRgbImage};
+use crate::color::{BlendSpace, Color, ColorDistance, NormalizedColor, denormalize_color};
+use crate::error::{Error, Result};
+use crate::unmix::DEFAULT_COLOR_CLOSENESS_THRESHOLD;
+
+/// How pixels are unmixed from the background
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Non-strict mode without foreground colors: any color can be used,
+    /// with the minimum alpha that perfectly reconstructs each pixel
+    #[default]
+    NonStrict,
+    /// Non-strict mode with foreground colors: pixels close to a foreground
+    /// color use it, other pixels can use any color
+    NonStrictWithFg,
+    /// Strict mode: unmixing is restricted to the foreground colors
+    Strict,
+}
+
+impl Mode {
+    /// Pick the mode matching the CLI flags
+    pub fn from_flags(strict: bool, has_foreground_colors: bool) -> Self {
+        if strict {
+            Mode::Strict
+        } else if has_foreground_colors {
+            Mode::NonStrictWithFg
+        } else {
+            Mode::NonStrict
+        }
+    }
+}
+
+/// Options for removing the background of an image
+///
+/// Foreground colors must already be resolved: use
+/// [`deduce_unknown_colors`](crate::deduce::deduce_unknown_colors) to turn
+/// `auto` specifications into colors first.
+///
+/// # Example
+/// ```
+/// use bgone::{Mode, ProcessOptions};
+///
+/// let options = ProcessOptions::new([255, 255, 255])
+///     .mode(Mode::Strict)
+///     .foreground_colors(vec![[255, 0, 0]])
+///     .threshold(0.1);
+/// assert!(options.validate().is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessOptions {
+    /// Unmixing mode
+    pub mode: Mode,
+    /// Background color to remove
+    ///
+    /// With a per-pixel `background_image`, this is only used as a
+    /// representative color (e.g. for color deduction).
+    pub background_color: Color,
+    /// Background color with channels from 0.0 to 1.0, overriding
+    /// `background_color` when set
+    ///
+    /// Keeps the background of 16-bit images exact. 8-bit images are unmixed
+    /// against its 8-bit rounding, which is `background_color`.
+    pub normalized_background_color: Option<NormalizedColor>,
+    /// Background color of every pixel, with channels from 0.0 to 1.0,
+    /// overriding `background_color` when set
+    ///
+    /// Must have the same size as the processed image, otherwise processing
+    /// fails with [`Error::DimensionMismatch`].
+    pub background_image: Option<NormalizedRgbImage>,
+    /// Foreground colors to unmix against
+    pub foreground_colors: Vec<Color>,
+    /// Color similarity threshold (0.0-1.0), in the units of `distance`
+    pub threshold: f64,
+    /// How color similarity is measured
+    pub distance: ColorDistance,
+    /// Color space in which the foreground was blended over the background
+    pub blend_space: BlendSpace,
+}
+
+impl ProcessOptions {
+    /// Create options for non-strict mode without foreground colors
+    pub fn new(background_color: Color) -> Self {
+        Self {
+            mode: Mode::default(),
+            background_color,
+            normalized_background_color: None,
+            background_image: None,
+            foreground_colors: Vec::new(),
+            threshold: DEFAULT_COLOR_CLOSENESS_THRESHOLD,
+            distance: ColorDistance::default(),
+            blend_space: BlendSpace::default(),
+        }
+    }
+
+    /// Set the unmixing mode
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the background color with more than 8 bits of precision, e.g. the
+    /// background of a 16-bit image
+    ///
+    /// `background_color` is set to its 8-bit rounding.
+    pub fn normalized_background_color(mut self, color: NormalizedColor) -> Self {
+        self.normalized_background_color = Some(color);
+        self.background_color = denormalize_color(color);
+        self
+    }
+
+    /// Set a per-pixel background, such as a fitted gradient or a clean plate
+    /// (a photo of the empty background)
+    ///
+    /// Use [`normalize_image`](crate::background::normalize_image) to convert
+    /// an 8-bit or 16-bit image.
+    pub fn background_image(mut self, background_image: NormalizedRgbImage) -> Self {
+        self.background_image = Some(background_image);
+        self
+    }
+
+    /// The background behind each pixel
+    pub fn background(&self) -> Background<'_> {
+        match (&self.background_image, self.normalized_background_color) {
+            (Some(image), _) => Background::PerPixel(image),
+            (None, Some(color)) => Background::SolidNormalized(color),
+            (None, None) => Background::Solid(self.background_color),
+        }
+    }
+
+    /// Set the foreground colors
+    ///
+    /// Switches [`Mode::NonStrict`] to [`Mode::NonStrictWithFg`] when the
+    /// colors are not empty, so the options stay consistent.
+    pub fn foreground_colors(mut self, foreground_colors: Vec<Color>) -> Self {
+        if self.mode == Mode::NonStrict && !foreground_colors.is_empty() {
+            self.mode = Mode::NonStrictWithFg;
+        }
+        self.foreground_colors = foreground_colors;
+        self
+    }
+
+    /// Set the color similarity threshold
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set how color similarity is measured
+    pub fn distance(mut self, distance: ColorDistance) -> Self {
+        self.distance = distance;
+        self
+    }
+
+    /// Set the color space in which the foreground was blended over the
+    /// background: sRGB for browsers and most 2D editors, linear for 3D
+    /// renderers and linear compositors
+    pub fn blend_space(mut self, blend_space: BlendSpace) -> Self {
+        self.blend_space = blend_space;
+        self
+    }
+
+    /// Check that the options are consistent
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.threshold) {
+            return Err(Error::InvalidThreshold(self.threshold));
+        }
+
+        match self.mode {
+            Mode::Strict if self.foreground_colors.is_empty() => {
+                Err(Error::StrictModeRequiresForeground)
+            }
+            Mode::NonStrictWithFg if self.foreground_colors.is_empty() => {
+                Err(self.mode_foreground_mismatch())
+            }
+            Mode::NonStrict if !self.foreground_colors.is_empty() => {
+                Err(self.mode_foreground_mismatch())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Error for a mode that does not match the foreground colors
+    fn mode_foreground_mismatch(&self) -> Error {
+        Error::ModeForegroundMismatch {
+            mode: self.mode,
+            foreground_count: self.foreground_colors.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::normalize_image;
+
+    #[test]
+    fn test_mode_from_flags() {
+        assert_eq!(Mode::from_flags(false, false), Mode::NonStrict);
+        assert_eq!(Mode::from_flags(false, true), Mode::NonStrictWithFg);
+        assert_eq!(Mode::from_flags(true, true), Mode::Strict);
+        assert_eq!(Mode::from_flags(true, false), Mode::Strict);
+    }
+
+    #[test]
+    fn test_validate_default_options() {
+        assert!(ProcessOptions::new([255, 255, 255]).validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_strict_requires_foreground() {
+        let options = ProcessOptions::new([0, 0, 0]).mode(Mode::Strict);
+        let err = options.validate().unwrap_err();
+        assert!(matches!(err, Error::StrictModeRequiresForeground));
+        assert!(err.to_string().contains("strict mode"));
+
+        let options = options.foreground_colors(vec![[255, 0, 0]]);
+        assert!(options.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_mode_foreground_mismatch() {
+        let options = ProcessOptions::new([0, 0, 0]).mode(Mode::NonStrictWithFg);
+        assert!(matches!(
+            options.validate(),
+            Err(Error::ModeForegroundMismatch {
+                mode: Mode::NonStrictWithFg,
+                foreground_count: 0
+            })
+        ));
+
+        let options = ProcessOptions::new([0, 0, 0])
+            .foreground_colors(vec![[255, 0, 0]])
+            .mode(Mode::NonStrict);
+        assert!(matches!(
+            options.validate(),
+            Err(Error::ModeForegroundMismatch {
+                mode: Mode::NonStrict,
+                foreground_count: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_foreground_colors_switch_non_strict_mode() {
+        let options = ProcessOptions::new([0, 0, 0]).foreground_colors(vec![[255, 0, 0]]);
+        assert_eq!(options.mode, Mode::NonStrictWithFg);
+        assert!(options.validate().is_ok());
+
+        let options = ProcessOptions::new([0, 0, 0]).foreground_colors(Vec::new());
+        assert_eq!(options.mode, Mode::NonStrict);
+
+        let options = ProcessOptions::new([0, 0, 0])
+            .mode(Mode::Strict)
+            .foreground_colors(vec![[255, 0, 0]]);
+        assert_eq!(options.mode, Mode::Strict);
+    }
+
+    #[test]
+    fn test_validate_threshold_range() {
+        let options = ProcessOptions::new([0, 0, 0]);
+        assert!(options.clone().threshold(0.0).validate().is_ok());
+        assert!(options.clone().threshold(1.0).validate().is_ok());
+        assert!(options.clone().threshold(-0.1).validate().is_err());
+        assert!(matches!(
+            options.clone().threshold(1.5).validate(),
+            Err(Error::InvalidThreshold(_))
+        ));
+        assert!(options.threshold(f64::NAN).validate().is_err());
+    }
+
+    #[test]
+    fn test_background_image() {
+        let options = ProcessOptions::new([0, 0, 0]);
+        assert_eq!(options.background(), Background::Solid([0, 0, 0]));
+
+        let plate = image::RgbImage::from_pixel(2, 2, image::Rgb([10, 20, 30]));
+        let options = options.background_image(normalize_image(&plate));
+        assert_eq!(options.background().color_at(1, 1), [10, 20, 30]);
+    }
+
+    #[test]
+    fn test_normalized_background_color() {
+        let color = [0x1234 as f64 / 65535.0; 3];
+        let options = ProcessOptions::new([0, 0, 0]).normalized_background_color(color);
+        assert_eq!(options.background_color, [0x12, 0x12, 0x12]);
+        assert_eq!(options.background(), Background::SolidNormalized(color));
+    }
+}
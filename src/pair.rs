@@ -0,0 +1,214 @@
+use crate::color::{Color, NormalizedColor, normalize_color};
+use crate::composite_pixel_over_background;
+use crate::error::{Error, Result};
+use crate::progress::{NoopObserver, ProgressObserver};
+use crate::quality::ReconstructionError;
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Result of difference matting
+#[derive(Debug, Clone)]
+pub struct PairMatte {
+    /// The foreground with its exact alpha
+    pub image: RgbaImage,
+    /// How far the matte is from each input when composited back over its
+    /// background, taking the larger of the two errors for each pixel
+    ///
+    /// Pixels with a large error are pixels where the two inputs disagree,
+    /// i.e. they are not the same foreground over the two backgrounds.
+    pub disagreement: ReconstructionError,
+}
+
+/// Recover the foreground from two renders of it over different backgrounds
+///
+/// # Arguments
+/// * `first` - The foreground over `first_background`
+/// * `first_background` - The background color of the first image
+/// * `second` - The same foreground over `second_background`
+/// * `second_background` - The background color of the second image
+///
+/// # Returns
+/// The foreground with exact alpha, and how well it reproduces both inputs
+pub fn remove_background_pair(
+    first: &RgbaImage,
+    first_background: Color,
+    second: &RgbaImage,
+    second_background: Color,
+) -> Result<PairMatte> {
+    remove_background_pair_with_observer(
+        first,
+        first_background,
+        second,
+        second_background,
+        &NoopObserver,
+    )
+}
+
+/// Recover the foreground from two renders of it over different backgrounds,
+/// reporting progress to an observer
+///
+/// Since `observed = alpha * foreground + (1 - alpha) * background` holds for
+/// both images, their difference is `(1 - alpha) * (first_bg - second_bg)`,
+/// which gives alpha in closed form. The foreground color then follows from
+/// either image.
+pub fn remove_background_pair_with_observer(
+    first: &RgbaImage,
+    first_background: Color,
+    second: &RgbaImage,
+    second_background: Color,
+    observer: &dyn ProgressObserver,
+) -> Result<PairMatte> {
+    if first.dimensions() != second.dimensions() {
+        return Err(Error::DimensionMismatch {
+            expected: first.dimensions(),
+            actual: second.dimensions(),
+        });
+    }
+    if first_background == second_background {
+        return Err(Error::IdenticalPairBackgrounds(first_background));
+    }
+
+    let (width, height) = first.dimensions();
+    let total_pixels = width as u64 * height as u64;
+    observer.processing_started(total_pixels);
+
+    let pixels: Vec<_> = first.pixels().zip(second.pixels()).collect();
+    let results: Vec<([u8; 4], u8)> = pixels
+        .par_iter()
+        .inspect(|_| observer.pixels_processed(1))
+        .map(|(first_pixel, second_pixel)| {
+            let first_observed = composite_pixel_over_background(first_pixel, first_background);
+            let second_observed = composite_pixel_over_background(second_pixel, second_background);
+
+            let pixel = unmix_pair(
+                normalize_color(first_observed),
+                normalize_color(first_background),
+                normalize_color(second_observed),
+                normalize_color(second_background),
+            );
+
+            // Error of the matte against each input, in 8-bit levels
+            let rgba = Rgba(pixel);
+            let error = [
+                (first_observed, first_background),
+                (second_observed, second_background),
+            ]
+            .iter()
+            .flat_map(|&(observed, background)| {
+                let reconstructed = composite_pixel_over_background(&rgba, background);
+                (0..3).map(move |i| observed[i].abs_diff(reconstructed[i]))
+            })
+            .max()
+            .unwrap_or(0);
+
+            (pixel, error)
+        })
+        .collect();
+
+    observer.processing_finished(total_pixels);
+
+    let pixel_at = |x: u32, y: u32| &results[(y * width + x) as usize];
+    Ok(PairMatte {
+        image: RgbaImage::from_fn(width, height, |x, y| Rgba(pixel_at(x, y).0)),
+        disagreement: ReconstructionError::from_errors(GrayImage::from_fn(
+            width,
+            height,
+            |x, y| Luma([pixel_at(x, y).1]),
+        )),
+    })
+}
+
+/// Solve for the foreground color and alpha of one pixel seen over two backgrounds
+fn unmix_pair(
+    first: NormalizedColor,
+    first_background: NormalizedColor,
+    second: NormalizedColor,
+    second_background: NormalizedColor,
+) -> [u8; 4] {
+    // Least-squares estimate of (1 - alpha) over the three channels
+    let mut numerator = 0.0;
+    let mut denominator = 0.0;
+    for i in 0..3 {
+        let background_difference = first_background[i] - second_background[i];
+        numerator += (first[i] - second[i]) * background_difference;
+        denominator += background_difference * background_difference;
+    }
+    let alpha = (1.0 - numerator / denominator).clamp(0.0, 1.0);
+
+    if alpha < 0.5 / 255.0 {
+        return [0, 0, 0, 0];
+    }
+
+    // Average the foreground color recovered from both images
+    let color = [0, 1, 2].map(|i| {
+        let from_first = (first[i] - (1.0 - alpha) * first_background[i]) / alpha;
+        let from_second = (second[i] - (1.0 - alpha) * second_background[i]) / alpha;
+        (((from_first + from_second) / 2.0).clamp(0.0, 1.0) * 255.0).round() as u8
+    });
+
+    [color[0], color[1], color[2], (alpha * 255.0).round() as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Composite a pixel over a background color
+    fn over(pixel: [u8; 4], background: Color) -> Rgba<u8> {
+        let [r, g, b] = composite_pixel_over_background(&Rgba(pixel), background);
+        Rgba([r, g, b, 255])
+    }
+
+    #[test]
+    fn test_pair_recovers_translucent_pixels() {
+        let foreground = [
+            [255, 0, 0, 255],
+            [255, 128, 0, 128],
+            [0, 0, 255, 51],
+            [0, 0, 0, 0],
+        ];
+        let on_black = RgbaImage::from_fn(4, 1, |x, _| over(foreground[x as usize], [0, 0, 0]));
+        let on_white =
+            RgbaImage::from_fn(4, 1, |x, _| over(foreground[x as usize], [255, 255, 255]));
+
+        let matte =
+            remove_background_pair(&on_black, [0, 0, 0], &on_white, [255, 255, 255]).unwrap();
+
+        assert_eq!(matte.image.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(matte.image.get_pixel(3, 0)[3], 0);
+        for x in 1..3 {
+            let pixel = matte.image.get_pixel(x, 0);
+            let expected = foreground[x as usize];
+            assert!(pixel[3].abs_diff(expected[3]) <= 1, "{:?}", pixel);
+            for i in 0..3 {
+                assert!(pixel[i].abs_diff(expected[i]) <= 2, "{:?}", pixel);
+            }
+        }
+        assert!(matte.disagreement.stats(1).max <= 1);
+    }
+
+    #[test]
+    fn test_pair_reports_disagreement() {
+        // The same pixel is red on black but green on white
+        let on_black = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 255]));
+        let on_white = RgbaImage::from_pixel(1, 1, Rgba([0, 255, 0, 255]));
+
+        let matte =
+            remove_background_pair(&on_black, [0, 0, 0], &on_white, [255, 255, 255]).unwrap();
+        assert_eq!(matte.disagreement.stats(1).over_tolerance, 1);
+    }
+
+    #[test]
+    fn test_pair_errors() {
+        let small = RgbaImage::new(1, 1);
+        let large = RgbaImage::new(2, 2);
+        assert!(matches!(
+            remove_background_pair(&small, [0, 0, 0], &large, [255, 255, 255]),
+            Err(Error::DimensionMismatch { .. })
+        ));
+        assert!(matches!(
+            remove_background_pair(&small, [0, 0, 0], &small, [0, 0, 0]),
+            Err(Error::IdenticalPairBackgrounds(_))
+        ));
+    }
+}
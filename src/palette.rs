@@ -0,0 +1,611 @@
+use crate::color::{Color, parse_color};
+use crate::error::{Error, Result};
+use serde_json::{Map, Value, json};
+use std::path::Path;
+
+/// Named color of a palette
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Swatch {
+    /// Name of the swatch, possibly empty
+    ///
+    /// Design tokens are named by their path in the JSON file, joined with
+    /// dots (e.g. `color.brand.red`).
+    pub name: String,
+    /// Color of the swatch
+    pub color: Color,
+}
+
+impl Swatch {
+    /// Create a swatch
+    pub fn new(name: impl Into<String>, color: Color) -> Self {
+        Self {
+            name: name.into(),
+            color,
+        }
+    }
+}
+
+/// Palette file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+    /// GIMP palette (`.gpl`)
+    Gpl,
+    /// Adobe Swatch Exchange (`.ase`)
+    Ase,
+    /// JSON design tokens (`.json`), in the W3C Design Tokens format or as
+    /// plain `"name": "color"` entries
+    Json,
+}
+
+impl PaletteFormat {
+    /// Determine the format of a palette file from its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gpl" => Some(PaletteFormat::Gpl),
+            "ase" => Some(PaletteFormat::Ase),
+            "json" => Some(PaletteFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Read the swatches of a palette file, in file order
+///
+/// The format is determined by the file extension (see [`PaletteFormat`]).
+pub fn read_palette(path: &Path) -> Result<Vec<Swatch>> {
+    let format = palette_format(path)?;
+    let bytes = std::fs::read(path).map_err(|source| Error::PaletteIo {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    match format {
+        PaletteFormat::Gpl => std::str::from_utf8(&bytes)
+            .map_err(|_| "not valid UTF-8 text".to_string())
+            .and_then(parse_gpl),
+        PaletteFormat::Ase => parse_ase(&bytes),
+        PaletteFormat::Json => std::str::from_utf8(&bytes)
+            .map_err(|_| "not valid UTF-8 text".to_string())
+            .and_then(parse_json_tokens),
+    }
+    .map_err(|reason| Error::InvalidPalette {
+        path: path.to_path_buf(),
+        reason,
+    })
+}
+
+/// Write swatches to a palette file
+///
+/// The format is determined by the file extension (see [`PaletteFormat`]).
+pub fn write_palette(path: &Path, swatches: &[Swatch]) -> Result<()> {
+    let bytes = match palette_format(path)? {
+        PaletteFormat::Gpl => format_gpl(swatches).into_bytes(),
+        PaletteFormat::Ase => format_ase(swatches),
+        PaletteFormat::Json => format_json_tokens(swatches).into_bytes(),
+    };
+
+    std::fs::write(path, bytes).map_err(|source| Error::PaletteIo {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Pick swatches by name, in the order of the names
+///
+/// Names are matched case-insensitively. Fails with
+/// [`Error::SwatchNotFound`] if a name matches no swatch.
+pub fn filter_swatches(swatches: &[Swatch], names: &[String]) -> Result<Vec<Swatch>> {
+    names
+        .iter()
+        .map(|name| {
+            swatches
+                .iter()
+                .find(|swatch| swatch.name.eq_ignore_ascii_case(name.trim()))
+                .cloned()
+                .ok_or_else(|| Error::SwatchNotFound {
+                    name: name.clone(),
+                    available: swatches.iter().map(|swatch| swatch.name.clone()).collect(),
+                })
+        })
+        .collect()
+}
+
+/// Format of a palette file, or an error for unsupported extensions
+fn palette_format(path: &Path) -> Result<PaletteFormat> {
+    PaletteFormat::from_path(path).ok_or_else(|| Error::InvalidPalette {
+        path: path.to_path_buf(),
+        reason: "unsupported format, expected a .gpl, .ase or .json file".to_string(),
+    })
+}
+
+/// Parse a GIMP palette
+///
+/// After the `GIMP Palette` line, each color is a line of three 0-255 values
+/// followed by an optional name. `Name:` and `Columns:` headers, comments and
+/// blank lines are skipped.
+fn parse_gpl(text: &str) -> std::result::Result<Vec<Swatch>, String> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == "GIMP Palette" => {}
+        _ => return Err("missing 'GIMP Palette' header".to_string()),
+    }
+
+    let mut swatches = Vec::new();
+    for (i, line) in lines {
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("Name:")
+            || line.starts_with("Columns:")
+        {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let mut color = [0; 3];
+        for channel in &mut color {
+            *channel = parts
+                .next()
+                .and_then(|part| part.parse().ok())
+                .ok_or_else(|| format!("line {}: expected three values from 0 to 255", i + 1))?;
+        }
+        swatches.push(Swatch::new(parts.collect::<Vec<_>>().join(" "), color));
+    }
+
+    Ok(swatches)
+}
+
+/// Format swatches as a GIMP palette
+fn format_gpl(swatches: &[Swatch]) -> String {
+    let mut text = String::from("GIMP Palette\nName: bgone\n#\n");
+    for Swatch {
+        name,
+        color: [r, g, b],
+    } in swatches
+    {
+        text.push_str(&format!("{:3} {:3} {:3}\t{}\n", r, g, b, name));
+    }
+    text
+}
+
+/// ASE block type of a color entry
+const ASE_COLOR_ENTRY: u16 = 0x0001;
+
+/// Parse an Adobe Swatch Exchange file
+///
+/// RGB, gray and CMYK swatches are read (CMYK without color management);
+/// groups are flattened.
+fn parse_ase(bytes: &[u8]) -> std::result::Result<Vec<Swatch>, String> {
+    let mut reader = AseReader { bytes, offset: 0 };
+    if reader.take(4)? != b"ASEF" {
+        return Err("missing 'ASEF' signature".to_string());
+    }
+    // Version
+    reader.take(4)?;
+    let block_count = reader.u32()?;
+
+    let mut swatches = Vec::new();
+    for _ in 0..block_count {
+        let block_type = reader.u16()?;
+        let length = reader.u32()? as usize;
+        let mut block = AseReader {
+            bytes: reader.take(length)?,
+            offset: 0,
+        };
+        if block_type != ASE_COLOR_ENTRY {
+            continue;
+        }
+
+        let name = block.name()?;
+        let model = block.take(4)?;
+        let channels = match model {
+            b"RGB " => [block.f32()?, block.f32()?, block.f32()?],
+            b"Gray" => [block.f32()?; 3],
+            b"CMYK" => {
+                let [c, m, y, k] = [block.f32()?, block.f32()?, block.f32()?, block.f32()?];
+                [c, m, y].map(|channel| (1.0 - channel) * (1.0 - k))
+            }
+            _ => {
+                return Err(format!(
+                    "swatch '{}' uses the unsupported color model '{}'",
+                    name,
+                    String::from_utf8_lossy(model).trim()
+                ));
+            }
+        };
+        let color = channels.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+        swatches.push(Swatch::new(name, color));
+    }
+
+    Ok(swatches)
+}
+
+/// Format swatches as an Adobe Swatch Exchange file of RGB colors
+fn format_ase(swatches: &[Swatch]) -> Vec<u8> {
+    let mut bytes = b"ASEF".to_vec();
+    bytes.extend_from_slice(&1u16.to_be_bytes());
+    bytes.extend_from_slice(&0u16.to_be_bytes());
+    bytes.extend_from_slice(&(swatches.len() as u32).to_be_bytes());
+
+    for swatch in swatches {
+        let name: Vec<u16> = swatch.name.encode_utf16().chain([0]).collect();
+        let mut block = (name.len() as u16).to_be_bytes().to_vec();
+        block.extend(name.iter().flat_map(|unit| unit.to_be_bytes()));
+        block.extend_from_slice(b"RGB ");
+        for channel in swatch.color {
+            block.extend_from_slice(&(channel as f32 / 255.0).to_be_bytes());
+        }
+        // Normal (not global or spot) color
+        block.extend_from_slice(&2u16.to_be_bytes());
+
+        bytes.extend_from_slice(&ASE_COLOR_ENTRY.to_be_bytes());
+        bytes.extend_from_slice(&(block.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&block);
+    }
+
+    bytes
+}
+
+/// Big-endian reader over the bytes of an ASE file
+struct AseReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> AseReader<'a> {
+    fn take(&mut self, length: usize) -> std::result::Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + length)
+            .ok_or_else(|| "unexpected end of file".to_string())?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> std::result::Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> std::result::Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn f32(&mut self) -> std::result::Result<f32, String> {
+        self.u32().map(f32::from_bits)
+    }
+
+    /// Read a name: its length in UTF-16 code units, including a
+    /// terminating null, then the code units
+    fn name(&mut self) -> std::result::Result<String, String> {
+        let length = self.u16()? as usize;
+        let units = (0..length)
+            .map(|_| self.u16())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let units = units.strip_suffix(&[0]).unwrap_or(&units);
+        String::from_utf16(units).map_err(|_| "invalid swatch name".to_string())
+    }
+}
+
+/// Parse JSON design tokens
+///
+/// Tokens are objects with a `$value` (or `value`) that is a color string,
+/// a W3C color object with a `hex` member, or an alias to another token such as
+/// `{color.brand.red}`. Tokens whose `$type`, possibly
+/// inherited from a group, is not `color` are skipped, as are plain string
+/// entries that aren't colors, so a full token file can be used. Untyped
+/// tokens and strings are only colors in an explicit syntax (see
+/// [`untyped_color`]), so values like `100` or `decade` are not imported.
+fn parse_json_tokens(text: &str) -> std::result::Result<Vec<Swatch>, String> {
+    let root: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let mut swatches = Vec::new();
+    collect_tokens(&root, &root, "", None, &mut swatches)?;
+    Ok(swatches)
+}
+
+/// Collect the color tokens of a JSON value and its children
+fn collect_tokens(
+    root: &Value,
+    value: &Value,
+    name: &str,
+    inherited_type: Option<&str>,
+    swatches: &mut Vec<Swatch>,
+) -> std::result::Result<(), String> {
+    match value {
+        Value::Object(object) => {
+            let token_type = object
+                .get("$type")
+                .or_else(|| object.get("type"))
+                .and_then(Value::as_str)
+                .or(inherited_type);
+
+            if let Some(token_value) = object.get("$value").or_else(|| object.get("value")) {
+                if token_type.is_some_and(|token_type| token_type != "color") {
+                    return Ok(());
+                }
+                let color_value = resolve_alias(root, token_value).and_then(|value| {
+                    value
+                        .as_str()
+                        .or_else(|| value.get("hex").and_then(Value::as_str))
+                });
+                match (color_value, token_type) {
+                    (Some(value), Some(_)) => match parse_color(value) {
+                        Ok(color) => swatches.push(Swatch::new(name, color)),
+                        Err(error) => return Err(format!("token '{}': {}", name, error)),
+                    },
+                    (None, Some(_)) => {
+                        return Err(format!("token '{}' has no color value", name));
+                    }
+                    // Untyped tokens may be anything
+                    (Some(value), None) => {
+                        if let Some(color) = untyped_color(value) {
+                            swatches.push(Swatch::new(name, color));
+                        }
+                    }
+                    (None, None) => {}
+                }
+                return Ok(());
+            }
+
+            for (key, child) in object.iter().filter(|(key, _)| !key.starts_with('$')) {
+                collect_tokens(root, child, &token_name(name, key), token_type, swatches)?;
+            }
+        }
+        Value::Array(values) => {
+            for (i, child) in values.iter().enumerate() {
+                collect_tokens(
+                    root,
+                    child,
+                    &token_name(name, &i.to_string()),
+                    inherited_type,
+                    swatches,
+                )?;
+            }
+        }
+        Value::String(string) => {
+            let color = match inherited_type {
+                Some("color") => parse_color(string).ok(),
+                Some(_) => None,
+                None => untyped_color(string),
+            };
+            if let Some(color) = color {
+                swatches.push(Swatch::new(name, color));
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Parse the value of an untyped token, which may be anything
+///
+/// Only explicit colors are accepted: `#` hex colors, functional notations
+/// like `rgb()` and CSS named colors. Bare hex digits such as `fff`, `100` or
+/// `decade` are more likely numbers or words than colors.
+fn untyped_color(value: &str) -> Option<Color> {
+    if value.trim().chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    parse_color(value).ok()
+}
+
+/// Follow `{group.token}` aliases to the value they refer to
+///
+/// Returns `None` for aliases to missing tokens, or chains of more than 16
+/// aliases (which may be cycles).
+fn resolve_alias<'a>(root: &'a Value, mut value: &'a Value) -> Option<&'a Value> {
+    for _ in 0..16 {
+        let Some(path) = value
+            .as_str()
+            .and_then(|value| value.strip_prefix('{')?.strip_suffix('}'))
+        else {
+            return Some(value);
+        };
+        let token = path
+            .split('.')
+            .try_fold(root, |group, key| group.get(key))?;
+        value = token.get("$value").or_else(|| token.get("value"))?;
+    }
+    None
+}
+
+/// Name of a token within a group
+fn token_name(group: &str, key: &str) -> String {
+    if group.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", group, key)
+    }
+}
+
+/// Format swatches as W3C design tokens
+///
+/// Unnamed swatches are named `color-N`, and repeated names get a `-N`
+/// suffix, since token names must be unique.
+fn format_json_tokens(swatches: &[Swatch]) -> String {
+    let mut tokens = Map::new();
+    for (i, swatch) in swatches.iter().enumerate() {
+        let mut name = match swatch.name.as_str() {
+            "" => format!("color-{}", i + 1),
+            name => name.to_string(),
+        };
+        if tokens.contains_key(&name) {
+            name = format!("{}-{}", name, i + 1);
+        }
+        let [r, g, b] = swatch.color;
+        tokens.insert(
+            name,
+            json!({
+                "$type": "color",
+                "$value": format!("#{:02x}{:02x}{:02x}", r, g, b),
+            }),
+        );
+    }
+    let mut text = serde_json::to_string_pretty(&tokens).unwrap_or_default();
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brand_swatches() -> Vec<Swatch> {
+        vec![
+            Swatch::new("Brand Red", [230, 30, 40]),
+            Swatch::new("Ink", [20, 20, 30]),
+        ]
+    }
+
+    #[test]
+    fn test_gpl_round_trip() {
+        let text = "GIMP Palette\nName: Brand\nColumns: 2\n# comment\n\n230  30  40\tBrand Red\n 20 20 30 Ink\n0 0 0\n";
+        let mut expected = brand_swatches();
+        expected.push(Swatch::new("", [0, 0, 0]));
+        assert_eq!(parse_gpl(text).unwrap(), expected);
+        assert_eq!(parse_gpl(&format_gpl(&expected)).unwrap(), expected);
+
+        assert!(parse_gpl("not a palette").is_err());
+        assert!(parse_gpl("GIMP Palette\n255 0\n").is_err());
+        assert!(parse_gpl("GIMP Palette\n256 0 0 Red\n").is_err());
+    }
+
+    #[test]
+    fn test_ase_round_trip() {
+        let swatches = brand_swatches();
+        assert_eq!(parse_ase(&format_ase(&swatches)).unwrap(), swatches);
+        assert!(parse_ase(b"ASEF\0\x01\0\0\0\0\0\x01").is_err());
+    }
+
+    #[test]
+    fn test_ase_groups_and_models() {
+        let mut bytes = b"ASEF\0\x01\0\0\0\0\0\x04".to_vec();
+        // Group start named "G", then a gray and a CMYK swatch, then group end
+        bytes.extend_from_slice(&[0xc0, 0x01, 0, 0, 0, 6, 0, 2, 0, b'G', 0, 0]);
+        for (name, model, channels) in [
+            ("Gray", b"Gray", vec![0.5f32]),
+            ("Cyan", b"CMYK", vec![1.0, 0.0, 0.0, 0.0]),
+        ] {
+            let mut block = vec![0, name.len() as u8 + 1];
+            block.extend(name.bytes().flat_map(|byte| [0, byte]));
+            block.extend_from_slice(&[0, 0]);
+            block.extend_from_slice(model);
+            block.extend(channels.iter().flat_map(|channel| channel.to_be_bytes()));
+            block.extend_from_slice(&[0, 2]);
+            bytes.extend_from_slice(&[0, 1]);
+            bytes.extend_from_slice(&(block.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&block);
+        }
+        bytes.extend_from_slice(&[0xc0, 0x02, 0, 0, 0, 0]);
+
+        assert_eq!(
+            parse_ase(&bytes).unwrap(),
+            vec![
+                Swatch::new("Gray", [128, 128, 128]),
+                Swatch::new("Cyan", [0, 255, 255]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_tokens() {
+        let text = r##"{
+            "color": {
+                "$type": "color",
+                "brand": {
+                    "red": { "$value": "#e61e28" },
+                    "ink": { "$value": { "colorSpace": "srgb", "hex": "#14141e" } }
+                },
+                "text": { "$value": "{color.brand.ink}" }
+            },
+            "spacing": { "small": { "$type": "dimension", "$value": "4px" } },
+            "legacy": { "accent": { "value": "rgb(0 128 255)" } },
+            "font": "Inter",
+            "plain": "teal"
+        }"##;
+        assert_eq!(
+            parse_json_tokens(text).unwrap(),
+            vec![
+                Swatch::new("color.brand.red", [230, 30, 40]),
+                Swatch::new("color.brand.ink", [20, 20, 30]),
+                Swatch::new("color.text", [20, 20, 30]),
+                Swatch::new("legacy.accent", [0, 128, 255]),
+                Swatch::new("plain", [0, 128, 128]),
+            ]
+        );
+
+        // A color token that isn't a color is an error
+        assert!(parse_json_tokens(r#"{"red": {"$type": "color", "$value": "nope"}}"#).is_err());
+        assert!(parse_json_tokens(r#"{"red": {"$type": "color", "$value": "{blue}"}}"#).is_err());
+        assert!(parse_json_tokens("not json").is_err());
+
+        let swatches = brand_swatches();
+        assert_eq!(
+            parse_json_tokens(&format_json_tokens(&swatches)).unwrap(),
+            swatches
+        );
+
+        // Token names are made unique
+        let swatches = [
+            Swatch::new("", [0, 0, 0]),
+            Swatch::new("Ink", [20, 20, 30]),
+            Swatch::new("Ink", [30, 30, 40]),
+        ];
+        let names: Vec<_> = parse_json_tokens(&format_json_tokens(&swatches))
+            .unwrap()
+            .into_iter()
+            .map(|swatch| swatch.name)
+            .collect();
+        assert_eq!(names, ["color-1", "Ink", "Ink-3"]);
+    }
+
+    #[test]
+    fn test_json_tokens_skip_values_that_look_like_hex() {
+        let text = r##"{
+            "size": { "$type": "dimension", "small": "100", "icon": "fff", "red": "red" },
+            "words": { "decade": "decade", "beef": { "$value": "beef" } },
+            "number": "100",
+            "explicit": { "hex": "#fff", "name": "red", "function": "rgb(0 0 255)" },
+            "color": { "$type": "color", "short": "fff" }
+        }"##;
+        assert_eq!(
+            parse_json_tokens(text).unwrap(),
+            vec![
+                Swatch::new("explicit.hex", [255, 255, 255]),
+                Swatch::new("explicit.name", [255, 0, 0]),
+                Swatch::new("explicit.function", [0, 0, 255]),
+                Swatch::new("color.short", [255, 255, 255]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter_swatches() {
+        let swatches = brand_swatches();
+        let names = ["ink".to_string(), "brand red".to_string()];
+        assert_eq!(
+            filter_swatches(&swatches, &names).unwrap(),
+            vec![swatches[1].clone(), swatches[0].clone()]
+        );
+
+        let err = filter_swatches(&swatches, &["Blue".to_string()]).unwrap_err();
+        assert!(matches!(err, Error::SwatchNotFound { .. }));
+        assert!(err.to_string().contains("Brand Red, Ink"));
+    }
+
+    #[test]
+    fn test_palette_format_from_path() {
+        assert_eq!(
+            PaletteFormat::from_path(Path::new("brand.GPL")),
+            Some(PaletteFormat::Gpl)
+        );
+        assert_eq!(PaletteFormat::from_path(Path::new("brand.txt")), None);
+        assert!(matches!(
+            read_palette(Path::new("brand.txt")),
+            Err(Error::InvalidPalette { .. })
+        ));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::color::{Color, NormalizedColor, denormalize_color};
+use crate::error::{Error, Result};
+use crate::pixel::normalized_pixel;
+use image::{DynamicImage, GenericImageView};
+
+/// Parse a pixel position given as "x,y", e.g. "120,45"
+pub fn parse_pixel_position(input: &str) -> Result<(u32, u32)> {
+    let invalid = |reason: &str| Error::InvalidPosition {
+        input: input.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let (x, y) = input
+        .split_once(',')
+        .ok_or_else(|| invalid("expected x,y"))?;
+    let coordinate = |value: &str, name: &str| {
+        value
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| invalid(&format!("invalid {} coordinate", name)))
+    };
+
+    Ok((coordinate(x, "x")?, coordinate(y, "y")?))
+}
+
+/// Read a color from an image, averaging the `size`×`size` square of pixels
+/// centered on a position
+///
+/// The square is clipped to the image. Translucent pixels are composited over
+/// black, like in background detection.
+///
+/// # Arguments
+/// * `img` - The image to pick the color from
+/// * `position` - The pixel at the center of the square
+/// * `size` - The width and height of the square (1 reads a single pixel)
+pub fn pick_color(img: &DynamicImage, position: (u32, u32), size: u32) -> Result<Color> {
+    pick_color_normalized(img, position, size).map(denormalize_color)
+}
+
+/// Read a color from an image like [`pick_color`], without rounding it to 8
+/// bits
+///
+/// Only the pixels of the square are read, at the precision of the image, so
+/// the color picked from a 16-bit image can be between two 8-bit levels.
+pub fn pick_color_normalized(
+    img: &DynamicImage,
+    position: (u32, u32),
+    size: u32,
+) -> Result<NormalizedColor> {
+    let (width, height) = img.dimensions();
+    let (x, y) = position;
+    if x >= width || y >= height {
+        return Err(Error::PositionOutOfBounds {
+            position,
+            dimensions: (width, height),
+        });
+    }
+
+    // The square spans `size` pixels starting `(size - 1) / 2` before the center
+    let span = |center: u32, limit: u32| {
+        let start = center as i64 - (size.max(1) as i64 - 1) / 2;
+        let end = start + size.max(1) as i64;
+        start.max(0) as u32..end.min(limit as i64) as u32
+    };
+    let columns = span(x, width);
+    let rows = span(y, height);
+
+    let mut sum = [0.0; 3];
+    let mut count = 0.0;
+    for row in rows {
+        for column in columns.clone() {
+            let pixel = normalized_pixel(img, column, row);
+            for (channel, total) in sum.iter_mut().enumerate() {
+                *total += pixel[channel] * pixel[3];
+            }
+            count += 1.0;
+        }
+    }
+
+    Ok(sum.map(|total| total / count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_parse_pixel_position() {
+        assert_eq!(parse_pixel_position("120,45").unwrap(), (120, 45));
+        assert_eq!(parse_pixel_position("0, 7").unwrap(), (0, 7));
+        assert!(parse_pixel_position("120").is_err());
+        assert!(parse_pixel_position("-1,0").is_err());
+        assert!(matches!(
+            parse_pixel_position("a,b"),
+            Err(Error::InvalidPosition { .. })
+        ));
+    }
+
+    #[test]
+    fn test_pick_single_pixel() {
+        let mut img = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        img.put_pixel(3, 4, Rgba([255, 0, 0, 255]));
+        let img = DynamicImage::ImageRgba8(img);
+
+        assert_eq!(pick_color(&img, (3, 4), 1).unwrap(), [255, 0, 0]);
+        assert_eq!(pick_color(&img, (0, 0), 1).unwrap(), [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_pick_averages_neighborhood() {
+        // Alternating black and white columns average to gray
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(10, 10, |x, _y| {
+            if x % 2 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([200, 200, 200, 255])
+            }
+        }));
+
+        // 3x3 around a white column: two black columns, one white
+        assert_eq!(pick_color(&img, (5, 4), 3).unwrap(), [67, 67, 67]);
+        // 2x2 in a corner: one black and one white column
+        assert_eq!(pick_color(&img, (0, 0), 2).unwrap(), [100, 100, 100]);
+        // Clipped at the corner: columns 0 and 1 only
+        assert_eq!(pick_color(&img, (0, 0), 3).unwrap(), [100, 100, 100]);
+    }
+
+    #[test]
+    fn test_pick_keeps_16_bit_precision() {
+        // Between the 8-bit levels 0x12 and 0x13
+        let level = 0x1280;
+        let img = image::ImageBuffer::from_pixel(4, 4, Rgba([level, level, level, 65535u16]));
+        let img = DynamicImage::ImageRgba16(img);
+
+        let color = pick_color_normalized(&img, (1, 1), 3).unwrap();
+        assert_eq!(color, [level as f64 / 65535.0; 3]);
+        assert_eq!(pick_color(&img, (1, 1), 3).unwrap(), [0x12; 3]);
+    }
+
+    #[test]
+    fn test_pick_out_of_bounds() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::new(10, 5));
+        assert!(matches!(
+            pick_color(&img, (10, 0), 1),
+            Err(Error::PositionOutOfBounds {
+                position: (10, 0),
+                dimensions: (10, 5)
+            })
+        ));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::color::{BlendSpace, NormalizedColor, denormalize_color, normalize_color};
+use crate::composite_pixel_in_blend_space;
+use crate::unmixer::PixelUnmixer;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel, Primitive, Rgba};
+
+/// 16-bit RGBA image
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+/// Read a pixel of an image at the precision of the image, with channels from
+/// 0.0 to 1.0
+///
+/// Only the pixel is read, without converting the whole image. Floating-point
+/// channels are clamped to 0.0-1.0.
+pub(crate) fn normalized_pixel(img: &DynamicImage, x: u32, y: u32) -> [f64; 4] {
+    match img {
+        DynamicImage::ImageLuma16(image) => normalized_buffer_pixel(image, x, y),
+        DynamicImage::ImageLumaA16(image) => normalized_buffer_pixel(image, x, y),
+        DynamicImage::ImageRgb16(image) => normalized_buffer_pixel(image, x, y),
+        DynamicImage::ImageRgba16(image) => normalized_buffer_pixel(image, x, y),
+        DynamicImage::ImageRgb32F(image) => normalized_buffer_pixel(image, x, y),
+        DynamicImage::ImageRgba32F(image) => normalized_buffer_pixel(image, x, y),
+        _ => img.get_pixel(x, y).0.map(|channel| channel as f64 / 255.0),
+    }
+}
+
+/// Read a pixel of an image buffer with channels from 0.0 to 1.0
+fn normalized_buffer_pixel<P>(image: &ImageBuffer<P, Vec<P::Subpixel>>, x: u32, y: u32) -> [f64; 4]
+where
+    P: Pixel,
+    P::Subpixel: Into<f64>,
+{
+    let max: f64 = P::Subpixel::DEFAULT_MAX_VALUE.into();
+    let pixel = image.get_pixel(x, y).to_rgba();
+    [0, 1, 2, 3].map(|i| (pixel[i].into() / max).clamp(0.0, 1.0))
+}
+
+/// RGBA pixel type that backgrounds can be removed from
+///
+/// Implemented for 8-bit pixels, which are unmixed with
+/// [`PixelUnmixer::unmix`], and 16-bit pixels, which are unmixed at full
+/// precision with [`PixelUnmixer::unmix_normalized`] so that soft gradients
+/// keep all of their levels. Background colors have channels from 0.0 to 1.0,
+/// so the background of a 16-bit image does not have to be an 8-bit level;
+/// 8-bit pixels are unmixed against its 8-bit rounding.
+pub trait UnmixPixel: Pixel + Send + Sync {
+    /// Alpha of the pixel, from 0.0 to 1.0
+    fn normalized_alpha(&self) -> f64;
+
+    /// Composite the pixel over a background color in a blend space
+    ///
+    /// Returns the opaque color the pixel looks like, with channels from 0.0
+    /// to 1.0.
+    fn composite_over(
+        &self,
+        background: NormalizedColor,
+        blend_space: BlendSpace,
+    ) -> NormalizedColor;
+
+    /// Unmix the pixel from its background color
+    ///
+    /// Translucent pixels are first composited over the background in the
+    /// blend space of the unmixer.
+    fn unmix(&self, background: NormalizedColor, unmixer: &dyn PixelUnmixer) -> Self;
+}
+
+impl UnmixPixel for Rgba<u8> {
+    fn normalized_alpha(&self) -> f64 {
+        self[3] as f64 / 255.0
+    }
+
+    fn composite_over(
+        &self,
+        background: NormalizedColor,
+        blend_space: BlendSpace,
+    ) -> NormalizedColor {
+        normalize_color(composite_pixel_in_blend_space(
+            self,
+            denormalize_color(background),
+            blend_space,
+        ))
+    }
+
+    fn unmix(&self, background: NormalizedColor, unmixer: &dyn PixelUnmixer) -> Self {
+        let background = denormalize_color(background);
+        let observed =
+            composite_pixel_in_blend_space(self, background, unmixer.assumed_blend_space());
+        Rgba(unmixer.unmix(observed, background))
+    }
+}
+
+impl UnmixPixel for Rgba<u16> {
+    fn normalized_alpha(&self) -> f64 {
+        self[3] as f64 / 65535.0
+    }
+
+    fn composite_over(
+        &self,
+        background: NormalizedColor,
+        blend_space: BlendSpace,
+    ) -> NormalizedColor {
+        let alpha = self.normalized_alpha();
+        let foreground = blend_space.decode([0, 1, 2].map(|i| self[i] as f64 / 65535.0));
+        let background = blend_space.decode(background);
+        blend_space.encode([0, 1, 2].map(|i| foreground[i] * alpha + background[i] * (1.0 - alpha)))
+    }
+
+    fn unmix(&self, background: NormalizedColor, unmixer: &dyn PixelUnmixer) -> Self {
+        let observed = self.composite_over(background, unmixer.assumed_blend_space());
+        let pixel = unmixer.unmix_normalized(observed, background);
+        Rgba(pixel.map(|channel| (channel * 65535.0).round().clamp(0.0, 65535.0) as u16))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unmixer::NonStrictUnmixer;
+
+    #[test]
+    fn test_normalized_pixel() {
+        let image = Rgba16Image::from_pixel(2, 2, Rgba([0x1234, 0, 65535, 32768]));
+        let pixel = normalized_pixel(&DynamicImage::ImageRgba16(image), 1, 1);
+        assert_eq!(pixel[0], 0x1234 as f64 / 65535.0);
+        assert_eq!(pixel[2], 1.0);
+
+        let image = image::RgbImage::from_pixel(1, 1, image::Rgb([51, 0, 255]));
+        let pixel = normalized_pixel(&DynamicImage::ImageRgb8(image), 0, 0);
+        assert_eq!(pixel, [0.2, 0.0, 1.0, 1.0]);
+
+        let image = image::Rgb32FImage::from_pixel(1, 1, image::Rgb([0.5, 2.0, -1.0]));
+        let pixel = normalized_pixel(&DynamicImage::ImageRgb32F(image), 0, 0);
+        assert_eq!(pixel, [0.5, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_16_bit_composite() {
+        let pixel = Rgba([65535u16, 0, 0, 32768]);
+        let observed = pixel.composite_over([1.0, 1.0, 1.0], BlendSpace::Srgb);
+        let expected = 1.0 - 32768.0 / 65535.0;
+        assert!((observed[0] - 1.0).abs() < 1e-12);
+        assert!((observed[1] - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_16_bit_unmix_keeps_precision() {
+        // Neighbouring 16-bit levels of a dim red glow on black stay distinct,
+        // even though they round to the same 8-bit level
+        let unmixer = NonStrictUnmixer::default();
+        let dim = Rgba([300u16, 0, 0, 65535]).unmix([0.0; 3], &unmixer);
+        let dimmer = Rgba([299u16, 0, 0, 65535]).unmix([0.0; 3], &unmixer);
+
+        assert_eq!(dim[0], 65535);
+        assert_ne!(dim[3], dimmer[3]);
+        assert!(dim[3].abs_diff(300) <= 1, "{:?}", dim);
+    }
+}
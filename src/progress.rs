@@ -0,0 +1,53 @@
+use crate::color::Color;
+use std::path::Path;
+
+/// Receives progress events while an image is processed
+///
+/// Every method has an empty default implementation, so implementors only
+/// override the events they care about. Pixel events are reported from the
+/// worker threads, which is why observers must be `Sync`.
+pub trait ProgressObserver: Sync {
+    /// An image is about to be loaded
+    fn loading(&self, _path: &Path) {}
+
+    /// An image was loaded
+    fn loaded(&self, _path: &Path, _width: u32, _height: u32) {}
+
+    /// Background color detection started
+    fn detecting_background(&self) {}
+
+    /// The background color was detected
+    fn background_detected(&self, _color: Color) {}
+
+    /// Deduction of unknown foreground colors started
+    fn deduction_started(&self, _unique_colors: usize) {}
+
+    /// Some candidate color combinations were evaluated during deduction
+    fn deduction_progress(&self, _evaluated: usize, _total: usize) {}
+
+    /// Unknown foreground colors were deduced
+    fn deduction_finished(&self, _deduced: &[Color]) {}
+
+    /// Pixel processing started
+    fn processing_started(&self, _total_pixels: u64) {}
+
+    /// Some pixels were processed
+    fn pixels_processed(&self, _count: u64) {}
+
+    /// All pixels were processed
+    fn processing_finished(&self, _total_pixels: u64) {}
+
+    /// The output image is about to be saved
+    fn saving(&self, _path: &Path) {}
+
+    /// The output image was saved
+    fn saved(&self, _path: &Path) {}
+}
+
+/// Observer that ignores every event
+///
+/// This is what the library uses when no observer is given.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopObserver;
+
+impl ProgressObserver for NoopObserver {}
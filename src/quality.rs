@@ -0,0 +1,368 @@
+use crate::background::Background;
+use crate::color::{BlendSpace, Color};
+use crate::error::{Error, Result};
+use crate::pixel::UnmixPixel;
+use crate::unmixer::{PixelUnmixer, StrictUnmixer};
+use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Default number of 8-bit levels a reconstructed pixel may differ from the input
+///
+/// Output colors and alpha are quantized to 8 bits, so a perfect unmixing can
+/// still be off by one level once composited back over the background.
+pub const DEFAULT_ERROR_TOLERANCE: u8 = 1;
+
+/// Per-pixel reconstruction error of a processed image
+///
+/// The error of a pixel is the largest channel difference, in 8-bit levels,
+/// between the input pixel and the output pixel composited back over the
+/// background color. Both are composited the same way translucent input pixels
+/// are before unmixing, so a perfect reconstruction has an error of 0. Errors
+/// of 16-bit images are also measured in 8-bit levels.
+#[derive(Debug, Clone)]
+pub struct ReconstructionError {
+    errors: GrayImage,
+}
+
+/// Summary statistics of a [`ReconstructionError`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorStats {
+    /// Largest pixel error
+    pub max: u8,
+    /// Mean pixel error
+    pub mean: f64,
+    /// 99th percentile of the pixel errors
+    pub p99: u8,
+    /// Tolerance used to count pixels over tolerance
+    pub tolerance: u8,
+    /// Number of pixels with an error greater than the tolerance
+    pub over_tolerance: u64,
+    /// Total number of pixels
+    pub pixel_count: u64,
+}
+
+impl ReconstructionError {
+    /// Compute the reconstruction error of an output image against its input
+    ///
+    /// # Arguments
+    /// * `input` - The image before background removal
+    /// * `output` - The image after background removal
+    /// * `background_color` - The background color that was removed
+    pub fn compute<P: UnmixPixel>(
+        input: &ImageBuffer<P, Vec<P::Subpixel>>,
+        output: &ImageBuffer<P, Vec<P::Subpixel>>,
+        background_color: Color,
+    ) -> Result<Self> {
+        Self::compute_with_background(input, output, background_color.into(), BlendSpace::Srgb)
+    }
+
+    /// Compute the reconstruction error of an output image against its input,
+    /// with a background color per pixel, compositing in a blend space
+    pub fn compute_with_background<P: UnmixPixel>(
+        input: &ImageBuffer<P, Vec<P::Subpixel>>,
+        output: &ImageBuffer<P, Vec<P::Subpixel>>,
+        background: Background<'_>,
+        blend_space: BlendSpace,
+    ) -> Result<Self> {
+        if input.dimensions() != output.dimensions() {
+            return Err(Error::DimensionMismatch {
+                expected: input.dimensions(),
+                actual: output.dimensions(),
+            });
+        }
+        background.check_dimensions(input.dimensions())?;
+
+        let errors = GrayImage::from_fn(input.width(), input.height(), |x, y| {
+            let background_color = background.normalized_color_at(x, y);
+            let observed = input
+                .get_pixel(x, y)
+                .composite_over(background_color, blend_space);
+            let reconstructed = output
+                .get_pixel(x, y)
+                .composite_over(background_color, blend_space);
+            let error = (0..3)
+                .map(|i| ((observed[i] - reconstructed[i]).abs() * 255.0).round() as u8)
+                .max()
+                .unwrap_or(0);
+            Luma([error])
+        });
+
+        Ok(Self { errors })
+    }
+
+    /// Wrap already computed per-pixel errors
+    pub(crate) fn from_errors(errors: GrayImage) -> Self {
+        Self { errors }
+    }
+
+    /// Error of every pixel, in 8-bit levels
+    pub fn errors(&self) -> &GrayImage {
+        &self.errors
+    }
+
+    /// Summarize the errors, counting pixels with an error above `tolerance`
+    pub fn stats(&self, tolerance: u8) -> ErrorStats {
+        let mut histogram = [0u64; 256];
+        for pixel in self.errors.pixels() {
+            histogram[pixel[0] as usize] += 1;
+        }
+
+        let pixel_count: u64 = histogram.iter().sum();
+        let total_error: u64 = histogram
+            .iter()
+            .enumerate()
+            .map(|(error, &count)| error as u64 * count)
+            .sum();
+        let max = histogram.iter().rposition(|&count| count > 0).unwrap_or(0) as u8;
+        let over_tolerance = histogram[tolerance as usize + 1..].iter().sum();
+
+        // Smallest error that at least 99% of the pixels are at or below
+        let p99_rank = (pixel_count as f64 * 0.99).ceil() as u64;
+        let mut cumulative = 0;
+        let p99 = histogram
+            .iter()
+            .position(|&count| {
+                cumulative += count;
+                cumulative >= p99_rank
+            })
+            .unwrap_or(0) as u8;
+
+        ErrorStats {
+            max,
+            mean: if pixel_count > 0 {
+                total_error as f64 / pixel_count as f64
+            } else {
+                0.0
+            },
+            p99,
+            tolerance,
+            over_tolerance,
+            pixel_count,
+        }
+    }
+
+    /// Check that no pixel has an error above `tolerance`
+    ///
+    /// # Returns
+    /// The error statistics, or [`Error::ReconstructionExceedsTolerance`] if
+    /// any pixel is over tolerance
+    pub fn verify(&self, tolerance: u8) -> Result<ErrorStats> {
+        let stats = self.stats(tolerance);
+        if stats.over_tolerance > 0 {
+            return Err(Error::ReconstructionExceedsTolerance(stats));
+        }
+
+        Ok(stats)
+    }
+
+    /// Grayscale error map, scaled so the largest error is white
+    pub fn grayscale_map(&self) -> GrayImage {
+        let scale = self.scale();
+        GrayImage::from_fn(self.errors.width(), self.errors.height(), |x, y| {
+            let value = self.errors.get_pixel(x, y)[0] as f64 * scale;
+            Luma([(value * 255.0).round() as u8])
+        })
+    }
+
+    /// Heatmap error map, from black (no error) through blue, green and yellow
+    /// to red (largest error)
+    pub fn heatmap(&self) -> RgbImage {
+        let scale = self.scale();
+        RgbImage::from_fn(self.errors.width(), self.errors.height(), |x, y| {
+            let error = self.errors.get_pixel(x, y)[0];
+            if error == 0 {
+                Rgb([0, 0, 0])
+            } else {
+                heatmap_color(error as f64 * scale)
+            }
+        })
+    }
+
+    /// Factor mapping errors to 0.0-1.0, with the largest error at 1.0
+    fn scale(&self) -> f64 {
+        let max = self.errors.pixels().map(|p| p[0]).max().unwrap_or(0);
+        if max == 0 { 0.0 } else { 1.0 / max as f64 }
+    }
+}
+
+/// Summary of the solver residuals of an image unmixed in strict mode
+///
+/// The residual of a pixel is the distance between its color and the closest
+/// mix of the foreground colors over its background (see
+/// [`StrictUnmixer::residual`]). Unlike the reconstruction error, it is not
+/// affected by rounding the output, and it is only above 0.0 for colors the
+/// foreground colors can't reproduce.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResidualStats {
+    /// Largest pixel residual
+    pub max: f64,
+    /// Mean pixel residual
+    pub mean: f64,
+}
+
+impl ResidualStats {
+    /// Compute the solver residuals of an image unmixed by a strict unmixer
+    ///
+    /// Translucent pixels are composited over their background first, as they
+    /// are before unmixing.
+    pub fn compute<P: UnmixPixel>(
+        image: &ImageBuffer<P, Vec<P::Subpixel>>,
+        background: Background<'_>,
+        unmixer: &StrictUnmixer,
+    ) -> Result<Self> {
+        background.check_dimensions(image.dimensions())?;
+
+        // Images have far fewer distinct colors than pixels, so each color is
+        // only solved once
+        let blend_space = unmixer.assumed_blend_space();
+        let mut counts: HashMap<_, u64> = HashMap::new();
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let background = background.normalized_color_at(x, y);
+            let observed = pixel.composite_over(background, blend_space);
+            *counts
+                .entry((observed.map(f64::to_bits), background.map(f64::to_bits)))
+                .or_default() += 1;
+        }
+
+        let residuals: Vec<(f64, u64)> = counts
+            .into_par_iter()
+            .map(|((observed, background), count)| {
+                let residual =
+                    unmixer.residual(observed.map(f64::from_bits), background.map(f64::from_bits));
+                (residual, count)
+            })
+            .collect();
+
+        let pixel_count: u64 = residuals.iter().map(|&(_, count)| count).sum();
+        let total: f64 = residuals
+            .iter()
+            .map(|&(residual, count)| residual * count as f64)
+            .sum();
+        Ok(Self {
+            max: residuals
+                .iter()
+                .map(|&(residual, _)| residual)
+                .fold(0.0, f64::max),
+            mean: if pixel_count > 0 {
+                total / pixel_count as f64
+            } else {
+                0.0
+            },
+        })
+    }
+}
+
+/// Map a value in 0.0-1.0 to a blue-green-yellow-red color ramp
+fn heatmap_color(value: f64) -> Rgb<u8> {
+    const STOPS: [[f64; 3]; 4] = [
+        [0.0, 0.0, 1.0], // Blue
+        [0.0, 1.0, 0.0], // Green
+        [1.0, 1.0, 0.0], // Yellow
+        [1.0, 0.0, 0.0], // Red
+    ];
+
+    let position = value.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let index = (position.floor() as usize).min(STOPS.len() - 2);
+    let t = position - index as f64;
+    let [from, to] = [STOPS[index], STOPS[index + 1]];
+
+    Rgb([
+        ((from[0] + (to[0] - from[0]) * t) * 255.0).round() as u8,
+        ((from[1] + (to[1] - from[1]) * t) * 255.0).round() as u8,
+        ((from[2] + (to[2] - from[2]) * t) * 255.0).round() as u8,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_perfect_reconstruction() {
+        let input = RgbaImage::from_pixel(4, 4, Rgba([255, 127, 127, 255]));
+        // 50% red over white
+        let output = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 128]));
+
+        let error = ReconstructionError::compute(&input, &output, [255, 255, 255]).unwrap();
+        let stats = error.stats(0);
+        assert_eq!(stats.max, 0);
+        assert_eq!(stats.mean, 0.0);
+        assert_eq!(stats.over_tolerance, 0);
+        assert_eq!(stats.pixel_count, 16);
+    }
+
+    #[test]
+    fn test_error_stats() {
+        let input = RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 255]));
+        let mut output = RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 0]));
+        // One pixel is off by 10 levels, one by 2
+        output.put_pixel(0, 0, Rgba([10, 0, 0, 255]));
+        output.put_pixel(1, 0, Rgba([0, 2, 0, 255]));
+
+        let error = ReconstructionError::compute(&input, &output, [0, 0, 0]).unwrap();
+        let stats = error.stats(1);
+        assert_eq!(stats.max, 10);
+        assert!((stats.mean - 0.12).abs() < 1e-9);
+        assert_eq!(stats.p99, 2);
+        assert_eq!(stats.over_tolerance, 2);
+        assert_eq!(error.stats(2).over_tolerance, 1);
+    }
+
+    #[test]
+    fn test_verify() {
+        let input = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 255]));
+        let mut output = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 0]));
+        output.put_pixel(1, 0, Rgba([3, 0, 0, 255]));
+
+        let error = ReconstructionError::compute(&input, &output, [0, 0, 0]).unwrap();
+        assert_eq!(error.verify(3).unwrap().max, 3);
+        match error.verify(2) {
+            Err(Error::ReconstructionExceedsTolerance(stats)) => {
+                assert_eq!(stats.over_tolerance, 1);
+            }
+            other => panic!("Expected a tolerance error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_error_maps() {
+        let input = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 255]));
+        let mut output = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 0]));
+        output.put_pixel(1, 0, Rgba([4, 0, 0, 255]));
+
+        let error = ReconstructionError::compute(&input, &output, [0, 0, 0]).unwrap();
+
+        let gray = error.grayscale_map();
+        assert_eq!(gray.get_pixel(0, 0)[0], 0);
+        assert_eq!(gray.get_pixel(1, 0)[0], 255);
+
+        let heatmap = error.heatmap();
+        assert_eq!(heatmap.get_pixel(0, 0), &Rgb([0, 0, 0]));
+        assert_eq!(heatmap.get_pixel(1, 0), &Rgb([255, 0, 0]));
+    }
+
+    #[test]
+    fn test_residual_stats() {
+        // Black, half red, and green, which red over black can't reproduce
+        let mut input = RgbaImage::from_pixel(3, 1, Rgba([0, 0, 0, 255]));
+        input.put_pixel(1, 0, Rgba([128, 0, 0, 255]));
+        input.put_pixel(2, 0, Rgba([0, 255, 0, 255]));
+
+        let unmixer = StrictUnmixer::new(&[[255, 0, 0]]);
+        let stats = ResidualStats::compute(&input, [0, 0, 0].into(), &unmixer).unwrap();
+        assert!((stats.max - 1.0).abs() < 1e-6, "{:?}", stats);
+        assert!((stats.mean - 1.0 / 3.0).abs() < 1e-6, "{:?}", stats);
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        let input = RgbaImage::new(2, 2);
+        let output = RgbaImage::new(3, 2);
+        assert!(matches!(
+            ReconstructionError::compute(&input, &output, [0, 0, 0]),
+            Err(Error::DimensionMismatch { .. })
+        ));
+    }
+}
@@ -1,10 +1,28 @@
-use crate::color::{Color, NormalizedColor};
+use crate::color::{Color, ColorDistance, NormalizedColor, normalize_color};
 use nalgebra::{DMatrix, DVector, Vector3};
 
 /// Small epsilon value for numerical stability in floating point comparisons
 const EPSILON: f64 = 1e-10;
 
-/// Default threshold for color closeness in non-strict mode (0.05 = 5% of max RGB distance)
+/// Residual below which a color counts as exactly reconstructed
+const RESIDUAL_TOLERANCE: f64 = 1e-6;
+
+/// Weight of the sum-to-one row in the constrained least squares system, large
+/// enough that the weights of the background and foreground colors sum to 1
+const SUM_ROW_WEIGHT: f64 = 100.0;
+
+/// Weight of the row penalizing the background weight in the constrained least
+/// squares system, small enough not to affect the residual but large enough to
+/// pick the most opaque of equally good solutions
+const OPACITY_ROW_WEIGHT: f64 = 1e-4;
+
+/// Gradient below which the nonnegative least squares solver stops
+const NNLS_TOLERANCE: f64 = 1e-12;
+
+/// Default threshold for color closeness in non-strict mode
+///
+/// 0.05 is 5% of the distance between black and pure red in RGB, or about 2.5
+/// just noticeable differences in OKLab.
 pub const DEFAULT_COLOR_CLOSENESS_THRESHOLD: f64 = 0.05;
 
 /// Result of color unmixing: weights for each foreground color and overall alpha
@@ -14,6 +32,9 @@ pub struct UnmixResult {
     pub weights: Vec<f64>,
     /// Overall alpha value (0.0 = fully transparent, 1.0 = fully opaque)
     pub alpha: f64,
+    /// Distance between the observed color and its reconstruction from the
+    /// weights, in normalized RGB (0.0 when the color is reproduced exactly)
+    pub residual: f64,
 }
 
 /// Unmix an observed color into foreground components
@@ -25,26 +46,28 @@ pub fn unmix_colors(
     foreground_colors: &[NormalizedColor],
     background: NormalizedColor,
 ) -> UnmixResult {
-    unmix_colors_internal(observed, foreground_colors, background, true)
+    unmix_colors_internal(
+        normalize_color(observed),
+        foreground_colors,
+        background,
+        true,
+    )
 }
 
 /// Internal unmix function with opacity optimization control
 pub(crate) fn unmix_colors_internal(
-    observed: Color,
+    observed: NormalizedColor,
     foreground_colors: &[NormalizedColor],
     background: NormalizedColor,
     optimize_opacity: bool,
 ) -> UnmixResult {
-    let observed = Vector3::new(
-        observed[0] as f64 / 255.0,
-        observed[1] as f64 / 255.0,
-        observed[2] as f64 / 255.0,
-    );
+    let observed = Vector3::from_row_slice(&observed);
 
     match foreground_colors.len() {
         0 => UnmixResult {
             weights: vec![],
             alpha: 0.0,
+            residual: (observed - Vector3::from_row_slice(&background)).norm(),
         },
         1 => unmix_single_color(observed, foreground_colors[0], background),
         _ => {
@@ -80,6 +103,7 @@ fn unmix_single_color(
     };
 
     UnmixResult {
+        residual: residual(observed, &[weight], &[foreground], background),
         weights: vec![weight],
         alpha: weight,
     }
@@ -130,13 +154,46 @@ fn unmix_multiple_colors_simple(
     };
 
     UnmixResult {
+        residual: residual(observed, &final_weights, foreground_colors, background),
         weights: final_weights,
         alpha,
     }
 }
 
-/// Unmix when there are multiple foreground colors using least squares
-/// Optimizes for maximum opacity while maintaining color accuracy.
+/// Unmix when there are multiple foreground colors, optimizing for maximum
+/// opacity while maintaining color accuracy
+///
+/// Finds the nonnegative weights, summing to 1.0 or less, that reconstruct the
+/// observed color as closely as possible, and among those the most opaque ones
+/// (see [`unmix_constrained_least_squares`]). The background itself is always
+/// transparent, even when the foreground colors can be mixed into it.
+///
+/// The fast approaches of [`unmix_multiple_colors_heuristic`] are tried first,
+/// and kept when they reproduce the color exactly at full opacity.
+fn unmix_multiple_colors_optimized(
+    observed: Vector3<f64>,
+    foreground_colors: &[NormalizedColor],
+    background: NormalizedColor,
+) -> UnmixResult {
+    let heuristic = unmix_multiple_colors_heuristic(observed, foreground_colors, background);
+    let is_background = (observed - Vector3::from_row_slice(&background)).norm() < EPSILON;
+    if heuristic.residual < RESIDUAL_TOLERANCE
+        && (heuristic.alpha >= 1.0 - EPSILON || is_background)
+    {
+        return heuristic;
+    }
+
+    let solved = unmix_constrained_least_squares(observed, foreground_colors, background);
+    if heuristic.residual <= solved.residual + RESIDUAL_TOLERANCE && heuristic.alpha > solved.alpha
+    {
+        heuristic
+    } else {
+        solved
+    }
+}
+
+/// Unmix when there are multiple foreground colors using least squares on
+/// all colors, single colors and pairs of colors
 ///
 /// This function tries multiple approaches to find the solution with maximum
 /// opacity that still accurately reconstructs the observed color:
@@ -145,8 +202,9 @@ fn unmix_multiple_colors_simple(
 /// 3. Pairs of colors (compromise between opacity and flexibility)
 ///
 /// All solutions are verified to ensure they reconstruct the original color
-/// within a small error threshold.
-fn unmix_multiple_colors_optimized(
+/// within a small error threshold. Mixes of three or more colors are only
+/// found by the first approach, and only when it needs no clamping.
+fn unmix_multiple_colors_heuristic(
     observed: Vector3<f64>,
     foreground_colors: &[NormalizedColor],
     background: NormalizedColor,
@@ -268,23 +326,155 @@ fn unmix_multiple_colors_optimized(
     }
 
     UnmixResult {
+        residual: residual(observed, &best_weights, foreground_colors, background),
         weights: best_weights,
         alpha: best_alpha,
     }
 }
 
-/// Calculate the Euclidean distance between two colors in RGB space
-fn color_distance(color1: Vector3<f64>, color2: Vector3<f64>) -> f64 {
-    (color1 - color2).norm()
+/// Unmix with any number of foreground colors by constrained least squares
+///
+/// The background and foreground colors are mixed with nonnegative weights
+/// summing to 1 (the background weight being 1 - alpha), which are found by
+/// nonnegative least squares on the color equations, with an extra row
+/// enforcing the sum and another one penalizing the background weight:
+///
+/// ```text
+/// | bg    fg_1 ... fg_n |          | observed |
+/// | δ     δ    ... δ    | * w  ≈   | δ        |
+/// | ε     0    ... 0    |          | 0        |
+/// ```
+///
+/// The result reconstructs the observed color as closely as the colors allow,
+/// and is the most opaque of the closest reconstructions.
+fn unmix_constrained_least_squares(
+    observed: Vector3<f64>,
+    foreground_colors: &[NormalizedColor],
+    background: NormalizedColor,
+) -> UnmixResult {
+    let n = foreground_colors.len();
+    let columns = std::iter::once(&background).chain(foreground_colors);
+    let mut matrix = DMatrix::zeros(5, n + 1);
+    for (j, color) in columns.enumerate() {
+        matrix[(0, j)] = color[0];
+        matrix[(1, j)] = color[1];
+        matrix[(2, j)] = color[2];
+        matrix[(3, j)] = SUM_ROW_WEIGHT;
+    }
+    matrix[(4, 0)] = OPACITY_ROW_WEIGHT;
+    let target =
+        DVector::from_column_slice(&[observed[0], observed[1], observed[2], SUM_ROW_WEIGHT, 0.0]);
+
+    let solution = solve_nonnegative_least_squares(&matrix, &target);
+    let total = solution.sum();
+    let weights: Vec<f64> = if total > EPSILON {
+        solution.iter().skip(1).map(|w| w / total).collect()
+    } else {
+        vec![0.0; n]
+    };
+    let alpha = weights.iter().sum::<f64>().min(1.0);
+
+    UnmixResult {
+        residual: residual(observed, &weights, foreground_colors, background),
+        weights,
+        alpha,
+    }
+}
+
+/// Solve `min |Ax - b|` subject to `x >= 0` with the Lawson-Hanson active
+/// set method
+fn solve_nonnegative_least_squares(a: &DMatrix<f64>, b: &DVector<f64>) -> DVector<f64> {
+    let n = a.ncols();
+    let mut x = DVector::zeros(n);
+    let mut passive = vec![false; n];
+    // Variables that only looked promising because of rounding errors
+    let mut skipped = vec![false; n];
+
+    for _ in 0..3 * n {
+        // Free the variable whose increase reduces the error the most
+        let gradient = a.tr_mul(&(b - a * &x));
+        let Some(j) = (0..n)
+            .filter(|&j| !passive[j] && !skipped[j] && gradient[j] > NNLS_TOLERANCE)
+            .max_by(|&i, &j| gradient[i].total_cmp(&gradient[j]))
+        else {
+            break;
+        };
+        passive[j] = true;
+
+        let mut z = solve_least_squares_on(a, b, &passive);
+        if z[j] <= 0.0 {
+            passive[j] = false;
+            skipped[j] = true;
+            continue;
+        }
+
+        loop {
+            // Step towards the unconstrained solution until a variable would
+            // become negative, and make it zero again
+            let step = (0..n)
+                .filter(|&i| passive[i] && z[i] <= 0.0)
+                .map(|i| x[i] / (x[i] - z[i]))
+                .fold(f64::INFINITY, f64::min);
+            if step.is_infinite() {
+                x = z;
+                break;
+            }
+
+            x += (&z - &x) * step;
+            for i in 0..n {
+                if passive[i] && x[i] <= NNLS_TOLERANCE {
+                    passive[i] = false;
+                    x[i] = 0.0;
+                }
+            }
+            z = solve_least_squares_on(a, b, &passive);
+        }
+    }
+
+    x
+}
+
+/// Solve `min |Ax - b|` using only the selected columns of `A`, with the other
+/// variables at 0
+fn solve_least_squares_on(a: &DMatrix<f64>, b: &DVector<f64>, selected: &[bool]) -> DVector<f64> {
+    let indices: Vec<usize> = (0..a.ncols()).filter(|&j| selected[j]).collect();
+    let mut x = DVector::zeros(a.ncols());
+    if let Ok(solution) = a.select_columns(&indices).svd(true, true).solve(b, EPSILON) {
+        for (&j, &value) in indices.iter().zip(solution.iter()) {
+            x[j] = value;
+        }
+    }
+    x
+}
+
+/// Distance between an observed color and its reconstruction from foreground
+/// weights over the background
+fn residual(
+    observed: Vector3<f64>,
+    weights: &[f64],
+    foreground_colors: &[NormalizedColor],
+    background: NormalizedColor,
+) -> f64 {
+    let bg = Vector3::from_row_slice(&background);
+    let reconstructed = weights
+        .iter()
+        .zip(foreground_colors)
+        .fold(bg, |sum, (&weight, fg)| {
+            sum + weight * (Vector3::from_row_slice(fg) - bg)
+        });
+    (reconstructed - observed).norm()
 }
 
 /// Check if an observed color is "close enough" to any foreground color when unmixed
 /// Returns true if the color can be primarily represented by one of the foreground colors
+///
+/// The threshold is in the units of `distance`.
 pub fn is_color_close_to_foreground(
     observed: Vector3<f64>,
     foreground_colors: &[NormalizedColor],
     background: NormalizedColor,
     threshold: f64,
+    distance: ColorDistance,
 ) -> bool {
     // Try unmixing with each individual foreground color
     for fg in foreground_colors {
@@ -303,7 +493,7 @@ pub fn is_color_close_to_foreground(
             let reconstructed = weight * fg_vec + (1.0 - weight) * bg_vec;
 
             // Check if the reconstruction is close to the observed color
-            if color_distance(reconstructed, observed) < threshold {
+            if distance.between(reconstructed.into(), observed.into()) < threshold {
                 return true;
             }
         }
@@ -342,6 +532,17 @@ pub fn compute_result_color(
     (result, unmix_result.alpha)
 }
 
+/// Deterministic pseudo-random colors with channels from 0.0 to 1.0, for tests
+#[cfg(test)]
+pub(crate) fn pseudo_random_colors(seed: u32) -> impl Iterator<Item = NormalizedColor> {
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        (state >> 8) as f64 / (1 << 24) as f64
+    };
+    std::iter::repeat_with(move || [next(), next(), next()])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -393,11 +594,79 @@ mod tests {
         assert!((result.alpha - 1.0).abs() < 0.1);
     }
 
+    #[test]
+    fn test_unmix_mix_of_three_colors() {
+        // 20% red, 30% green and 40% blue over black, with white also available
+        let foreground_colors = [
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 1.0, 1.0],
+        ];
+        let observed = Vector3::new(0.2, 0.3, 0.4);
+        let result = unmix_colors_internal(observed.into(), &foreground_colors, [0.0; 3], true);
+
+        // Using white would be less opaque: 0.2 white + 0.1 green + 0.2 blue
+        // reproduces the color at 50% alpha
+        for (weight, expected) in result.weights.iter().zip([0.2, 0.3, 0.4, 0.0]) {
+            assert!((weight - expected).abs() < 1e-6, "{:?}", result);
+        }
+        assert!((result.alpha - 0.9).abs() < 1e-6);
+        assert!(result.residual < RESIDUAL_TOLERANCE);
+
+        // Pairs and clamped least squares cannot reproduce it
+        let heuristic = unmix_multiple_colors_heuristic(observed, &foreground_colors, [0.0; 3]);
+        assert!(heuristic.residual > 0.01, "{:?}", heuristic);
+    }
+
+    #[test]
+    fn test_unmix_unreachable_color_residual() {
+        // Blue cannot be mixed from red and green: the closest is the background
+        let result = unmix_colors(
+            [0, 0, 128],
+            &[[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            [0.0, 0.0, 0.0],
+        );
+        assert!(result.alpha < 1e-6, "{:?}", result);
+        assert!((result.residual - 128.0 / 255.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_unmix_constraints_hold() {
+        let foreground_colors = [
+            [0.9, 0.1, 0.1],
+            [0.1, 0.8, 0.2],
+            [0.2, 0.2, 0.9],
+            [0.95, 0.85, 0.1],
+            [0.1, 0.1, 0.1],
+            [0.6, 0.3, 0.7],
+        ];
+        let background = [0.98, 0.97, 0.95];
+
+        for observed in pseudo_random_colors(12345).take(200) {
+            let observed = Vector3::from(observed);
+            let result =
+                unmix_colors_internal(observed.into(), &foreground_colors, background, true);
+
+            assert!(result.weights.iter().all(|&weight| weight >= 0.0));
+            let sum: f64 = result.weights.iter().sum();
+            assert!(sum <= 1.0 + 1e-9 && (sum - result.alpha).abs() < 1e-9);
+            let recomputed = residual(observed, &result.weights, &foreground_colors, background);
+            assert!((result.residual - recomputed).abs() < 1e-9);
+
+            // Never worse than the fast approaches
+            let heuristic =
+                unmix_multiple_colors_heuristic(observed, &foreground_colors, background);
+            assert!(result.residual <= heuristic.residual + RESIDUAL_TOLERANCE);
+        }
+    }
+
     #[test]
     fn test_compute_result_color_zero_alpha() {
         let unmix = UnmixResult {
             weights: vec![1.0],
             alpha: 0.0,
+            residual: 0.0,
         };
         let (color, alpha) = compute_result_color(&unmix, &[[1.0, 0.0, 0.0]]);
         assert_eq!(color, [0.0, 0.0, 0.0]);
@@ -409,6 +678,7 @@ mod tests {
         let unmix = UnmixResult {
             weights: vec![0.3, 0.7],
             alpha: 1.0,
+            residual: 0.0,
         };
         let (color, alpha) = compute_result_color(&unmix, &[[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
         assert!((color[0] - 0.3).abs() < EPSILON);
@@ -438,17 +708,6 @@ mod tests {
         assert!(result2.alpha < 0.01); // Should be nearly transparent
     }
 
-    #[test]
-    fn test_color_distance() {
-        let color1 = Vector3::new(0.0, 0.0, 0.0);
-        let color2 = Vector3::new(1.0, 0.0, 0.0);
-        assert!((color_distance(color1, color2) - 1.0).abs() < EPSILON);
-
-        let color3 = Vector3::new(0.0, 0.0, 0.0);
-        let color4 = Vector3::new(1.0, 1.0, 1.0);
-        assert!((color_distance(color3, color4) - 1.732).abs() < 0.01); // sqrt(3)
-    }
-
     #[test]
     fn test_is_color_close_to_foreground() {
         let red = [1.0, 0.0, 0.0];
@@ -456,10 +715,68 @@ mod tests {
         let observed = Vector3::new(0.5, 0.0, 0.0); // 50% red
 
         // Should be close to red when mixed with black background
-        assert!(is_color_close_to_foreground(observed, &[red], black, 0.1));
+        let rgb = ColorDistance::Rgb;
+        assert!(is_color_close_to_foreground(
+            observed,
+            &[red],
+            black,
+            0.1,
+            rgb
+        ));
 
         // Purple should not be close to red
         let purple = Vector3::new(0.5, 0.0, 0.5);
-        assert!(!is_color_close_to_foreground(purple, &[red], black, 0.1));
+        assert!(!is_color_close_to_foreground(
+            purple,
+            &[red],
+            black,
+            0.1,
+            rgb
+        ));
+    }
+
+    #[test]
+    fn test_is_color_close_to_foreground_oklab() {
+        let blue = [0.0, 0.0, 1.0];
+        let yellow = [1.0, 1.0, 0.0];
+        let black = [0.0, 0.0, 0.0];
+        let white = [1.0, 1.0, 1.0];
+
+        // The same RGB offset from a blue-over-black and a yellow-over-white mix
+        let near_blue = Vector3::new(0.04, 0.0, 0.2);
+        let near_yellow = Vector3::new(1.0 - 0.04, 1.0, 0.8);
+
+        let rgb = ColorDistance::Rgb;
+        assert!(is_color_close_to_foreground(
+            near_blue,
+            &[blue],
+            black,
+            0.05,
+            rgb
+        ));
+        assert!(is_color_close_to_foreground(
+            near_yellow,
+            &[yellow],
+            white,
+            0.05,
+            rgb
+        ));
+
+        // Perceptually, the offset is visible on the dark blue only
+        let oklab = ColorDistance::Oklab;
+        assert!(!is_color_close_to_foreground(
+            near_blue,
+            &[blue],
+            black,
+            0.02,
+            oklab
+        ));
+        assert!(is_color_close_to_foreground(
+            near_yellow,
+            &[yellow],
+            white,
+            0.02,
+            oklab
+        ));
     }
 }
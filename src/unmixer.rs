@@ -0,0 +1,635 @@
+use crate::color::{
+    BlendSpace, Color, ColorDistance, NormalizedColor, denormalize_color, normalize_color,
+};
+use crate::options::{Mode, ProcessOptions};
+use crate::unmix::{compute_result_color, is_color_close_to_foreground, unmix_colors_internal};
+use nalgebra::Vector3;
+
+/// Strategy that turns an observed color into a foreground color and alpha
+///
+/// The observed color is opaque (translucent input pixels are composited over
+/// the background first). Implementations return the RGBA pixel that
+/// reconstructs it when blended over `background`, which is the background
+/// color behind that pixel. They run on worker threads, which is why they must
+/// be `Sync`.
+pub trait PixelUnmixer: Sync {
+    /// Unmix an observed color into an RGBA pixel over a background color
+    fn unmix(&self, observed: Color, background: Color) -> [u8; 4];
+
+    /// Unmix an observed color into an RGBA pixel and the weight of each
+    /// foreground color in it
+    ///
+    /// The weights are in the order of the foreground colors the unmixer was
+    /// created with. An empty vector means the pixel is not made of any
+    /// foreground color, which is what the default implementation reports.
+    fn unmix_with_weights(&self, observed: Color, background: Color) -> ([u8; 4], Vec<f64>) {
+        (self.unmix(observed, background), Vec::new())
+    }
+
+    /// Unmix an observed color into an RGBA pixel without rounding to 8 bits
+    ///
+    /// Channels, including alpha, are from 0.0 to 1.0. This is used for images
+    /// with more than 8 bits per channel. The default implementation rounds the
+    /// colors to 8 bits and calls [`unmix`](Self::unmix).
+    fn unmix_normalized(&self, observed: NormalizedColor, background: NormalizedColor) -> [f64; 4] {
+        self.unmix(denormalize_color(observed), denormalize_color(background))
+            .map(|channel| channel as f64 / 255.0)
+    }
+
+    /// Color space in which the unmixer assumes the foreground was blended
+    ///
+    /// Translucent input pixels are composited over the background in this
+    /// space before they are unmixed. The default implementation uses sRGB.
+    fn assumed_blend_space(&self) -> BlendSpace {
+        BlendSpace::Srgb
+    }
+}
+
+/// Create the built-in unmixer for the mode of the given options
+pub fn unmixer_for_options(options: &ProcessOptions) -> Box<dyn PixelUnmixer> {
+    unmixer_with_max_foreground(options, 1.0)
+}
+
+/// Create the built-in unmixer for the mode of the given options, letting
+/// foreground channels reach `max_foreground` where any color can be used
+pub(crate) fn unmixer_with_max_foreground(
+    options: &ProcessOptions,
+    max_foreground: f64,
+) -> Box<dyn PixelUnmixer> {
+    match options.mode {
+        Mode::NonStrict => Box::new(
+            NonStrictUnmixer::default()
+                .blend_space(options.blend_space)
+                .max_foreground(max_foreground),
+        ),
+        Mode::NonStrictWithFg => Box::new(
+            NonStrictWithFgUnmixer::new(&options.foreground_colors, options.threshold)
+                .distance(options.distance)
+                .blend_space(options.blend_space)
+                .max_foreground(max_foreground),
+        ),
+        Mode::Strict => Box::new(
+            StrictUnmixer::new(&options.foreground_colors).blend_space(options.blend_space),
+        ),
+    }
+}
+
+/// Non-strict mode without foreground colors
+///
+/// In this mode, we find the optimal foreground color and alpha that produces
+/// the observed color when alpha-blended with the background.
+///
+/// The algorithm:
+/// 1. Bounds alpha per channel in closed form: a channel brighter than the
+///    background needs a foreground channel of at most `max_foreground`, and a
+///    darker one a foreground channel of at least 0
+/// 2. Uses the largest of these bounds as the minimum alpha, and derives the
+///    foreground color from it
+/// 3. Always produces perfect reconstruction of the original image
+#[derive(Debug, Clone, Copy)]
+pub struct NonStrictUnmixer {
+    blend_space: BlendSpace,
+    max_foreground: f64,
+}
+
+impl Default for NonStrictUnmixer {
+    fn default() -> Self {
+        Self {
+            blend_space: BlendSpace::default(),
+            max_foreground: 1.0,
+        }
+    }
+}
+
+impl NonStrictUnmixer {
+    /// Set the color space in which the foreground was blended
+    pub fn blend_space(mut self, blend_space: BlendSpace) -> Self {
+        self.blend_space = blend_space;
+        self
+    }
+
+    /// Set the largest value of a foreground channel, in the blend space
+    ///
+    /// The default of 1.0 keeps the foreground no brighter than white. Higher
+    /// values are for HDR images, where bright glows can then keep a low alpha.
+    pub fn max_foreground(mut self, max_foreground: f64) -> Self {
+        self.max_foreground = max_foreground;
+        self
+    }
+}
+
+impl PixelUnmixer for NonStrictUnmixer {
+    fn unmix(&self, observed: Color, background: Color) -> [u8; 4] {
+        to_rgba8(self.unmix_normalized(normalize_color(observed), normalize_color(background)))
+    }
+
+    fn unmix_normalized(&self, observed: NormalizedColor, background: NormalizedColor) -> [f64; 4] {
+        let obs_norm = self.blend_space.decode(observed);
+        let bg_norm = self.blend_space.decode(background);
+
+        // If the observed color is exactly the background, it's fully transparent
+        if is_background(obs_norm, bg_norm) {
+            return [0.0; 4];
+        }
+
+        unmix_with_minimum_alpha(obs_norm, bg_norm, self.blend_space, self.max_foreground)
+    }
+
+    fn assumed_blend_space(&self) -> BlendSpace {
+        self.blend_space
+    }
+}
+
+/// Non-strict mode with foreground colors
+///
+/// This mode combines two strategies:
+/// 1. For pixels "close enough" to specified foreground colors (within threshold):
+///    - Uses the standard unmixing algorithm optimized for high opacity
+///    - Restricts to the specified foreground colors
+/// 2. For pixels NOT close to any foreground color:
+///    - Allows ANY color to be used
+///    - Finds the minimum alpha that produces a valid foreground color
+///    - Ensures perfect reconstruction
+///
+/// This allows the tool to preserve colors like glows and gradients that aren't
+/// close to the specified foreground colors, while still optimizing for the
+/// specified colors when appropriate.
+#[derive(Debug, Clone)]
+pub struct NonStrictWithFgUnmixer {
+    foreground_colors: Vec<NormalizedColor>,
+    threshold: f64,
+    distance: ColorDistance,
+    blend_space: BlendSpace,
+    max_foreground: f64,
+}
+
+impl NonStrictWithFgUnmixer {
+    /// Create an unmixer for the given foreground colors and color closeness threshold
+    pub fn new(foreground_colors: &[Color], threshold: f64) -> Self {
+        Self {
+            foreground_colors: foreground_colors
+                .iter()
+                .map(|&c| normalize_color(c))
+                .collect(),
+            threshold,
+            distance: ColorDistance::default(),
+            blend_space: BlendSpace::default(),
+            max_foreground: 1.0,
+        }
+    }
+
+    /// Set how the closeness of a pixel to the foreground colors is measured
+    pub fn distance(mut self, distance: ColorDistance) -> Self {
+        self.distance = distance;
+        self
+    }
+
+    /// Set the color space in which the foreground was blended
+    pub fn blend_space(mut self, blend_space: BlendSpace) -> Self {
+        self.blend_space = blend_space;
+        self
+    }
+
+    /// Set the largest value of a foreground channel, in the blend space, for
+    /// pixels that are not close to any foreground color
+    pub fn max_foreground(mut self, max_foreground: f64) -> Self {
+        self.max_foreground = max_foreground;
+        self
+    }
+
+    /// Distance between an observed color and the closest mix of the
+    /// foreground colors over a background color
+    ///
+    /// This is the residual of the solver, in the blend space with channels
+    /// from 0.0 to 1.0: 0.0 when the observed color is reproduced exactly, and
+    /// larger the further it is from anything the foreground colors can make.
+    pub fn residual(&self, observed: NormalizedColor, background: NormalizedColor) -> f64 {
+        let space = self.blend_space;
+        unmix_colors_internal(
+            space.decode(observed),
+            &space.decode_all(&self.foreground_colors),
+            space.decode(background),
+            true,
+        )
+        .residual
+    }
+
+    /// Unmix a normalized color into a normalized RGBA pixel and the weight of
+    /// each foreground color in it
+    fn unmix_precise(
+        &self,
+        obs_norm: NormalizedColor,
+        bg_norm: NormalizedColor,
+    ) -> ([f64; 4], Vec<f64>) {
+        // If the observed color is exactly the background, it's fully transparent
+        if is_background(obs_norm, bg_norm) {
+            return ([0.0; 4], Vec::new());
+        }
+
+        // Check if this pixel is close to any foreground color, in sRGB so
+        // that the threshold means the same in every blend space
+        let obs_vec = Vector3::new(obs_norm[0], obs_norm[1], obs_norm[2]);
+        let close_to_fg = is_color_close_to_foreground(
+            obs_vec,
+            &self.foreground_colors,
+            bg_norm,
+            self.threshold,
+            self.distance,
+        );
+
+        let space = self.blend_space;
+        let obs_norm = space.decode(obs_norm);
+        let bg_norm = space.decode(bg_norm);
+
+        if close_to_fg {
+            // Use the standard unmixing algorithm optimized for high opacity
+            let foreground_colors = space.decode_all(&self.foreground_colors);
+            let unmix_result = unmix_colors_internal(obs_norm, &foreground_colors, bg_norm, true);
+            let (result_color, alpha) = compute_result_color(&unmix_result, &foreground_colors);
+            (
+                with_alpha(space.encode(result_color), alpha),
+                unmix_result.weights,
+            )
+        } else {
+            // Not close to any foreground color - find ANY color that works with minimal alpha
+            (
+                unmix_with_minimum_alpha(obs_norm, bg_norm, space, self.max_foreground),
+                Vec::new(),
+            )
+        }
+    }
+}
+
+impl PixelUnmixer for NonStrictWithFgUnmixer {
+    fn unmix(&self, observed: Color, background: Color) -> [u8; 4] {
+        self.unmix_with_weights(observed, background).0
+    }
+
+    fn unmix_with_weights(&self, observed: Color, background: Color) -> ([u8; 4], Vec<f64>) {
+        let (pixel, weights) =
+            self.unmix_precise(normalize_color(observed), normalize_color(background));
+        (to_rgba8(pixel), weights)
+    }
+
+    fn unmix_normalized(&self, observed: NormalizedColor, background: NormalizedColor) -> [f64; 4] {
+        self.unmix_precise(observed, background).0
+    }
+
+    fn assumed_blend_space(&self) -> BlendSpace {
+        self.blend_space
+    }
+}
+
+/// Strict mode: unmixing is restricted to the foreground colors
+///
+/// Optimizes for maximum opacity while maintaining color accuracy. Colors that
+/// cannot be reached by the foreground colors are not perfectly reconstructed.
+#[derive(Debug, Clone)]
+pub struct StrictUnmixer {
+    foreground_colors: Vec<NormalizedColor>,
+    blend_space: BlendSpace,
+}
+
+impl StrictUnmixer {
+    /// Create an unmixer for the given foreground colors
+    pub fn new(foreground_colors: &[Color]) -> Self {
+        Self {
+            foreground_colors: foreground_colors
+                .iter()
+                .map(|&c| normalize_color(c))
+                .collect(),
+            blend_space: BlendSpace::default(),
+        }
+    }
+
+    /// Set the color space in which the foreground was blended
+    pub fn blend_space(mut self, blend_space: BlendSpace) -> Self {
+        self.blend_space = blend_space;
+        self
+    }
+
+    /// Distance between an observed color and the closest mix of the
+    /// foreground colors over a background color
+    ///
+    /// This is the residual of the solver, in the blend space with channels
+    /// from 0.0 to 1.0: 0.0 when the observed color is reproduced exactly, and
+    /// larger the further it is from anything the foreground colors can make.
+    pub fn residual(&self, observed: NormalizedColor, background: NormalizedColor) -> f64 {
+        let space = self.blend_space;
+        unmix_colors_internal(
+            space.decode(observed),
+            &space.decode_all(&self.foreground_colors),
+            space.decode(background),
+            true,
+        )
+        .residual
+    }
+
+    /// Unmix a normalized color into a normalized RGBA pixel and the weight of
+    /// each foreground color in it
+    fn unmix_precise(
+        &self,
+        observed: NormalizedColor,
+        background: NormalizedColor,
+    ) -> ([f64; 4], Vec<f64>) {
+        let space = self.blend_space;
+        let foreground_colors = space.decode_all(&self.foreground_colors);
+        let unmix_result = unmix_colors_internal(
+            space.decode(observed),
+            &foreground_colors,
+            space.decode(background),
+            true,
+        );
+        let (result_color, alpha) = compute_result_color(&unmix_result, &foreground_colors);
+        (
+            with_alpha(space.encode(result_color), alpha),
+            unmix_result.weights,
+        )
+    }
+}
+
+impl PixelUnmixer for StrictUnmixer {
+    fn unmix(&self, observed: Color, background: Color) -> [u8; 4] {
+        self.unmix_with_weights(observed, background).0
+    }
+
+    fn unmix_with_weights(&self, observed: Color, background: Color) -> ([u8; 4], Vec<f64>) {
+        let (pixel, weights) =
+            self.unmix_precise(normalize_color(observed), normalize_color(background));
+        (to_rgba8(pixel), weights)
+    }
+
+    fn unmix_normalized(&self, observed: NormalizedColor, background: NormalizedColor) -> [f64; 4] {
+        self.unmix_precise(observed, background).0
+    }
+
+    fn assumed_blend_space(&self) -> BlendSpace {
+        self.blend_space
+    }
+}
+
+/// Check if an observed color is exactly the background color
+fn is_background(obs_norm: NormalizedColor, background: NormalizedColor) -> bool {
+    (obs_norm[0] - background[0]).abs() < 1e-6
+        && (obs_norm[1] - background[1]).abs() < 1e-6
+        && (obs_norm[2] - background[2]).abs() < 1e-6
+}
+
+/// Combine a normalized color and alpha into a normalized RGBA pixel
+fn with_alpha(color: NormalizedColor, alpha: f64) -> [f64; 4] {
+    [color[0], color[1], color[2], alpha]
+}
+
+/// Round a normalized RGBA pixel to 8 bits per channel
+fn to_rgba8(pixel: [f64; 4]) -> [u8; 4] {
+    pixel.map(|channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+/// Unmix a color using ANY foreground color with the minimum alpha
+///
+/// The observed and background colors are in `blend_space`, and foreground
+/// channels range from 0 to `max_foreground`.
+fn unmix_with_minimum_alpha(
+    obs_norm: NormalizedColor,
+    background: NormalizedColor,
+    blend_space: BlendSpace,
+    max_foreground: f64,
+) -> [f64; 4] {
+    let (best_fg, best_alpha) = find_minimum_alpha_for_color(obs_norm, background, max_foreground);
+    with_alpha(blend_space.encode(best_fg), best_alpha)
+}
+
+/// Find the minimum alpha value that produces a valid foreground color
+///
+/// Given an observed color and background, this function finds the minimum alpha
+/// value (between 0 and 1) such that there exists a valid foreground color
+/// (all RGB components in [0, max_foreground]) that satisfies:
+/// observed = alpha * foreground + (1 - alpha) * background
+///
+/// Each channel bounds alpha on its own. A channel brighter than the background
+/// needs at least (observed - background) / (max_foreground - background),
+/// with the foreground channel at max_foreground, and a darker one needs at
+/// least (background - observed) / background, with the foreground channel at
+/// 0. The minimum alpha is the largest of these bounds, and the foreground
+/// color follows from it.
+///
+/// A channel brighter than a background that is itself at max_foreground or
+/// above (which only happens with HDR backgrounds) cannot be reached, so the
+/// pixel is opaque with the observed color.
+///
+/// Returns (foreground_color, alpha)
+fn find_minimum_alpha_for_color(
+    obs_norm: NormalizedColor,
+    background: NormalizedColor,
+    max_foreground: f64,
+) -> (NormalizedColor, f64) {
+    let alpha = (0..3)
+        .map(|i| {
+            let (observed, background) = (obs_norm[i], background[i]);
+            if observed > background {
+                if background < max_foreground {
+                    (observed - background) / (max_foreground - background)
+                } else {
+                    1.0
+                }
+            } else if observed < background {
+                (background - observed) / background
+            } else {
+                0.0
+            }
+        })
+        .fold(0.0, f64::max);
+
+    if alpha <= 0.0 {
+        return ([0.0; 3], 0.0);
+    }
+    if alpha >= 1.0 {
+        return (obs_norm, 1.0);
+    }
+
+    // observed = alpha * foreground + (1 - alpha) * background, with rounding
+    // errors clamped away from the channels at the bounds
+    let foreground = [0, 1, 2].map(|i| {
+        (background[i] + (obs_norm[i] - background[i]) / alpha).clamp(0.0, max_foreground)
+    });
+    (foreground, alpha)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unmix::pseudo_random_colors;
+
+    #[test]
+    fn test_non_strict_unmixer_background_is_transparent() {
+        let unmixer = NonStrictUnmixer::default();
+        assert_eq!(
+            unmixer.unmix([255, 255, 255], [255, 255, 255]),
+            [0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_non_strict_unmixer_minimum_alpha() {
+        // 50% red over black: the minimum alpha is reached with pure red
+        let unmixer = NonStrictUnmixer::default();
+        let [r, g, b, a] = unmixer.unmix([128, 0, 0], [0, 0, 0]);
+        assert_eq!([r, g, b], [255, 0, 0]);
+        assert_eq!(a, 128);
+    }
+
+    #[test]
+    fn test_non_strict_with_fg_unmixer_uses_foreground() {
+        let unmixer = NonStrictWithFgUnmixer::new(&[[255, 0, 0]], 0.05);
+        assert_eq!(
+            unmixer.unmix([255, 0, 0], [255, 255, 255]),
+            [255, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn test_strict_unmixer_restricts_to_foreground() {
+        // Green cannot be made from red over black, so it becomes transparent
+        let unmixer = StrictUnmixer::new(&[[255, 0, 0]]);
+        assert_eq!(unmixer.unmix([255, 0, 0], [0, 0, 0]), [255, 0, 0, 255]);
+        assert_eq!(unmixer.unmix([0, 255, 0], [0, 0, 0])[3], 0);
+    }
+
+    #[test]
+    fn test_strict_unmixer_weights() {
+        let unmixer = StrictUnmixer::new(&[[255, 0, 0], [0, 255, 0]]);
+        let (pixel, weights) = unmixer.unmix_with_weights([0, 255, 0], [0, 0, 0]);
+        assert_eq!(pixel, [0, 255, 0, 255]);
+        assert_eq!(weights.len(), 2);
+        assert!(weights[0].abs() < 1e-6);
+        assert!((weights[1] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_non_strict_unmixer_has_no_weights() {
+        let unmixer = NonStrictUnmixer::default();
+        let (_, weights) = unmixer.unmix_with_weights([255, 0, 0], [0, 0, 0]);
+        assert!(weights.is_empty());
+    }
+
+    #[test]
+    fn test_unmixer_for_options() {
+        let options = ProcessOptions::new([0, 0, 0])
+            .mode(Mode::Strict)
+            .foreground_colors(vec![[255, 0, 0]]);
+        let unmixer = unmixer_for_options(&options);
+        assert_eq!(unmixer.unmix([255, 0, 0], [0, 0, 0]), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_unmixers_in_linear_light() {
+        // 50% white over black, blended in linear light, is 188 in sRGB
+        let unmixer = NonStrictUnmixer::default().blend_space(BlendSpace::Linear);
+        assert_eq!(
+            unmixer.unmix([188, 188, 188], [0, 0, 0]),
+            [255, 255, 255, 128]
+        );
+        assert_eq!(unmixer.assumed_blend_space(), BlendSpace::Linear);
+
+        // Blended in sRGB, the same pixel would be about 74% white
+        let unmixer = NonStrictUnmixer::default();
+        assert_eq!(
+            unmixer.unmix([188, 188, 188], [0, 0, 0]),
+            [255, 255, 255, 188]
+        );
+
+        let unmixer = StrictUnmixer::new(&[[255, 0, 0]]).blend_space(BlendSpace::Linear);
+        assert_eq!(unmixer.unmix([188, 0, 0], [0, 0, 0]), [255, 0, 0, 128]);
+
+        let unmixer =
+            NonStrictWithFgUnmixer::new(&[[255, 0, 0]], 0.05).blend_space(BlendSpace::Linear);
+        assert_eq!(unmixer.unmix([188, 0, 0], [0, 0, 0]), [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn test_minimum_alpha_reconstructs_exactly() {
+        // Pseudo-random colors, including HDR backgrounds and foregrounds
+        // brighter than white
+        let mut colors = pseudo_random_colors(2024);
+
+        for max_foreground in [1.0, 4.0] {
+            for _ in 0..10_000 {
+                let mut next_color = || colors.next().unwrap().map(|c| c * max_foreground);
+                let observed = next_color();
+                let background = next_color();
+                let (foreground, alpha) =
+                    find_minimum_alpha_for_color(observed, background, max_foreground);
+
+                // The foreground is valid and reconstructs the observed color
+                assert!((0.0..=1.0).contains(&alpha));
+                assert!(
+                    foreground
+                        .iter()
+                        .all(|c| (0.0..=max_foreground).contains(c))
+                );
+                for i in 0..3 {
+                    let reconstructed = alpha * foreground[i] + (1.0 - alpha) * background[i];
+                    assert!(
+                        (reconstructed - observed[i]).abs() < 1e-9,
+                        "{:?} over {:?}: {:?} at {}",
+                        observed,
+                        background,
+                        foreground,
+                        alpha
+                    );
+                }
+
+                // A slightly lower alpha needs a foreground out of range
+                let lower = alpha * (1.0 - 1e-6);
+                let out_of_range = (0..3).any(|i| {
+                    let channel = background[i] + (observed[i] - background[i]) / lower;
+                    !(0.0..=max_foreground).contains(&channel)
+                });
+                assert!(alpha == 0.0 || out_of_range);
+            }
+        }
+    }
+
+    #[test]
+    fn test_minimum_alpha_edge_cases() {
+        // The background itself is transparent
+        assert_eq!(
+            find_minimum_alpha_for_color([0.5; 3], [0.5; 3], 1.0),
+            ([0.0; 3], 0.0)
+        );
+
+        // Black over white, and white over black, are opaque
+        assert_eq!(
+            find_minimum_alpha_for_color([0.0; 3], [1.0; 3], 1.0),
+            ([0.0; 3], 1.0)
+        );
+        assert_eq!(
+            find_minimum_alpha_for_color([1.0; 3], [0.0; 3], 1.0),
+            ([1.0; 3], 1.0)
+        );
+
+        // Brighter than an HDR background above the foreground limit
+        assert_eq!(
+            find_minimum_alpha_for_color([3.0, 2.0, 2.0], [2.0; 3], 1.5),
+            ([3.0, 2.0, 2.0], 1.0)
+        );
+
+        // Channels needing different alphas: the largest one wins
+        let (foreground, alpha) = find_minimum_alpha_for_color([0.6, 0.25, 0.5], [0.5; 3], 1.0);
+        assert!((alpha - 0.5).abs() < 1e-12);
+        assert!((foreground[1] - 0.0).abs() < 1e-12);
+        assert!((foreground[0] - 0.7).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_unmixer_uses_given_background() {
+        // The same observed color is the background on one pixel and a
+        // foreground color on another
+        let unmixer = NonStrictUnmixer::default();
+        assert_eq!(
+            unmixer.unmix([128, 128, 128], [128, 128, 128]),
+            [0, 0, 0, 0]
+        );
+        assert_ne!(unmixer.unmix([128, 128, 128], [0, 0, 0])[3], 0);
+    }
+}
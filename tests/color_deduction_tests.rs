@@ -1,8 +1,9 @@
 mod common;
 
 use assert_cmd::Command;
-use bgone::color::ForegroundColorSpec;
+use bgone::color::{BlendSpace, ColorDistance, ForegroundColorSpec};
 use bgone::deduce::deduce_unknown_colors;
+use bgone::palette::{Swatch, read_palette};
 use bgone::unmix::{compute_result_color, unmix_colors};
 use common::{
     calculate_psnr, calculate_similarity_percentage, ensure_output_dir, overlay_on_background,
@@ -827,7 +828,15 @@ fn test_pure_color_deduction() {
     let specs = vec![ForegroundColorSpec::Unknown];
     let background = [0, 0, 0];
 
-    let result = deduce_unknown_colors(&img, &specs, background, 0.05).unwrap();
+    let result = deduce_unknown_colors(
+        &img,
+        &specs,
+        background,
+        0.05,
+        ColorDistance::Rgb,
+        BlendSpace::Srgb,
+    )
+    .unwrap();
 
     assert_eq!(result.len(), 1);
     let deduced_color = result[0];
@@ -882,3 +891,136 @@ fn test_opacity_optimization() {
     // Should achieve good opacity
     assert!(result.alpha > 0.6);
 }
+
+#[test]
+fn test_deduce_linear_blended_color() {
+    // An orange glow blended in linear light over dark blue, up to 90% opacity
+    let foreground = [255, 90, 40];
+    let background = [20, 20, 60];
+    let space = BlendSpace::Linear;
+    let normalize = |color: [u8; 3]| color.map(|channel| channel as f64 / 255.0);
+    let (fg, bg) = (
+        space.decode(normalize(foreground)),
+        space.decode(normalize(background)),
+    );
+    let img = image::RgbImage::from_fn(100, 10, |x, _y| {
+        let alpha = (x / 10) as f64 / 10.0;
+        let blended = space.encode([0, 1, 2].map(|i| fg[i] * alpha + bg[i] * (1.0 - alpha)));
+        image::Rgb(blended.map(|channel| (channel * 255.0).round() as u8))
+    });
+    let img = image::DynamicImage::ImageRgb8(img);
+
+    let result = deduce_unknown_colors(
+        &img,
+        &[ForegroundColorSpec::Unknown],
+        background,
+        0.01,
+        ColorDistance::Rgb,
+        space,
+    )
+    .unwrap();
+
+    let deduced = result[0];
+    assert!(
+        (0..3).all(|i| deduced[i].abs_diff(foreground[i]) <= 1),
+        "Deduced {:?}, expected {:?}",
+        deduced,
+        foreground
+    );
+}
+
+#[test]
+fn test_palette_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let palette_path = temp_dir.path().join("brand.gpl");
+    let saved_path = temp_dir.path().join("saved.json");
+    let first_output = temp_dir.path().join("first.png");
+    let second_output = temp_dir.path().join("second.png");
+    std::fs::write(
+        &palette_path,
+        "GIMP Palette\nName: Brand\n#\n255   0   0\tRed\n  0 255   0\tGreen\n",
+    )
+    .unwrap();
+
+    // Red from the palette, and a deduced color
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "tests/inputs/rectangles.png",
+        first_output.to_str().unwrap(),
+        "--fg",
+        "auto",
+        "--palette",
+        palette_path.to_str().unwrap(),
+        "--swatch",
+        "red",
+        "--bg",
+        "0000ff",
+        "--save-palette",
+        saved_path.to_str().unwrap(),
+    ]);
+    cmd.assert().success();
+
+    let saved = read_palette(&saved_path).unwrap();
+    assert_eq!(saved.len(), 2);
+    assert!(saved[0].name.starts_with('#'), "{:?}", saved);
+    assert_eq!(saved[1], Swatch::new("Red", [255, 0, 0]));
+
+    // The saved palette gives the same result without deduction
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "tests/inputs/rectangles.png",
+        second_output.to_str().unwrap(),
+        "--palette",
+        saved_path.to_str().unwrap(),
+        "--bg",
+        "0000ff",
+    ]);
+    cmd.assert().success();
+    assert_eq!(
+        image::open(&first_output).unwrap(),
+        image::open(&second_output).unwrap()
+    );
+
+    // Unknown swatch names are reported
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "tests/inputs/rectangles.png",
+        second_output.to_str().unwrap(),
+        "--palette",
+        palette_path.to_str().unwrap(),
+        "--swatch",
+        "Blue",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("No swatch named 'Blue'"));
+}
+
+#[test]
+fn test_oklab_distance() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("output.png");
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "tests/inputs/rectangles.png",
+        output_path.to_str().unwrap(),
+        "--fg",
+        "ff0000",
+        "auto",
+        "--bg",
+        "0000ff",
+        "--distance",
+        "oklab",
+        "--threshold",
+        "0.02",
+        "--report",
+        "json",
+    ]);
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let report: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(report["distance"], "oklab");
+    assert_eq!(report["threshold"], 0.02);
+    assert_eq!(report["foreground_colors"][0]["color"], "#ff0000");
+}
@@ -1,12 +1,18 @@
 mod common;
 
 use assert_cmd::Command;
-use bgone::process_image;
+use bgone::background::normalize_image;
+use bgone::metadata::{PixelDensity, PngChunk};
+use bgone::progress::NoopObserver;
+use bgone::{
+    Error, ImageMetadata, ProcessOptions, load_image_with_metadata, process_image,
+    remove_background, save_image_with_metadata,
+};
 use common::{
     calculate_psnr, calculate_similarity_percentage, ensure_output_dir, overlay_on_background,
     save_test_images,
 };
-use image::{DynamicImage, Rgba, RgbaImage};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba, RgbaImage};
 use tempfile::TempDir;
 
 #[test]
@@ -121,7 +127,7 @@ fn test_non_strict_optimal_alpha() {
     input.save(&input_path).unwrap();
 
     let background = [255u8, 255, 255];
-    process_image(&input_path, &output_path, vec![], background, false, None).unwrap();
+    process_image(&input_path, &output_path, &ProcessOptions::new(background)).unwrap();
 
     let result = image::open(&output_path).unwrap();
     if let DynamicImage::ImageRgba8(result_img) = &result {
@@ -176,7 +182,7 @@ fn test_non_strict_edge_cases() {
     input.save(&input_path).unwrap();
 
     let background = [255u8, 255, 255];
-    process_image(&input_path, &output_path, vec![], background, false, None).unwrap();
+    process_image(&input_path, &output_path, &ProcessOptions::new(background)).unwrap();
 
     let result = image::open(&output_path).unwrap();
     if let DynamicImage::ImageRgba8(result_img) = &result {
@@ -207,7 +213,7 @@ fn test_non_strict_edge_cases() {
     let output_path = temp_dir.path().join("output3.png");
     input.save(&input_path).unwrap();
 
-    process_image(&input_path, &output_path, vec![], background, false, None).unwrap();
+    process_image(&input_path, &output_path, &ProcessOptions::new(background)).unwrap();
 
     let result = image::open(&output_path).unwrap();
     if let DynamicImage::ImageRgba8(result_img) = &result {
@@ -255,7 +261,7 @@ fn test_non_strict_alpha_minimization() {
         let output_path = temp_dir.path().join(format!("output_{}.png", i));
         input.save(&input_path).unwrap();
 
-        process_image(&input_path, &output_path, vec![], *background, false, None).unwrap();
+        process_image(&input_path, &output_path, &ProcessOptions::new(*background)).unwrap();
 
         let result = image::open(&output_path).unwrap();
         if let DynamicImage::ImageRgba8(result_img) = &result {
@@ -286,3 +292,644 @@ fn test_non_strict_alpha_minimization() {
         }
     }
 }
+
+#[test]
+fn test_remove_background_in_memory() {
+    // Red square on white, processed without touching the filesystem
+    let img = RgbaImage::from_fn(10, 10, |x, y| {
+        if (3..7).contains(&x) && (3..7).contains(&y) {
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([255, 255, 255, 255])
+        }
+    });
+
+    let result = remove_background(&img, &ProcessOptions::new([255, 255, 255])).unwrap();
+
+    assert_eq!(result.dimensions(), (10, 10));
+    assert_eq!(
+        result.get_pixel(0, 0)[3],
+        0,
+        "Background should be transparent"
+    );
+    assert_eq!(result.get_pixel(5, 5), &Rgba([255, 0, 0, 255]));
+}
+
+#[test]
+fn test_process_image_missing_input() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("missing.png");
+    let output_path = temp_dir.path().join("output.png");
+
+    let result = process_image(
+        &input_path,
+        &output_path,
+        &ProcessOptions::new([255, 255, 255]),
+    );
+    assert!(matches!(result, Err(Error::ImageDecode { .. })));
+}
+
+#[test]
+fn test_error_map() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("output.png");
+    let error_map_path = temp_dir.path().join("error.png");
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "tests/inputs/circle-gradients.png",
+        output_path.to_str().unwrap(),
+        "--bg",
+        "ffffff",
+        "--error-map",
+        error_map_path.to_str().unwrap(),
+        "--error-map-style",
+        "gray",
+    ]);
+
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("Reconstruction error: max"));
+
+    let original = image::open("tests/inputs/circle-gradients.png").unwrap();
+    let error_map = image::open(&error_map_path).unwrap();
+    assert_eq!(error_map.dimensions(), original.dimensions());
+}
+
+#[test]
+fn test_verify_passes() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("output.png");
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "tests/inputs/circle-gradients.png",
+        output_path.to_str().unwrap(),
+        "--bg",
+        "ffffff",
+        "--verify",
+    ]);
+
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("Verified reconstruction"));
+}
+
+#[test]
+fn test_json_report() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("output.png");
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "tests/inputs/rectangles.png",
+        output_path.to_str().unwrap(),
+        "--fg",
+        "ff0000",
+        "auto",
+        "--bg",
+        "0000ff",
+        "--report",
+        "json",
+    ]);
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let report: serde_json::Value =
+        serde_json::from_slice(&output).expect("stdout should only contain the JSON report");
+
+    assert_eq!(report["width"], 150);
+    assert_eq!(report["height"], 100);
+    assert_eq!(report["bit_depth"], 8);
+    assert_eq!(report["background"]["color"], "#0000ff");
+    assert_eq!(report["background"]["source"], "given");
+    assert_eq!(report["mode"], "non-strict-with-fg");
+    assert_eq!(report["distance"], "rgb");
+    assert_eq!(report["foreground_colors"][0]["deduced"], false);
+    assert_eq!(report["foreground_colors"][1]["deduced"], true);
+    assert!(report["timings_ms"]["processing"].is_number());
+    assert!(report["alpha"]["transparent"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn test_pair_json_report() {
+    let temp_dir = TempDir::new().unwrap();
+    let on_black_path = temp_dir.path().join("on_black.png");
+    let on_white_path = temp_dir.path().join("on_white.png");
+    let output_path = temp_dir.path().join("output.png");
+
+    // A half-transparent red square on a clear background, rendered over black and white
+    let render = |square: [u8; 3], background: u8| {
+        RgbaImage::from_fn(40, 30, |x, y| {
+            if (10..30).contains(&x) && (10..20).contains(&y) {
+                Rgba([square[0], square[1], square[2], 255])
+            } else {
+                Rgba([background, background, background, 255])
+            }
+        })
+    };
+    render([128, 0, 0], 0).save(&on_black_path).unwrap();
+    render([255, 127, 127], 255).save(&on_white_path).unwrap();
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "--pair",
+        on_black_path.to_str().unwrap(),
+        on_white_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--report",
+        "json",
+    ]);
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let report: serde_json::Value =
+        serde_json::from_slice(&output).expect("stdout should only contain the JSON report");
+
+    assert_eq!(report["mode"], "pair");
+    assert_eq!(report["width"], 40);
+    assert_eq!(report["height"], 30);
+    assert_eq!(report["second_input"], on_white_path.to_str().unwrap());
+    assert_eq!(report["background"]["source"], "pair");
+    assert_eq!(report["background"]["colors"][0], "#000000");
+    assert_eq!(report["background"]["colors"][1], "#ffffff");
+    assert_eq!(report["foreground_colors"].as_array().unwrap().len(), 0);
+    assert!(report["timings_ms"]["processing"].is_number());
+    assert_eq!(report["alpha"]["transparent"], 40 * 30 - 20 * 10);
+    assert_eq!(report["alpha"]["translucent"], 20 * 10);
+    assert_eq!(report["disagreement"]["over_tolerance"], 0);
+}
+
+#[test]
+fn test_uncertain_background() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("split.png");
+    let output_path = temp_dir.path().join("output.png");
+
+    // Half white, half black: the border has no dominant color
+    RgbaImage::from_fn(100, 100, |x, _y| {
+        if x < 50 {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    })
+    .save(&input_path)
+    .unwrap();
+
+    // The warning is a status line, on stderr when the report is on stdout
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([input_path.to_str().unwrap(), output_path.to_str().unwrap()]);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("runner-up"));
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--report",
+        "json",
+    ]);
+    cmd.assert()
+        .success()
+        .stderr(predicates::str::contains("runner-up"));
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--require-confident-bg",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("use --bg to specify it"));
+}
+
+#[test]
+fn test_background_sample_region() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("title_bar.png");
+    let output_path = temp_dir.path().join("output.png");
+
+    // A dark title bar across the top of a short, wide white window
+    RgbaImage::from_fn(200, 40, |_x, y| {
+        if y < 20 {
+            Rgba([40, 40, 40, 255])
+        } else {
+            Rgba([255, 255, 255, 255])
+        }
+    })
+    .save(&input_path)
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--bg-sample",
+        "bottom",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("#ffffff"));
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--bg-rect",
+        "0,0,200,10",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("#282828"));
+
+    // A region outside the image has nothing to sample
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--bg-rect",
+        "500,500,10,10",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("sampling region"));
+}
+
+#[test]
+fn test_pick_colors_from_pixels() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("square.png");
+    let output_path = temp_dir.path().join("output.png");
+
+    // Red square on a light gray background
+    RgbaImage::from_fn(100, 100, |x, y| {
+        if (30..70).contains(&x) && (30..70).contains(&y) {
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([230, 230, 230, 255])
+        }
+    })
+    .save(&input_path)
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--bg-at",
+        "5,5",
+        "--fg",
+        "@50,50",
+        "--pick-size",
+        "5",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "Picked background color #e6e6e6 at 5,5",
+        ))
+        .stdout(predicates::str::contains(
+            "Picked foreground color #ff0000 at 50,50",
+        ));
+
+    let output = image::open(&output_path).unwrap().to_rgba8();
+    assert_eq!(output.get_pixel(0, 0)[3], 0);
+    assert_eq!(output.get_pixel(50, 50), &Rgba([255, 0, 0, 255]));
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--fg-at",
+        "100,0",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("outside the 100x100 image"));
+}
+
+#[test]
+fn test_css_color_syntax() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("square.png");
+    let output_path = temp_dir.path().join("output.png");
+
+    RgbaImage::from_fn(20, 20, |x, _y| {
+        if x < 10 {
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([255, 255, 255, 255])
+        }
+    })
+    .save(&input_path)
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--bg",
+        "white",
+        "--fg",
+        "rgb(255 0 0)",
+        "hsl(240 100% 50%)",
+    ]);
+    cmd.assert().success();
+
+    let output = image::open(&output_path).unwrap().to_rgba8();
+    assert_eq!(output.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+    assert_eq!(output.get_pixel(15, 0)[3], 0);
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--bg",
+        "rgb(300 0 0)",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("out of range"));
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--fg",
+        "#ff000080",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("opaque"));
+}
+
+#[test]
+fn test_gradient_background() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("gradient.png");
+    let output_path = temp_dir.path().join("output.png");
+
+    // Red square on a horizontal white-to-gray gradient
+    let gradient = |x: u32| (255.0 - x as f64 * 100.0 / 99.0).round() as u8;
+    RgbaImage::from_fn(100, 60, |x, y| {
+        if (40..60).contains(&x) && (20..40).contains(&y) {
+            Rgba([255, 0, 0, 255])
+        } else {
+            let value = gradient(x);
+            Rgba([value, value, value, 255])
+        }
+    })
+    .save(&input_path)
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--bg-gradient",
+        "linear",
+        "--verify",
+    ]);
+    cmd.assert().success().stdout(predicates::str::contains(
+        "Fitted linear background gradient",
+    ));
+
+    let output = image::open(&output_path).unwrap().to_rgba8();
+    for x in [0, 30, 70, 99] {
+        assert!(
+            output.get_pixel(x, 5)[3] == 0,
+            "Background at x={} should be transparent: {:?}",
+            x,
+            output.get_pixel(x, 5)
+        );
+    }
+    assert_eq!(output.get_pixel(50, 30), &Rgba([255, 0, 0, 255]));
+}
+
+#[test]
+fn test_gradient_background_16_bit() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("gradient.png");
+    let output_path = temp_dir.path().join("output.png");
+
+    // Red square on a gradient whose levels fall between 8-bit levels
+    let gradient = |x: u32| 0x8000 + x as u16 * 37;
+    let input: ImageBuffer<Rgba<u16>, Vec<u16>> = ImageBuffer::from_fn(100, 60, |x, y| {
+        if (40..60).contains(&x) && (20..40).contains(&y) {
+            Rgba([65535, 0, 0, 65535])
+        } else {
+            let value = gradient(x);
+            Rgba([value, value, value, 65535])
+        }
+    });
+    input.save(&input_path).unwrap();
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--bg-gradient",
+        "linear",
+    ]);
+    cmd.assert().success();
+
+    let output = image::open(&output_path).unwrap().to_rgba16();
+    for x in [0, 30, 70, 99] {
+        assert_eq!(
+            output.get_pixel(x, 5)[3],
+            0,
+            "Background at x={} should be transparent: {:?}",
+            x,
+            output.get_pixel(x, 5)
+        );
+    }
+    assert_eq!(output.get_pixel(50, 30), &Rgba([65535, 0, 0, 65535]));
+}
+
+/// Textured background that no color model can fit
+fn textured_background(width: u32, height: u32) -> image::RgbImage {
+    image::RgbImage::from_fn(width, height, |x, y| {
+        let value = ((x * 37 + y * 91) % 64) as u8 + 150;
+        image::Rgb([value, value / 2 + 100, 255 - value])
+    })
+}
+
+#[test]
+fn test_clean_plate_background() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("shot.png");
+    let plate_path = temp_dir.path().join("plate.png");
+    let output_path = temp_dir.path().join("output.png");
+
+    // A blue square on a textured background, and the background alone
+    let plate = textured_background(40, 40);
+    let shot = RgbaImage::from_fn(40, 40, |x, y| {
+        if (10..30).contains(&x) && (10..30).contains(&y) {
+            Rgba([0, 0, 255, 255])
+        } else {
+            let [r, g, b] = plate.get_pixel(x, y).0;
+            Rgba([r, g, b, 255])
+        }
+    });
+    shot.save(&input_path).unwrap();
+    plate.save(&plate_path).unwrap();
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--bg-image",
+        plate_path.to_str().unwrap(),
+        "--verify",
+    ]);
+    cmd.assert().success();
+
+    let output = image::open(&output_path).unwrap().to_rgba8();
+    assert_eq!(output.get_pixel(0, 0)[3], 0);
+    assert_eq!(output.get_pixel(39, 5)[3], 0);
+    assert_eq!(output.get_pixel(20, 20), &Rgba([0, 0, 255, 255]));
+
+    // The same through the library
+    let options = ProcessOptions::new([0, 0, 0]).background_image(normalize_image(&plate));
+    let result = remove_background(&shot, &options).unwrap();
+    assert_eq!(result, output);
+}
+
+#[test]
+fn test_clean_plate_size_mismatch() {
+    let temp_dir = TempDir::new().unwrap();
+    let plate_path = temp_dir.path().join("plate.png");
+    let output_path = temp_dir.path().join("output.png");
+    textured_background(10, 10).save(&plate_path).unwrap();
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "tests/inputs/square.png",
+        output_path.to_str().unwrap(),
+        "--bg-image",
+        plate_path.to_str().unwrap(),
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("same size"));
+
+    // The library reports the mismatch too
+    let img = image::open("tests/inputs/square.png").unwrap().to_rgba8();
+    let options = ProcessOptions::new([0, 0, 0])
+        .background_image(normalize_image(&textured_background(10, 10)));
+    assert!(matches!(
+        remove_background(&img, &options),
+        Err(Error::DimensionMismatch { .. })
+    ));
+}
+
+/// Minimal little-endian EXIF data with only an orientation tag
+fn exif_with_orientation(orientation: u16) -> Vec<u8> {
+    let mut exif = b"II*\0".to_vec();
+    exif.extend_from_slice(&8u32.to_le_bytes());
+    exif.extend_from_slice(&1u16.to_le_bytes());
+    // Tag 0x0112 (orientation), type SHORT, count 1, value
+    exif.extend_from_slice(&0x0112u16.to_le_bytes());
+    exif.extend_from_slice(&3u16.to_le_bytes());
+    exif.extend_from_slice(&1u32.to_le_bytes());
+    exif.extend_from_slice(&orientation.to_le_bytes());
+    exif.extend_from_slice(&[0, 0]);
+    // No next IFD
+    exif.extend_from_slice(&0u32.to_le_bytes());
+    exif
+}
+
+#[test]
+fn test_metadata_preserved() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("input.png");
+    let output_path = temp_dir.path().join("output.png");
+    let stripped_path = temp_dir.path().join("stripped.png");
+
+    // A red square on white, tagged as 300 DPI with a profile and a title
+    let metadata = ImageMetadata {
+        icc_profile: Some(b"not really an ICC profile".to_vec()),
+        density: Some(PixelDensity {
+            x: 11811,
+            y: 11811,
+            per_meter: true,
+        }),
+        png_chunks: vec![
+            PngChunk {
+                kind: *b"tEXt",
+                data: b"Title\0Logo".to_vec(),
+            },
+            PngChunk {
+                kind: *b"gAMA",
+                data: 45455u32.to_be_bytes().to_vec(),
+            },
+        ],
+        ..ImageMetadata::default()
+    };
+    let image = RgbaImage::from_fn(8, 8, |x, y| {
+        if (2..6).contains(&x) && (2..6).contains(&y) {
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([255, 255, 255, 255])
+        }
+    });
+    save_image_with_metadata(&image, &input_path, &metadata, &NoopObserver).unwrap();
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([input_path.to_str().unwrap(), output_path.to_str().unwrap()]);
+    cmd.assert().success();
+
+    let (output, output_metadata) = load_image_with_metadata(&output_path, &NoopObserver).unwrap();
+    assert_eq!(output.get_pixel(0, 0)[3], 0);
+    assert_eq!(output_metadata, metadata);
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        stripped_path.to_str().unwrap(),
+        "--strip-metadata",
+    ]);
+    cmd.assert().success();
+
+    let (_, stripped_metadata) = load_image_with_metadata(&stripped_path, &NoopObserver).unwrap();
+    assert!(stripped_metadata.is_empty(), "{:?}", stripped_metadata);
+}
+
+#[test]
+fn test_exif_orientation_applied() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("input.png");
+    let output_path = temp_dir.path().join("output.png");
+
+    // A portrait photo stored sideways, to be rotated 90° clockwise for display
+    let metadata = ImageMetadata {
+        exif: Some(exif_with_orientation(6)),
+        ..ImageMetadata::default()
+    };
+    let image = RgbaImage::from_fn(4, 2, |x, _| {
+        if x < 2 {
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([255, 255, 255, 255])
+        }
+    });
+    save_image_with_metadata(&image, &input_path, &metadata, &NoopObserver).unwrap();
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        "--bg",
+        "fff",
+    ]);
+    cmd.assert().success();
+
+    // The output is upright, with red on top, and its orientation no longer
+    // rotates it
+    let (output, output_metadata) = load_image_with_metadata(&output_path, &NoopObserver).unwrap();
+    assert_eq!(output.dimensions(), (2, 4));
+    assert_eq!(output.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+    assert_eq!(output.get_pixel(0, 3)[3], 0);
+    assert_eq!(output_metadata.exif, Some(exif_with_orientation(1)));
+}
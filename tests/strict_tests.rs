@@ -252,3 +252,121 @@ fn test_strict_mode_requires_fg() {
         .failure()
         .stderr(predicates::str::contains("strict mode"));
 }
+
+#[test]
+fn test_layers_export() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("output.png");
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "tests/inputs/rectangles.png",
+        output_path.to_str().unwrap(),
+        "--strict",
+        "--fg",
+        "#ff0000",
+        "#00ff00",
+        "--bg",
+        "#0000ff",
+        "--layers",
+    ]);
+
+    cmd.assert().success();
+
+    let red_layer = image::open(temp_dir.path().join("output-layer-1-ff0000.png"))
+        .unwrap()
+        .to_rgba8();
+    let green_layer = image::open(temp_dir.path().join("output-layer-2-00ff00.png"))
+        .unwrap()
+        .to_rgba8();
+
+    // Red rectangle only appears on the red layer
+    assert_eq!(red_layer.get_pixel(30, 30).0, [255, 0, 0, 255]);
+    assert_eq!(green_layer.get_pixel(30, 30)[3], 0);
+
+    // Green rectangle only appears on the green layer
+    assert_eq!(green_layer.get_pixel(70, 30).0, [0, 255, 0, 255]);
+    assert_eq!(red_layer.get_pixel(70, 30)[3], 0);
+
+    // 75% red over blue gives a 75% opaque red layer
+    let alpha = red_layer.get_pixel(30, 60)[3];
+    assert!(
+        (alpha as i32 - 191).abs() <= 1,
+        "Alpha {} is not ~75%",
+        alpha
+    );
+
+    // Background is transparent on every layer
+    assert_eq!(red_layer.get_pixel(0, 0)[3], 0);
+    assert_eq!(green_layer.get_pixel(0, 0)[3], 0);
+}
+
+#[test]
+fn test_layers_require_fg() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("output.png");
+
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "tests/inputs/square.png",
+        output_path.to_str().unwrap(),
+        "--layers",
+    ]);
+
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("foreground color"));
+}
+
+#[test]
+fn test_verify_fails_on_lossy_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("output.png");
+
+    // Gradients can't be reproduced with pure red only
+    let mut cmd = Command::cargo_bin("bgone").unwrap();
+    cmd.args([
+        "tests/inputs/circle-gradients.png",
+        output_path.to_str().unwrap(),
+        "--strict",
+        "--fg",
+        "ff0000",
+        "--bg",
+        "ffffff",
+        "--verify",
+    ]);
+
+    cmd.assert().failure().stderr(predicates::str::contains(
+        "Reconstruction exceeds tolerance",
+    ));
+}
+
+#[test]
+fn test_report_solver_residual() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("output.png");
+
+    let report = |fg: &str| -> serde_json::Value {
+        let mut cmd = Command::cargo_bin("bgone").unwrap();
+        cmd.args([
+            "tests/inputs/square.png",
+            output_path.to_str().unwrap(),
+            "--strict",
+            "--fg",
+            fg,
+            "--report",
+            "json",
+        ]);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        serde_json::from_slice(&output).unwrap()
+    };
+
+    // The square is reproduced exactly with its own color...
+    let exact = report("ff0000");
+    assert!(exact["solver_residual"]["max"].as_f64().unwrap() < 1e-6);
+
+    // ...but not with another one
+    let lossy = report("0000ff");
+    assert!(lossy["solver_residual"]["max"].as_f64().unwrap() > 0.1);
+    assert!(lossy["solver_residual"]["mean"].as_f64().unwrap() > 0.0);
+}
@@ -671,3 +671,362 @@ fn test_fire_on_colored_strict_mixed() {
         similarity
     );
 }
+
+// Difference matting tests
+
+#[test]
+fn test_fire_pair_difference_matting() {
+    ensure_output_dir();
+    let temp_dir = TempDir::new().unwrap();
+
+    let original_fire = load_fire_image();
+    let fire_on_black = create_composited_image(&original_fire, [0, 0, 0]);
+    let fire_on_white = create_composited_image(&original_fire, [255, 255, 255]);
+
+    let on_black_path = temp_dir.path().join("fire_on_black.png");
+    let on_white_path = temp_dir.path().join("fire_on_white.png");
+    fire_on_black.save(&on_black_path).unwrap();
+    fire_on_white.save(&on_white_path).unwrap();
+
+    let output_path = temp_dir.path().join("output.png");
+    Command::cargo_bin("bgone")
+        .unwrap()
+        .args([
+            "--pair",
+            on_black_path.to_str().unwrap(),
+            on_white_path.to_str().unwrap(),
+            output_path.to_str().unwrap(),
+            "--verify",
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Inputs disagree on 0 of"));
+
+    let processed = image::open(&output_path).unwrap();
+    let (similarity, psnr) = compare_with_original(&processed, &original_fire);
+
+    let reconstructed = overlay_on_background(&processed, [0, 0, 0]);
+    save_test_images("translucent_recovery", "pair", &processed, &reconstructed);
+
+    println!(
+        "Fire pair (difference matting) - Similarity: {:.2}%, PSNR: {:.2} dB",
+        similarity, psnr
+    );
+
+    // Both backgrounds pin down alpha, so recovery is near exact
+    assert!(
+        similarity > 99.0,
+        "Similarity {:.2}% is too low",
+        similarity
+    );
+}
+
+#[test]
+fn test_pair_reports_disagreeing_inputs() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // The second image is not the same foreground: the fire is flipped
+    let original_fire = load_fire_image();
+    let fire_on_black = create_composited_image(&original_fire, [0, 0, 0]);
+    let flipped_on_white = create_composited_image(&original_fire.fliph(), [255, 255, 255]);
+
+    let on_black_path = temp_dir.path().join("fire_on_black.png");
+    let on_white_path = temp_dir.path().join("flipped_on_white.png");
+    fire_on_black.save(&on_black_path).unwrap();
+    flipped_on_white.save(&on_white_path).unwrap();
+
+    let output_path = temp_dir.path().join("output.png");
+    Command::cargo_bin("bgone")
+        .unwrap()
+        .args([
+            "--pair",
+            on_black_path.to_str().unwrap(),
+            on_white_path.to_str().unwrap(),
+            output_path.to_str().unwrap(),
+            "--verify",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Reconstruction exceeds tolerance",
+        ));
+}
+
+#[test]
+fn test_linear_light_glow() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // A white glow with an alpha ramp, blended over black in linear light the
+    // way a 3D renderer would: the observed sRGB value is encode(alpha)
+    let encode = |linear: f64| {
+        if linear <= 0.0031308 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        }
+    };
+    let glow = ImageBuffer::from_fn(256, 4, |x, _y| {
+        let value = (encode(x as f64 / 255.0) * 255.0).round() as u8;
+        Rgba([value, value, value, 255])
+    });
+    let input_path = temp_dir.path().join("glow.png");
+    glow.save(&input_path).unwrap();
+
+    let output_path = temp_dir.path().join("output.png");
+    Command::cargo_bin("bgone")
+        .unwrap()
+        .args([
+            input_path.to_str().unwrap(),
+            output_path.to_str().unwrap(),
+            "--bg",
+            "000",
+            "--blend-space",
+            "linear",
+            "--verify",
+        ])
+        .assert()
+        .success();
+
+    // Alpha comes out as the ramp the glow was rendered with
+    let output = image::open(&output_path).unwrap().to_rgba8();
+    for x in 1..256 {
+        let pixel = output.get_pixel(x, 0);
+        assert!(
+            pixel[3].abs_diff(x as u8) <= 1,
+            "Alpha at {} is {}",
+            x,
+            pixel[3]
+        );
+        assert_eq!([pixel[0], pixel[1], pixel[2]], [255, 255, 255]);
+    }
+}
+
+#[test]
+fn test_16_bit_glow() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // A white glow over black whose 16-bit levels are finer than 8-bit ones
+    let glow: ImageBuffer<Rgba<u16>, Vec<u16>> = ImageBuffer::from_fn(1024, 2, |x, _y| {
+        let value = (x * 4) as u16;
+        Rgba([value, value, value, 65535])
+    });
+    let input_path = temp_dir.path().join("glow16.png");
+    glow.save(&input_path).unwrap();
+
+    let output_path = temp_dir.path().join("output.png");
+    let output = Command::cargo_bin("bgone")
+        .unwrap()
+        .args([
+            input_path.to_str().unwrap(),
+            output_path.to_str().unwrap(),
+            "--bg",
+            "000",
+            "--report",
+            "json",
+            "--verify",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["bit_depth"], 16);
+
+    // The output is 16-bit and alpha follows every level of the glow
+    let output = match image::open(&output_path).unwrap() {
+        DynamicImage::ImageRgba16(output) => output,
+        other => panic!("Expected a 16-bit RGBA output, got {:?}", other.color()),
+    };
+    for x in 1..1024 {
+        let pixel = output.get_pixel(x, 0);
+        assert!(
+            pixel[3].abs_diff((x * 4) as u16) <= 1,
+            "Alpha at {} is {}",
+            x,
+            pixel[3]
+        );
+        assert_eq!([pixel[0], pixel[1], pixel[2]], [65535, 65535, 65535]);
+    }
+}
+
+#[test]
+fn test_16_bit_background_between_8_bit_levels() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // A red square on a background that is not a multiple of 257, so it has
+    // no exact 8-bit level
+    let background = 0x1234u16;
+    let image: ImageBuffer<Rgba<u16>, Vec<u16>> = ImageBuffer::from_fn(40, 40, |x, y| {
+        if (10..30).contains(&x) && (10..30).contains(&y) {
+            Rgba([65535, 0, 0, 65535])
+        } else {
+            Rgba([background, background, background, 65535])
+        }
+    });
+    let input_path = temp_dir.path().join("square16.png");
+    image.save(&input_path).unwrap();
+
+    // Detected and given backgrounds are both used at 16 bits
+    for bg_args in [&[][..], &["--bg", "rgb(7.110704% 7.110704% 7.110704%)"][..]] {
+        let output_path = temp_dir.path().join("output.png");
+        Command::cargo_bin("bgone")
+            .unwrap()
+            .arg(input_path.to_str().unwrap())
+            .arg(output_path.to_str().unwrap())
+            .args(bg_args)
+            .assert()
+            .success();
+
+        let output = match image::open(&output_path).unwrap() {
+            DynamicImage::ImageRgba16(output) => output,
+            other => panic!("Expected a 16-bit RGBA output, got {:?}", other.color()),
+        };
+        assert_eq!(output.get_pixel(0, 0)[3], 0, "with {:?}", bg_args);
+        assert_eq!(output.get_pixel(39, 20)[3], 0, "with {:?}", bg_args);
+        assert_eq!(output.get_pixel(20, 20)[3], 65535, "with {:?}", bg_args);
+    }
+}
+
+#[test]
+fn test_hdr_exr_glow() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // A glow 3x brighter than white over a gray background
+    // brighter than white, in linear light
+    let background = 1.5f32;
+    let glow: ImageBuffer<Rgba<f32>, Vec<f32>> = ImageBuffer::from_fn(64, 2, |x, _y| {
+        let alpha = x as f32 / 63.0;
+        let foreground = [3.0, 3.0, 3.0];
+        let [r, g, b] = foreground.map(|c| c * alpha + background * (1.0 - alpha));
+        Rgba([r, g, b, 1.0])
+    });
+    let input_path = temp_dir.path().join("glow.exr");
+    glow.save(&input_path).unwrap();
+
+    let output_path = temp_dir.path().join("output.exr");
+    let output = Command::cargo_bin("bgone")
+        .unwrap()
+        .args([
+            input_path.to_str().unwrap(),
+            output_path.to_str().unwrap(),
+            "--bg-linear",
+            "1.5,1.5,1.5",
+            "--max-fg",
+            "3",
+            "--report",
+            "json",
+            "--verify",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["bit_depth"], 32);
+    assert_eq!(report["background"]["source"], "linear");
+    assert_eq!(report["blend_space"], "linear");
+
+    // Values above 1.0 survive and the glow keeps its alpha ramp
+    let output = image::open(&output_path).unwrap().to_rgba32f();
+    assert_eq!(output.get_pixel(0, 0)[3], 0.0);
+    for x in 1..64 {
+        let pixel = output.get_pixel(x, 0);
+        let alpha = x as f32 / 63.0;
+        assert!(
+            (pixel[3] - alpha).abs() < 1e-3,
+            "Pixel {} is {:?}",
+            x,
+            pixel
+        );
+        assert!((pixel[0] - 3.0).abs() < 1e-2, "Pixel {} is {:?}", x, pixel);
+    }
+}
+
+#[test]
+fn test_hdr_options_require_float_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("output.png");
+
+    Command::cargo_bin("bgone")
+        .unwrap()
+        .args([
+            "tests/inputs/square.png",
+            output_path.to_str().unwrap(),
+            "--bg-linear",
+            "1,1,1",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("floating-point input"));
+}
+
+#[test]
+fn test_hdr_exr_rejects_srgb_blend_space() {
+    let temp_dir = TempDir::new().unwrap();
+    let image: ImageBuffer<Rgba<f32>, Vec<f32>> =
+        ImageBuffer::from_pixel(4, 4, Rgba([0.5, 0.5, 0.5, 1.0]));
+    let input_path = temp_dir.path().join("gray.exr");
+    image.save(&input_path).unwrap();
+    let output_path = temp_dir.path().join("output.exr");
+
+    Command::cargo_bin("bgone")
+        .unwrap()
+        .args([
+            input_path.to_str().unwrap(),
+            output_path.to_str().unwrap(),
+            "--blend-space",
+            "srgb",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("--blend-space srgb"));
+}
+
+#[test]
+fn test_hdr_exr_to_png() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // A red square on a background that is sRGB #808080, stored in linear
+    // light (0.2159), which an unencoded 8-bit conversion would turn into 55
+    let background = 0.21586f32;
+    let image: ImageBuffer<Rgba<f32>, Vec<f32>> = ImageBuffer::from_fn(40, 40, |x, y| {
+        if (10..30).contains(&x) && (10..30).contains(&y) {
+            Rgba([1.0, 0.0, 0.0, 1.0])
+        } else {
+            Rgba([background, background, background, 1.0])
+        }
+    });
+    let input_path = temp_dir.path().join("square.exr");
+    image.save(&input_path).unwrap();
+
+    let check_output = |output_path: &std::path::Path| {
+        let output = image::open(output_path).unwrap().to_rgba8();
+        assert_eq!(output.get_pixel(0, 0)[3], 0);
+        assert_eq!(output.get_pixel(39, 20)[3], 0);
+        assert_eq!(*output.get_pixel(20, 20), Rgba([255, 0, 0, 255]));
+    };
+
+    // Given and detected backgrounds are both in sRGB
+    for bg_args in [&[][..], &["--bg", "808080"][..]] {
+        let output_path = temp_dir.path().join("output.png");
+        Command::cargo_bin("bgone")
+            .unwrap()
+            .arg(input_path.to_str().unwrap())
+            .arg(output_path.to_str().unwrap())
+            .args(bg_args)
+            .assert()
+            .success();
+        check_output(&output_path);
+    }
+
+    let output_path = temp_dir.path().join("library.png");
+    bgone::process_image(
+        &input_path,
+        &output_path,
+        &bgone::ProcessOptions::new([128, 128, 128]),
+    )
+    .unwrap();
+    check_output(&output_path);
+}